@@ -0,0 +1,75 @@
+// Times the interpreter on a few loop-heavy programs, with variable accesses
+// resolved to slots and with every access looked up by name.
+// Both ways scope names lexically; the programs only use globals and their
+// own locals, so they would also run the same under the old dynamic scoping.
+// Run with `cargo bench`.
+
+use std::{rc::Rc, time::{Duration, Instant}};
+
+use plz::{sink::Buffer, Ast, Interpreter, Parser, Resolver, Scanner, Slots, StmtId};
+
+const PROGRAMS: &[(&str, &str)] = &[
+	("nested loops", "
+		var i, j, sum;
+		begin
+			sum := 0; i := 0;
+			while i < 300 do begin
+				j := 0;
+				while j < 300 do begin sum := (sum + i * j) % 1000; j := j + 1 end;
+				i := i + 1
+			end
+		end."),
+	("outer variables from a procedure", "
+		var n, a, b, t;
+		procedure step;
+			var tmp;
+			begin tmp := (a + b) % 10007; a := b; b := tmp end;
+		begin
+			n := 0; a := 0; b := 1;
+			while n < 50000 do begin call step; n := n + 1 end
+		end."),
+	("recursion", "
+		var n, calls, round;
+		procedure down;
+		begin
+			calls := calls + 1;
+			if n > 0 then begin n := n - 1; call down; n := n + 1 end
+		end;
+		begin
+			round := 0; calls := 0;
+			while round < 50 do begin n := 500; call down; round := round + 1 end
+		end."),
+];
+
+fn parse(source: &str) -> (Ast, Vec<StmtId>) {
+	let tokens = Scanner::new(source.to_string(), "<bench>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let statements = parser.parse();
+	(parser.into_ast(), statements)
+}
+
+// best of a few runs
+fn time(ast: &Rc<Ast>, slots: &Rc<Slots>, statements: &[StmtId]) -> Duration {
+	(0..5).map(|_| {
+		let mut interpreter = Interpreter::new();
+		interpreter.set_output(Box::new(Buffer::new()));
+		let start = Instant::now();
+		interpreter.interpret(ast, slots, statements).expect("benchmark programs run to completion");
+		start.elapsed()
+	}).min().unwrap()
+}
+
+fn main() {
+	println!("{:<36} {:>12} {:>12} {:>8}", "program", "by name", "resolved", "speedup");
+	for (name, source) in PROGRAMS {
+		let (ast, statements) = parse(source);
+		let mut resolver = Resolver::new(&ast);
+		resolver.resolve(&statements, true);
+		let slots = Rc::new(resolver.into_slots());
+		let ast = Rc::new(ast);
+
+		let (slow, fast) = (time(&ast, &Rc::default(), &statements), time(&ast, &slots, &statements));
+		println!("{name:<36} {:>10.2}ms {:>10.2}ms {:>7.2}x",
+			slow.as_secs_f64() * 1000.0, fast.as_secs_f64() * 1000.0, slow.as_secs_f64() / fast.as_secs_f64());
+	}
+}
@@ -0,0 +1,118 @@
+use std::{marker::PhantomData, ops::{Index, IndexMut}};
+
+use crate::{expressions::Expr, parser::Stmt, token::Token};
+
+// Handles to the nodes of an `Ast`, small enough to copy around freely
+// and to key tables of whatever later passes work out about each node
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StmtId(u32);
+
+pub trait NodeId: Copy {
+	fn index(self) -> usize;
+}
+impl NodeId for ExprId {
+	fn index(self) -> usize { self.0 as usize }
+}
+impl NodeId for StmtId {
+	fn index(self) -> usize { self.0 as usize }
+}
+
+// Arena holding every node the parser builds, for all the modules of a program.
+// Nodes refer to their children by id, and are never removed, only replaced.
+#[derive(Default)]
+pub struct Ast {
+	exprs: Vec<Expr>,
+	stmts: Vec<Stmt>,
+}
+impl Ast {
+	pub fn new() -> Self { Self::default() }
+	pub fn add_expr(&mut self, expr: Expr) -> ExprId {
+		self.exprs.push(expr);
+		ExprId(self.exprs.len() as u32 - 1)
+	}
+	pub fn add_stmt(&mut self, stmt: Stmt) -> StmtId {
+		self.stmts.push(stmt);
+		StmtId(self.stmts.len() as u32 - 1)
+	}
+	pub fn expr_count(&self) -> usize { self.exprs.len() }
+	pub fn stmt_count(&self) -> usize { self.stmts.len() }
+
+	// the token an expression is reported at
+	pub fn expr_token(&self, id: ExprId) -> &Token {
+		match &self[id] {
+			Expr::Literal(t) | Expr::Variable(t) | Expr::Get(t, _) | Expr::Call(t, ..) => t,
+			Expr::Unary(op, _) | Expr::Binary(_, op, _) => op,
+			Expr::Grouping(e) => self.expr_token(*e),
+		}
+	}
+	// the token a statement is reported at; None for statements with nothing to point at
+	pub fn stmt_token(&self, id: StmtId) -> Option<&Token> {
+		match &self[id] {
+			Stmt::Proc(t, _) | Stmt::PrintVar(t) | Stmt::Assign(t, _) | Stmt::Set(t, _, _)
+			| Stmt::Call(t, _) | Stmt::Import(t, _) => Some(t),
+			Stmt::Print(e) | Stmt::Expression(e) | Stmt::If(e, _) | Stmt::While(e, _) | Stmt::Case(e, _) => Some(self.expr_token(*e)),
+			Stmt::Write(args, _, _) => args.first().map(|a| self.expr_token(*a)),
+			Stmt::Const(c) => c.first().map(|(t, _)| t),
+			Stmt::Type(t) => t.first().map(|(t, _)| t),
+			Stmt::Var(v) => v.first().map(|(t, _)| t),
+			Stmt::Scope(s) => s.first().and_then(|s| self.stmt_token(*s)),
+		}
+	}
+
+	// an expression as an s-expression
+	pub fn show(&self, id: ExprId) -> String {
+		use Expr::*;
+		match &self[id] {
+			Literal(l) => l.text().to_string(),
+			Grouping(a) => format!("(group {})", self.show(*a)),
+			Unary(o, a) => format!("({} {})", o.lexeme, self.show(*a)),
+			Binary(a, o, b) => format!("({} {} {})", o.lexeme, self.show(*a), self.show(*b)),
+			Variable(name) => format!("`{}`", name.lexeme),
+			Get(name, field) => format!("`{}.{}`", name.lexeme, field.lexeme),
+			Call(name, _, args) => {
+				let mut out = format!("(call `{}`", name.lexeme);
+				for a in args { out.push_str(&format!(" {}", self.show(*a))); }
+				out + ")"
+			},
+		}
+	}
+}
+impl Index<ExprId> for Ast {
+	type Output = Expr;
+	fn index(&self, id: ExprId) -> &Expr { &self.exprs[id.index()] }
+}
+impl Index<StmtId> for Ast {
+	type Output = Stmt;
+	fn index(&self, id: StmtId) -> &Stmt { &self.stmts[id.index()] }
+}
+// passes rewriting the tree replace nodes in place, keeping their ids
+impl IndexMut<ExprId> for Ast {
+	fn index_mut(&mut self, id: ExprId) -> &mut Expr { &mut self.exprs[id.index()] }
+}
+impl IndexMut<StmtId> for Ast {
+	fn index_mut(&mut self, id: StmtId) -> &mut Stmt { &mut self.stmts[id.index()] }
+}
+
+// Side table attaching a value to some of the nodes of an `Ast`
+pub struct NodeMap<K, V> {
+	values: Vec<Option<V>>,
+	key: PhantomData<K>,
+}
+impl<K, V> Default for NodeMap<K, V> {
+	fn default() -> Self { NodeMap { values: Vec::new(), key: PhantomData } }
+}
+impl<K: NodeId, V> NodeMap<K, V> {
+	pub fn new() -> Self { Self::default() }
+	pub fn insert(&mut self, id: K, value: V) {
+		let i = id.index();
+		if i >= self.values.len() {
+			self.values.resize_with(i + 1, || None);
+		}
+		self.values[i] = Some(value);
+	}
+	pub fn get(&self, id: K) -> Option<&V> {
+		self.values.get(id.index())?.as_ref()
+	}
+}
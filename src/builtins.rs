@@ -0,0 +1,112 @@
+use crate::token::TokenType;
+
+// Integer semantics of the language, kept apart from the interpreter
+// so that anything else evaluating expressions agrees with it.
+
+fn overflow() -> String { "Integer overflow".to_string() }
+
+pub fn unary(op: TokenType, v: i32) -> Result<i32, String> {
+	use TokenType::*;
+	match op {
+		PLUS => Ok(v),
+		MINUS => v.checked_neg().ok_or_else(overflow),
+		TILDE => Ok(!v),
+		_ => Err(format!("Invalid unary operator: {op:?}")),
+	}
+}
+
+pub fn binary(op: TokenType, a: i32, b: i32) -> Result<i32, String> {
+	use TokenType::*;
+	match op {
+		PLUS  => a.checked_add(b).ok_or_else(overflow),
+		MINUS => a.checked_sub(b).ok_or_else(overflow),
+		STAR  => a.checked_mul(b).ok_or_else(overflow),
+		SLASH | PERCENT | MOD if b == 0 => Err("Division by zero".to_string()),
+		SLASH => a.checked_div(b).ok_or_else(overflow),
+		PERCENT | MOD => a.checked_rem(b).ok_or_else(overflow),
+		STAR_STAR => {
+			if b < 0 { return Err(format!("Negative exponent: {b}")) }
+			a.checked_pow(b as u32).ok_or_else(overflow)
+		},
+		AMPERSAND => Ok(a & b),
+		PIPE => Ok(a | b),
+		CARET => Ok(a ^ b),
+		LESS_LESS => Ok(shift_left(a, b as i64)),
+		MORE_MORE => Ok(shift_right(a, b as i64)),
+		_ => Err(format!("Invalid binary operator: {op:?}")),
+	}
+}
+
+// Shifts never fail: a negative count shifts the other way,
+// and shifting by 32 or more pushes every bit out
+// (leaving 0, or -1 for a negative number shifted right).
+fn shift_left(a: i32, n: i64) -> i32 {
+	if n < 0 { shift_right(a, -n) }
+	else if n >= 32 { 0 }
+	else { a << n }
+}
+fn shift_right(a: i32, n: i64) -> i32 {
+	if n < 0 { shift_left(a, -n) }
+	else if n >= 32 { if a < 0 { -1 } else { 0 } }
+	else { a >> n }
+}
+
+// The built-in functions, told apart when parsed so that calls need no lookup by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Builtin { Abs, Sign, Sqrt, Min, Max, Gcd }
+impl Builtin {
+	pub fn named(name: &str) -> Option<Self> {
+		use Builtin::*;
+		Some(match name {
+			"abs" => Abs, "sign" => Sign, "sqrt" => Sqrt,
+			"min" => Min, "max" => Max, "gcd" => Gcd,
+			_ => return None,
+		})
+	}
+	pub fn name(self) -> &'static str {
+		use Builtin::*;
+		match self {
+			Abs => "abs", Sign => "sign", Sqrt => "sqrt",
+			Min => "min", Max => "max", Gcd => "gcd",
+		}
+	}
+	pub(crate) fn arity(self) -> usize {
+		use Builtin::*;
+		match self {
+			Abs | Sqrt | Sign => 1,
+			Min | Max | Gcd => 2,
+		}
+	}
+
+	pub fn call(self, args: &[i32]) -> Result<i32, String> {
+		use Builtin::*;
+		if args.len() != self.arity() {
+			return Err(format!("`{}` expects {} argument(s), got {}", self.name(), self.arity(), args.len()))
+		}
+		match self {
+			Abs => args[0].checked_abs().ok_or_else(overflow),
+			Sign => Ok(args[0].signum()),
+			Min => Ok(args[0].min(args[1])),
+			Max => Ok(args[0].max(args[1])),
+			Sqrt => isqrt(args[0]),
+			Gcd => {
+				let (mut a, mut b) = (args[0].unsigned_abs(), args[1].unsigned_abs());
+				while b != 0 { (a, b) = (b, a % b); }
+				i32::try_from(a).map_err(|_| overflow())
+			},
+		}
+	}
+}
+impl std::fmt::Display for Builtin {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { f.write_str(self.name()) }
+}
+
+// floor of the square root
+fn isqrt(v: i32) -> Result<i32, String> {
+	if v < 0 { return Err(format!("Square root of a negative number: {v}")) }
+	let v = v as i64;
+	let mut r = (v as f64).sqrt() as i64;
+	while r * r > v { r -= 1; }
+	while (r + 1) * (r + 1) <= v { r += 1; }
+	Ok(r as i32)
+}
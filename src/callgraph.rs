@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{parser::Stmt, ast::{Ast, StmtId, NodeId}, symbol::Symbol};
+
+// Which procedures each part of a module calls, built from its `call` statements.
+// Procedures are identified by their declarations; the module's own statements,
+// outside any procedure, are the caller `None`. Calls into other modules and to
+// native procedures have no declaration here, and are left out.
+pub struct CallGraph {
+	pub procedures: Vec<StmtId>, // every declaration, in the order they appear
+	calls: HashMap<Option<StmtId>, Vec<StmtId>>,
+	parents: HashMap<StmtId, Option<StmtId>>, // the procedure each is declared in
+}
+impl CallGraph {
+	pub fn build(ast: &Ast, statements: &[StmtId]) -> Self {
+		let mut builder = Builder { ast, blocks: Vec::new(), graph: CallGraph { procedures: Vec::new(), calls: HashMap::new(), parents: HashMap::new() } };
+		builder.block(None, statements);
+		builder.graph
+	}
+
+	// procedures called directly by `caller`, each once, in the order first called
+	pub fn callees(&self, caller: Option<StmtId>) -> &[StmtId] {
+		self.calls.get(&caller).map_or(&[], Vec::as_slice)
+	}
+	// the procedure `p` is declared in, None at the top level
+	pub fn parent(&self, p: StmtId) -> Option<StmtId> {
+		self.parents.get(&p).copied().flatten()
+	}
+	// every procedure `roots` call, directly or not
+	pub fn reachable(&self, roots: &[Option<StmtId>]) -> HashSet<StmtId> {
+		let mut seen = HashSet::new();
+		let mut pending: Vec<StmtId> = roots.iter().flat_map(|r| self.callees(*r)).copied().collect();
+		while let Some(p) = pending.pop() {
+			if seen.insert(p) {
+				pending.extend(self.callees(Some(p)));
+			}
+		}
+		seen
+	}
+	// Groups of procedures calling each other in a cycle (a procedure calling itself is one on its own),
+	// found as the strongly connected components of the graph with Tarjan's algorithm
+	pub fn cycles(&self) -> Vec<Vec<StmtId>> {
+		let mut tarjan = Tarjan { graph: self, index: HashMap::new(), low: HashMap::new(), stack: Vec::new(), cycles: Vec::new() };
+		for p in &self.procedures {
+			if !tarjan.index.contains_key(p) { tarjan.visit(*p); }
+		}
+		tarjan.cycles
+	}
+
+	// Writes the graph out in Graphviz's DOT language: solid edges for calls, dashed ones from
+	// each procedure to those declared in it, and procedures calling each other in a cycle in red
+	pub fn dot(&self, ast: &Ast) -> String {
+		let id = |p: Option<StmtId>| p.map_or("main".to_string(), |p| format!("p{}", p.index()));
+		let cycles = self.cycles();
+		let cycle = |p: StmtId| cycles.iter().position(|c| c.contains(&p));
+		let mut out = String::from("digraph calls {\n\tnode [shape=box, fontname=\"monospace\"];\n\tmain [shape=oval];\n");
+		for p in &self.procedures {
+			let Stmt::Proc(name, _) = &ast[*p] else { unreachable!("procedures are identified by their declarations") };
+			let style = if cycle(*p).is_some() { ", color=red, fontcolor=red" } else { "" };
+			out += &format!("\t{} [label=\"{}\"{style}];\n", id(Some(*p)), name.lexeme);
+		}
+		for p in &self.procedures {
+			out += &format!("\t{} -> {} [style=dashed, color=gray, arrowhead=none];\n", id(self.parent(*p)), id(Some(*p)));
+		}
+		for caller in std::iter::once(None).chain(self.procedures.iter().map(|p| Some(*p))) {
+			for callee in self.callees(caller) {
+				let recursive = caller.is_some_and(|c| cycle(c).is_some() && cycle(c) == cycle(*callee));
+				let style = if recursive { " [color=red, penwidth=2]" } else { "" };
+				out += &format!("\t{} -> {}{style};\n", id(caller), id(Some(*callee)));
+			}
+		}
+		out + "}\n"
+	}
+}
+
+struct Tarjan<'a> {
+	graph: &'a CallGraph,
+	index: HashMap<StmtId, usize>,
+	low: HashMap<StmtId, usize>,
+	stack: Vec<StmtId>,
+	cycles: Vec<Vec<StmtId>>,
+}
+impl Tarjan<'_> {
+	fn visit(&mut self, p: StmtId) {
+		let index = self.index.len();
+		self.index.insert(p, index);
+		self.low.insert(p, index);
+		self.stack.push(p);
+		for callee in self.graph.callees(Some(p)) {
+			if !self.index.contains_key(callee) {
+				self.visit(*callee);
+				let low = self.low[&p].min(self.low[callee]);
+				self.low.insert(p, low);
+			} else if self.stack.contains(callee) {
+				let low = self.low[&p].min(self.index[callee]);
+				self.low.insert(p, low);
+			}
+		}
+		if self.low[&p] == index {
+			let at = self.stack.iter().rposition(|q| *q == p).expect("visited procedures are on the stack");
+			let component = self.stack.split_off(at);
+			if component.len() > 1 || self.graph.callees(Some(p)).contains(&p) { self.cycles.push(component); }
+		}
+	}
+}
+
+struct Builder<'a> {
+	ast: &'a Ast,
+	// procedures declared by each enclosing block; a call finds the innermost one
+	// of its name, wherever in the block it is declared, as it does at runtime
+	blocks: Vec<HashMap<Symbol, StmtId>>,
+	graph: CallGraph,
+}
+impl Builder<'_> {
+	fn block(&mut self, owner: Option<StmtId>, statements: &[StmtId]) {
+		let declared = statements.iter()
+			.filter_map(|s| match &self.ast[*s] { Stmt::Proc(name, _) => Some((name.lexeme, *s)), _ => None })
+			.collect();
+		self.blocks.push(declared);
+		for s in statements { self.statement(owner, *s); }
+		self.blocks.pop();
+	}
+	fn statement(&mut self, caller: Option<StmtId>, s: StmtId) {
+		match &self.ast[s] {
+			Stmt::Proc(_, body) => {
+				self.graph.procedures.push(s);
+				self.graph.parents.insert(s, caller);
+				self.block(Some(s), body);
+			},
+			Stmt::Call(name, _) => {
+				let Some(callee) = self.blocks.iter().rev().find_map(|b| b.get(&name.lexeme)).copied() else { return };
+				let callees = self.graph.calls.entry(caller).or_default();
+				if !callees.contains(&callee) { callees.push(callee); }
+			},
+			Stmt::Scope(statements) => {
+				for s in statements { self.statement(caller, *s); }
+			},
+			Stmt::If(_, branch) | Stmt::While(_, branch) => self.statement(caller, *branch),
+			Stmt::Case(_, table) => {
+				for arm in &table.arms { self.statement(caller, *arm); }
+			},
+			_ => {},
+		}
+	}
+}
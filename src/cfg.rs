@@ -0,0 +1,169 @@
+use crate::{parser::Stmt, ast::{Ast, ExprId, StmtId}, symbol::Symbol, printer};
+
+// What a node of a control flow graph stands for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+	Entry,
+	Exit,
+	Block(Vec<StmtId>), // statements run one after another, with no branching in between
+	Condition(ExprId), // of an `if` or `while`, left by a `true` and a `false` edge
+	Case(ExprId), // the subject of a `case`, left by an edge for each arm
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edge {
+	Next,
+	True,
+	False,
+	Labels(Vec<i32>), // into a `case` arm
+	Otherwise, // out of a `case` no label matched
+}
+
+// The control flow of one procedure, or of the program's main body, at the level of statements
+pub struct Cfg {
+	pub name: Symbol,
+	pub parent: Option<Symbol>, // the procedure it is declared in
+	pub nodes: Vec<Node>, // the entry first, the exit second
+	pub edges: Vec<(usize, usize, Edge)>,
+}
+
+// One graph for the program's main body, then one for each procedure, outer ones first
+pub fn build(ast: &Ast, statements: &[StmtId]) -> Vec<Cfg> {
+	let mut cfgs = Vec::new();
+	procedure(ast, Symbol::intern("main"), None, statements, &mut cfgs);
+	cfgs
+}
+fn procedure(ast: &Ast, name: Symbol, parent: Option<Symbol>, body: &[StmtId], cfgs: &mut Vec<Cfg>) {
+	let mut builder = Builder { ast, cfg: Cfg { name, parent, nodes: vec![Node::Entry, Node::Exit], edges: Vec::new() }, open: None, pending: vec![(0, Edge::Next)] };
+	for s in body { builder.statement(*s); }
+	builder.connect(1);
+	cfgs.push(builder.cfg);
+	for s in body {
+		if let Stmt::Proc(inner, body) = &ast[*s] { procedure(ast, inner.lexeme, Some(name), body, cfgs); }
+	}
+}
+
+struct Builder<'a> {
+	ast: &'a Ast,
+	cfg: Cfg,
+	open: Option<usize>, // the block simple statements are added to, until something branches
+	pending: Vec<(usize, Edge)>, // edges leading to whatever comes next
+}
+impl Builder<'_> {
+	fn add(&mut self, node: Node) -> usize {
+		self.cfg.nodes.push(node);
+		self.cfg.nodes.len() - 1
+	}
+	// points the pending edges at `to`
+	fn connect(&mut self, to: usize) {
+		for (from, edge) in std::mem::take(&mut self.pending) { self.cfg.edges.push((from, to, edge)); }
+		self.open = None;
+	}
+	fn branch(&mut self, node: Node) -> usize {
+		let n = self.add(node);
+		self.connect(n);
+		n
+	}
+
+	fn statement(&mut self, s: StmtId) {
+		match &self.ast[s] {
+			Stmt::Proc(..) | Stmt::Const(_) | Stmt::Type(_) | Stmt::Var(_) | Stmt::Import(..) => {},
+			Stmt::Scope(statements) => {
+				for s in statements { self.statement(*s); }
+			},
+			Stmt::If(cond, branch) => {
+				let c = self.branch(Node::Condition(*cond));
+				self.pending = vec![(c, Edge::True)];
+				self.statement(*branch);
+				self.pending.push((c, Edge::False));
+				self.open = None;
+			},
+			Stmt::While(cond, body) => {
+				let c = self.branch(Node::Condition(*cond));
+				self.pending = vec![(c, Edge::True)];
+				self.statement(*body);
+				self.connect(c);
+				self.pending = vec![(c, Edge::False)];
+			},
+			Stmt::Case(subject, table) => {
+				let c = self.branch(Node::Case(*subject));
+				let mut after = Vec::new();
+				for (i, arm) in table.arms.iter().enumerate() {
+					let edge = if table.default == Some(i) { Edge::Otherwise }
+						else { Edge::Labels(table.labels.iter().filter(|(_, a)| *a == i).map(|(v, _)| *v).collect()) };
+					self.pending = vec![(c, edge)];
+					self.open = None;
+					self.statement(*arm);
+					after.append(&mut self.pending);
+				}
+				if table.default.is_none() { after.push((c, Edge::Otherwise)); }
+				self.pending = after;
+				self.open = None;
+			},
+			_ => {
+				let block = match self.open {
+					Some(block) => block,
+					None => {
+						let block = self.add(Node::Block(Vec::new()));
+						self.connect(block);
+						self.pending = vec![(block, Edge::Next)];
+						block
+					},
+				};
+				if let Node::Block(statements) = &mut self.cfg.nodes[block] { statements.push(s); }
+				self.open = Some(block);
+			},
+		}
+	}
+}
+
+// Writes graphs out in Graphviz's DOT language, one cluster per procedure.
+// Each statement of a block is labelled with the line it is on.
+pub fn dot(ast: &Ast, cfgs: &[Cfg]) -> String {
+	let line = |s: StmtId| ast.stmt_token(s).map_or(String::new(), |t| format!("{}: ", t.line));
+	let mut out = String::from("digraph cfg {\n\tnode [shape=box, fontname=\"monospace\"];\n");
+	for (i, cfg) in cfgs.iter().enumerate() {
+		let title = match cfg.parent {
+			Some(parent) => format!("procedure {} in {parent}", cfg.name),
+			None => format!("procedure {}", cfg.name),
+		};
+		out += &format!("\tsubgraph cluster_{i} {{\n\t\tlabel=\"{}\";\n", escape(&title));
+		for (n, node) in cfg.nodes.iter().enumerate() {
+			let (label, shape) = match node {
+				Node::Entry => ("entry".to_string(), "oval"),
+				Node::Exit => ("exit".to_string(), "oval"),
+				Node::Block(statements) => {
+					let lines: Vec<String> = statements.iter()
+						.map(|s| escape(&format!("{}{}", line(*s), printer::statement(ast, *s, 0))) + "\\l")
+						.collect();
+					(lines.concat(), "box")
+				},
+				Node::Condition(e) | Node::Case(e) => {
+					let kind = if let Node::Case(_) = node { "case " } else { "" };
+					let text = format!("{}: {kind}{}", ast.expr_token(*e).line, printer::expression(ast, *e));
+					(escape(&text), "diamond")
+				},
+			};
+			out += &format!("\t\tn{i}_{n} [label=\"{label}\", shape={shape}];\n");
+		}
+		for (from, to, edge) in &cfg.edges {
+			let label = match edge {
+				Edge::Next => String::new(),
+				Edge::True => " [label=\"true\"]".to_string(),
+				Edge::False => " [label=\"false\"]".to_string(),
+				Edge::Labels(labels) => {
+					let labels: Vec<String> = labels.iter().map(i32::to_string).collect();
+					format!(" [label=\"{}\"]", labels.join(", "))
+				},
+				Edge::Otherwise => " [label=\"else\"]".to_string(),
+			};
+			out += &format!("\t\tn{i}_{from} -> n{i}_{to}{label};\n");
+		}
+		out += "\t}\n";
+	}
+	out + "}\n"
+}
+
+fn escape(text: &str) -> String {
+	text.replace('\\', "\\\\").replace('"', "\\\"")
+}
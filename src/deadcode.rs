@@ -0,0 +1,201 @@
+use std::{collections::{HashMap, HashSet}, fmt::Display};
+
+use crate::{parser::Stmt, expressions::Expr, ast::{Ast, ExprId, StmtId}, callgraph::CallGraph, token::{Token, TokenType}, symbol::Symbol};
+
+// Something the dead code pass took out of the program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Removed {
+	Procedure(Token), // never called
+	Variable(Token), // never used
+	Statements(Token, usize), // following a loop that never ends, counted
+}
+impl Display for Removed {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Removed::Procedure(t) => write!(f, "Removed @ {}:{}: procedure `{}`, which is never called", t.file, t.line, t.lexeme),
+			Removed::Variable(t) => write!(f, "Removed @ {}:{}: variable `{}`, which is never used", t.file, t.line, t.lexeme),
+			Removed::Statements(t, 1) => write!(f, "Removed @ {}:{}: 1 statement after a loop that never ends", t.file, t.line),
+			Removed::Statements(t, n) => write!(f, "Removed @ {}:{}: {n} statements after a loop that never ends", t.file, t.line),
+		}
+	}
+}
+
+// Pass run after the optimizer, taking out what can never run or never matters:
+// procedures no call reaches, variables nothing refers to, and statements following a
+// `while true` loop (which only a runtime error or a limit gets the program out of).
+// Removed declarations are replaced with empty statements, keeping the ids of everything else.
+pub struct DeadCode<'a> {
+	ast: &'a mut Ast,
+	removed: Vec<Removed>,
+}
+impl<'a> DeadCode<'a> {
+	pub fn new(ast: &'a mut Ast) -> Self {
+		DeadCode { ast, removed: Vec::new() }
+	}
+	// Prunes one module. Top-level variables are always kept, as the host (through
+	// `Output::global`) and importing modules may read them; so are an imported module's
+	// top-level procedures. Only the program's own top-level procedures go if never called.
+	pub fn eliminate(&mut self, statements: &[StmtId], is_program: bool) {
+		for s in statements { self.after_endless_loops(*s); }
+
+		let graph = CallGraph::build(self.ast, statements);
+		let mut roots = vec![None];
+		let mut kept = HashSet::new();
+		if !is_program {
+			for s in statements {
+				if let Stmt::Proc(..) = self.ast[*s] { roots.push(Some(*s)); kept.insert(*s); }
+			}
+		}
+		let reachable: HashSet<StmtId> = graph.reachable(&roots).union(&kept).copied().collect();
+		for s in statements { self.unreachable(*s, &reachable); }
+
+		let mut uses = Uses { ast: self.ast, blocks: Vec::new(), used: HashSet::new() };
+		uses.block(statements);
+		let used = uses.used;
+		for s in statements { self.nested_unused(*s, &used); }
+	}
+	pub fn removed(&self) -> &[Removed] { &self.removed }
+	pub fn into_removed(self) -> Vec<Removed> { self.removed }
+
+	fn is_empty(&self, s: StmtId) -> bool {
+		matches!(&self.ast[s], Stmt::Scope(statements) if statements.is_empty())
+	}
+	fn never_ends(&self, s: StmtId) -> bool {
+		match &self.ast[s] {
+			Stmt::While(cond, _) => matches!(&self.ast[*cond], Expr::Literal(t) if t.kind == TokenType::TRUE),
+			Stmt::Scope(statements) => statements.iter().any(|s| self.never_ends(*s)),
+			Stmt::Case(_, table) => table.default.is_some() && table.arms.iter().all(|a| self.never_ends(*a)),
+			_ => false,
+		}
+	}
+
+	fn after_endless_loops(&mut self, s: StmtId) {
+		match self.ast[s].clone() {
+			Stmt::Proc(_, body) => {
+				for s in body { self.after_endless_loops(s); }
+			},
+			Stmt::Scope(mut statements) => {
+				if let Some(end) = statements.iter().position(|s| self.never_ends(*s)) {
+					let dead = statements.split_off(end + 1).into_iter().filter(|s| !self.is_empty(*s)).count();
+					if dead > 0 {
+						if let Some(at) = self.ast.stmt_token(statements[end]).cloned() {
+							self.removed.push(Removed::Statements(at, dead));
+						}
+					}
+					self.ast[s] = Stmt::Scope(statements.clone());
+				}
+				for s in statements { self.after_endless_loops(s); }
+			},
+			Stmt::If(_, branch) | Stmt::While(_, branch) => self.after_endless_loops(branch),
+			Stmt::Case(_, table) => {
+				for arm in table.arms { self.after_endless_loops(arm); }
+			},
+			_ => {},
+		}
+	}
+
+	fn unreachable(&mut self, s: StmtId, reachable: &HashSet<StmtId>) {
+		let Stmt::Proc(name, body) = self.ast[s].clone() else { return };
+		if reachable.contains(&s) {
+			for s in body { self.unreachable(s, reachable); }
+		} else {
+			self.removed.push(Removed::Procedure(name));
+			self.ast[s] = Stmt::Scope(Vec::new());
+		}
+	}
+
+	fn unused(&mut self, s: StmtId, used: &HashSet<(StmtId, usize)>) {
+		match self.ast[s].clone() {
+			Stmt::Var(vars) => {
+				let mut kept = Vec::new();
+				for (i, var) in vars.into_iter().enumerate() {
+					if used.contains(&(s, i)) { kept.push(var) }
+					else { self.removed.push(Removed::Variable(var.0)) }
+				}
+				self.ast[s] = if kept.is_empty() { Stmt::Scope(Vec::new()) } else { Stmt::Var(kept) };
+			},
+			Stmt::Proc(..) => self.nested_unused(s, used),
+			_ => {},
+		}
+	}
+	// unused variables of the procedure `s`, and of those nested in it
+	fn nested_unused(&mut self, s: StmtId, used: &HashSet<(StmtId, usize)>) {
+		if let Stmt::Proc(_, body) = self.ast[s].clone() {
+			for s in body { self.unused(s, used); }
+		}
+	}
+}
+
+// Finds the variables that are referred to, as (declaration, index in it),
+// resolving names the way the interpreter does: to the innermost block declaring them
+struct Uses<'a> {
+	ast: &'a Ast,
+	blocks: Vec<HashMap<Symbol, Option<(StmtId, usize)>>>, // None for consts, which shadow variables
+	used: HashSet<(StmtId, usize)>,
+}
+impl Uses<'_> {
+	fn block(&mut self, statements: &[StmtId]) {
+		let mut names = HashMap::new();
+		for s in statements {
+			match &self.ast[*s] {
+				Stmt::Var(vars) => {
+					for (i, (name, _)) in vars.iter().enumerate() { names.insert(name.lexeme, Some((*s, i))); }
+				},
+				Stmt::Const(consts) => {
+					for (name, _) in consts { names.insert(name.lexeme, None); }
+				},
+				_ => {},
+			}
+		}
+		self.blocks.push(names);
+		for s in statements { self.statement(*s); }
+		self.blocks.pop();
+	}
+	fn name(&mut self, name: &Token) {
+		if let Some(Some(var)) = self.blocks.iter().rev().find_map(|b| b.get(&name.lexeme)) {
+			self.used.insert(*var);
+		}
+	}
+	fn statement(&mut self, s: StmtId) {
+		let ast = self.ast;
+		match &ast[s] {
+			Stmt::Proc(_, body) => self.block(body),
+			Stmt::Print(e) | Stmt::Expression(e) => self.expression(*e),
+			Stmt::PrintVar(name) => self.name(name),
+			Stmt::Write(args, _, _) | Stmt::Call(_, args) => {
+				for a in args { self.expression(*a); }
+			},
+			Stmt::Scope(statements) => {
+				for s in statements { self.statement(*s); }
+			},
+			Stmt::Assign(name, e) | Stmt::Set(name, _, e) => {
+				self.name(name);
+				self.expression(*e);
+			},
+			Stmt::If(cond, branch) | Stmt::While(cond, branch) => {
+				self.expression(*cond);
+				self.statement(*branch);
+			},
+			Stmt::Case(subject, table) => {
+				self.expression(*subject);
+				for arm in &table.arms { self.statement(*arm); }
+			},
+			Stmt::Const(_) | Stmt::Type(_) | Stmt::Var(_) | Stmt::Import(..) => {},
+		}
+	}
+	fn expression(&mut self, expr: ExprId) {
+		let ast = self.ast;
+		match &ast[expr] {
+			Expr::Literal(_) => {},
+			Expr::Grouping(e) | Expr::Unary(_, e) => self.expression(*e),
+			Expr::Binary(a, _, b) => {
+				self.expression(*a);
+				self.expression(*b);
+			},
+			Expr::Variable(name) | Expr::Get(name, _) => self.name(name),
+			Expr::Call(_, _, args) => {
+				for a in args { self.expression(*a); }
+			},
+		}
+	}
+}
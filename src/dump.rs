@@ -0,0 +1,107 @@
+use crate::{parser::Stmt, expressions::Expr, ast::{Ast, ExprId, StmtId}, printer};
+
+// A node of the tree as dumped, with whatever it holds spelled out in its label
+struct Node {
+	label: String,
+	children: Vec<Node>,
+}
+fn leaf(label: String) -> Node { Node { label, children: Vec::new() } }
+
+// The syntax tree of a program, one node per line, children indented under their parent:
+//
+//   Program
+//     Var @1
+//       x
+//     Scope @1
+//       Assign x @1
+//         Binary * @1
+//
+// Statements and expressions are marked with the line they are on.
+pub fn tree(ast: &Ast, statements: &[StmtId]) -> String {
+	fn write(node: &Node, depth: usize, out: &mut String) {
+		*out += &format!("{}{}\n", "  ".repeat(depth), node.label);
+		for child in &node.children { write(child, depth + 1, out); }
+	}
+	let mut out = String::new();
+	write(&program(ast, statements), 0, &mut out);
+	out
+}
+
+// The same tree in Graphviz's DOT language
+pub fn dot(ast: &Ast, statements: &[StmtId]) -> String {
+	fn write(node: &Node, out: &mut String, next: &mut usize) -> usize {
+		let id = *next;
+		*next += 1;
+		*out += &format!("\tn{id} [label=\"{}\"];\n", node.label.replace('\\', "\\\\").replace('"', "\\\""));
+		for child in &node.children {
+			let child = write(child, out, next);
+			*out += &format!("\tn{id} -> n{child};\n");
+		}
+		id
+	}
+	let mut out = String::from("digraph ast {\n\tnode [shape=box, fontname=\"monospace\"];\n");
+	write(&program(ast, statements), &mut out, &mut 0);
+	out + "}\n"
+}
+
+fn program(ast: &Ast, statements: &[StmtId]) -> Node {
+	Node { label: "Program".to_string(), children: statements.iter().map(|s| statement(ast, *s)).collect() }
+}
+
+fn statement(ast: &Ast, s: StmtId) -> Node {
+	let at = ast.stmt_token(s).map_or(String::new(), |t| format!(" @{}", t.line));
+	let exprs = |args: &[ExprId]| args.iter().map(|a| expression(ast, *a)).collect::<Vec<_>>();
+	let stmts = |body: &[StmtId]| body.iter().map(|s| statement(ast, *s)).collect::<Vec<_>>();
+	let (label, children) = match &ast[s] {
+		Stmt::Proc(name, body) => (format!("Proc {}", name.lexeme), stmts(body)),
+		Stmt::Const(consts) => ("Const".to_string(), consts.iter().map(|(n, v)| leaf(format!("{} = {v}", n.lexeme))).collect()),
+		Stmt::Type(types) => ("Type".to_string(), types.iter().map(|(n, fields)| {
+			let fields: Vec<String> = fields.iter().map(|f| f.lexeme.to_string()).collect();
+			leaf(format!("{} = record {}", n.lexeme, fields.join(", ")))
+		}).collect()),
+		Stmt::Var(vars) => ("Var".to_string(), vars.iter().map(|(n, kind)| leaf(match kind {
+			Some(k) => format!("{}: {}", n.lexeme, k.lexeme),
+			None => n.lexeme.to_string(),
+		})).collect()),
+		Stmt::Print(e) => ("Print".to_string(), exprs(&[*e])),
+		Stmt::PrintVar(name) => (format!("PrintVar {}", name.lexeme), Vec::new()),
+		Stmt::Write(args, newline, prefix) => {
+			let kind = match (newline, prefix) { (true, true) => "Print", (true, false) => "Writeln", _ => "Write" };
+			(kind.to_string(), exprs(args))
+		},
+		Stmt::Expression(e) => ("Expression".to_string(), exprs(&[*e])),
+		Stmt::Scope(statements) => ("Scope".to_string(), stmts(statements)),
+		Stmt::Assign(name, e) => (format!("Assign {}", name.lexeme), exprs(&[*e])),
+		Stmt::Set(name, field, e) => (format!("Set {}.{}", name.lexeme, field.lexeme), exprs(&[*e])),
+		Stmt::If(cond, branch) => ("If".to_string(), vec![expression(ast, *cond), statement(ast, *branch)]),
+		Stmt::While(cond, body) => ("While".to_string(), vec![expression(ast, *cond), statement(ast, *body)]),
+		Stmt::Call(name, args) => (format!("Call {}", name.lexeme), exprs(args)),
+		Stmt::Case(subject, table) => {
+			let mut children = vec![expression(ast, *subject)];
+			for (i, arm) in table.arms.iter().enumerate() {
+				let labels = if table.default == Some(i) { "else".to_string() } else {
+					let labels: Vec<String> = table.labels.iter().filter(|(_, a)| *a == i).map(|(v, _)| v.to_string()).collect();
+					labels.join(", ")
+				};
+				children.push(Node { label: format!("Arm {labels}"), children: vec![statement(ast, *arm)] });
+			}
+			("Case".to_string(), children)
+		},
+		Stmt::Import(name, file) => (format!("Import {} from {file}", name.lexeme), Vec::new()),
+	};
+	Node { label: label + &at, children }
+}
+
+fn expression(ast: &Ast, e: ExprId) -> Node {
+	let at = format!(" @{}", ast.expr_token(e).line);
+	let (label, children) = match &ast[e] {
+		Expr::Literal(_) => (format!("Literal {}", printer::expression(ast, e)), Vec::new()),
+		Expr::Grouping(inner) => ("Grouping".to_string(), vec![expression(ast, *inner)]),
+		Expr::Unary(op, a) => (format!("Unary {}", op.lexeme), vec![expression(ast, *a)]),
+		Expr::Binary(a, op, b) => (format!("Binary {}", op.lexeme), vec![expression(ast, *a), expression(ast, *b)]),
+		Expr::Variable(name) => (format!("Variable {}", name.lexeme), Vec::new()),
+		Expr::Get(name, field) => (format!("Get {}.{}", name.lexeme, field.lexeme), Vec::new()),
+		Expr::Call(name, _, args) => (format!("Call {}", name.lexeme), args.iter().map(|a| expression(ast, *a)).collect()),
+	};
+	Node { label: label + &at, children }
+}
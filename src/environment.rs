@@ -1,78 +1,282 @@
 use std::{collections::HashMap, rc::Rc, cell::RefCell};
 
-use crate::{token::Token, error::RuntimeError, parser::Stmt};
+use crate::{token::{Token, Literal}, symbol::Symbol, error::RuntimeError, ast::StmtId, interpreter::RuntimeValue, typecheck::Type, expressions::Slot};
 
+type Shared = Rc<RefCell<Environment>>;
+
+// What a variable slot holds
+enum Binding {
+	Value(Option<RuntimeValue>),
+	Record(Vec<(Symbol, Option<i32>)>),
+}
+
+// One frame: the program's or a module's globals, or a procedure call's locals.
+// Variables sit in `slots` in declaration order, which is the index the resolver
+// hands out; `names` maps them back for lookups by name.
 #[derive(Default)]
 pub struct Environment {
 	parent: Option<Rc<RefCell<Environment>>>,
-	values: HashMap<String, Option<i32>>,
-	consts: HashMap<String, i32>,
-	procedures: HashMap<String, Vec<Stmt>>,
+	slots: Vec<Binding>,
+	names: HashMap<Symbol, usize>,
+	consts: HashMap<Symbol, i32>,
+	procedures: HashMap<Symbol, StmtId>, // their declarations
+	types: HashMap<Symbol, Vec<Symbol>>,
+	modules: HashMap<Symbol, Shared>,
 }
 impl Environment {
 	pub fn new() -> Self {
-		Environment {
-			..Default::default()
-		}
+		Self::default()
 	}
 	pub fn from_parent(parent: Rc<RefCell<Environment>>) -> Self {
-		Environment {
-			parent: Some(parent),
-			..Default::default()
+		let mut env = Self::default();
+		env.parent = Some(parent);
+		env
+	}
+	// the environment `depth` parents out from `env`
+	pub fn ancestor(env: &Shared, depth: usize) -> Shared {
+		let mut env = env.clone();
+		for _ in 0..depth {
+			let parent = env.borrow().parent.clone().expect("depth within the chain of environments");
+			env = parent;
 		}
+		env
 	}
 
-	pub fn get_var(&self, name: Token) -> Result<Option<i32>, RuntimeError> {
-		let lex = name.lexeme.clone();
-		if let Some(v) = self.values.get(&lex) { Ok(*v) }
-		else if let Some(c) = self.consts.get(&lex) { Ok(Some(*c)) }
-		else {
-			if self.parent.is_some() {
-				return self.parent.as_ref().unwrap().borrow().get_var(name)
-			}
-			Err(RuntimeError{ msg: format!("Uninitialised variable: {}", lex) })
+	// Walks from this environment outwards until `f` has an answer.
+	// Deep recursion makes for long chains, so this loops rather than recursing.
+	fn find<T>(&self, mut f: impl FnMut(&Environment) -> Option<T>) -> Option<T> {
+		if let Some(found) = f(self) { return Some(found) }
+		let mut next = self.parent.clone();
+		while let Some(env) = next {
+			let env = env.borrow();
+			if let Some(found) = f(&env) { return Some(found) }
+			next = env.parent.clone();
 		}
+		None
 	}
-	pub fn declare_var(&mut self, name: Token) -> Result<(), RuntimeError> {
-		if self.values.insert(name.lexeme.clone(), None).is_some() {
-			Err(RuntimeError{msg: format!("Double declaration of name: {}", name.lexeme)})
-		} else { Ok(()) }
+	fn find_mut<T>(&mut self, mut f: impl FnMut(&mut Environment) -> Option<T>) -> Option<T> {
+		if let Some(found) = f(self) { return Some(found) }
+		let mut next = self.parent.clone();
+		while let Some(env) = next {
+			let mut env = env.borrow_mut();
+			if let Some(found) = f(&mut env) { return Some(found) }
+			next = env.parent.clone();
+		}
+		None
+	}
+	// Runs `f` on the binding in a resolved slot
+	fn at<T>(&self, slot: Slot, f: impl FnOnce(&Binding) -> Result<T, RuntimeError>) -> Result<T, RuntimeError> {
+		let missing = || RuntimeError::new(format!("No variable in slot {}:{}", slot.depth, slot.index));
+		if slot.depth == 0 {
+			return f(self.slots.get(slot.index).ok_or_else(missing)?)
+		}
+		let parent = self.parent.as_ref().ok_or_else(missing)?;
+		let env = Self::ancestor(parent, slot.depth - 1);
+		let env = env.borrow();
+		f(env.slots.get(slot.index).ok_or_else(missing)?)
+	}
+	fn at_mut<T>(&mut self, slot: Slot, f: impl FnOnce(&mut Binding) -> Result<T, RuntimeError>) -> Result<T, RuntimeError> {
+		let missing = || RuntimeError::new(format!("No variable in slot {}:{}", slot.depth, slot.index));
+		if slot.depth == 0 {
+			return f(self.slots.get_mut(slot.index).ok_or_else(missing)?)
+		}
+		let parent = self.parent.as_ref().ok_or_else(missing)?;
+		let env = Self::ancestor(parent, slot.depth - 1);
+		let mut env = env.borrow_mut();
+		f(env.slots.get_mut(slot.index).ok_or_else(missing)?)
+	}
+	fn declare(&mut self, name: &Token, binding: Binding) -> Result<(), RuntimeError> {
+		if self.names.contains_key(&name.lexeme) {
+			return Err(RuntimeError::new(format!("Double declaration of name: {}", name.lexeme)))
+		}
+		self.names.insert(name.lexeme, self.slots.len());
+		self.slots.push(binding);
+		Ok(())
 	}
-	pub fn assign_var(&mut self, name: Token, value: Option<i32>) -> Result<(), RuntimeError> {
-		let lex = name.lexeme.clone();
-		if !self.values.contains_key(&lex) {
-			if self.parent.is_some() {
-				return self.parent.as_ref().unwrap().borrow_mut().assign_var(name, value)
+
+	pub fn import_module(&mut self, name: &Token, module: Shared) {
+		self.modules.insert(name.lexeme, module);
+	}
+	pub fn get_module(&self, name: Symbol) -> Result<Shared, RuntimeError> {
+		self.find(|env| env.modules.get(&name).cloned())
+			.ok_or_else(|| RuntimeError::new(format!("Unknown module: {}", name)))
+	}
+	// splits `module.member` into the module's environment and the member's name
+	fn qualified(&self, name: &Token) -> Result<Option<(Shared, Token)>, RuntimeError> {
+		match name.literal {
+			Some(Literal::Qualified(module, member)) => {
+				let member = Token { lexeme: member, literal: Some(Literal::Identifier(member)), ..name.clone() };
+				Ok(Some((self.get_module(module)?, member)))
+			},
+			_ => Ok(None),
+		}
+	}
+
+	pub fn get_var(&self, name: &Token) -> Result<Option<RuntimeValue>, RuntimeError> {
+		if let Some((module, member)) = self.qualified(name)? {
+			return module.borrow().get_var(&member)
+		}
+		let lex = name.lexeme;
+		self.find(|env| {
+			if let Some(i) = env.names.get(&lex) { Some(Self::value(&env.slots[*i], lex)) }
+			else { env.consts.get(&lex).map(|c| Ok(Some(RuntimeValue::Value(*c)))) }
+		}).unwrap_or_else(|| Err(RuntimeError::new(format!("Reading undeclared variable: {}", lex))))
+	}
+	pub fn get_slot(&self, name: &Token, slot: Slot) -> Result<Option<RuntimeValue>, RuntimeError> {
+		self.at(slot, |b| Self::value(b, name.lexeme))
+	}
+	fn value(binding: &Binding, name: Symbol) -> Result<Option<RuntimeValue>, RuntimeError> {
+		match binding {
+			Binding::Value(v) => Ok(v.clone()),
+			Binding::Record(_) => Err(RuntimeError::new(format!("Record used as a value: {}", name))),
+		}
+	}
+	pub fn declare_var(&mut self, name: &Token) -> Result<(), RuntimeError> {
+		self.declare(name, Binding::Value(None))
+	}
+	pub fn assign_var(&mut self, name: &Token, value: Option<RuntimeValue>) -> Result<(), RuntimeError> {
+		if let Some((module, member)) = self.qualified(name)? {
+			return module.borrow_mut().assign_var(&member, value)
+		}
+		let lex = name.lexeme;
+		let mut value = Some(value);
+		self.find_mut(|env| {
+			if let Some(i) = env.names.get(&lex) {
+				Some(Self::assign(&mut env.slots[*i], value.take().unwrap(), lex))
 			}
-			if self.consts.contains_key(&lex) {
-				return Err(RuntimeError{msg: format!("Attempting to assign to a const: {}", lex)})
+			else if env.consts.contains_key(&lex) {
+				Some(Err(RuntimeError::new(format!("Attempting to assign to a const: {}", lex))))
 			}
-			Err(RuntimeError{msg: format!("Assigning to undeclared variable: {}", lex)})
-		} else {
-			self.values.insert(lex, value);
-			Ok(())
+			else { None }
+		}).unwrap_or_else(|| Err(RuntimeError::new(format!("Assigning to undeclared variable: {}", lex))))
+	}
+	pub fn assign_slot(&mut self, name: &Token, slot: Slot, value: Option<RuntimeValue>) -> Result<(), RuntimeError> {
+		self.at_mut(slot, |b| Self::assign(b, value, name.lexeme))
+	}
+	fn assign(binding: &mut Binding, value: Option<RuntimeValue>, name: Symbol) -> Result<(), RuntimeError> {
+		match binding {
+			Binding::Value(slot) => {
+				*slot = value;
+				Ok(())
+			},
+			Binding::Record(_) => Err(RuntimeError::new(format!("Cannot assign to a whole record: {}", name))),
 		}
 	}
 
-	pub fn declare_const(&mut self, name: Token, value: i32) -> Result<(), RuntimeError> {
-		let lex = name.lexeme.clone();
-		if self.consts.insert(lex.clone(), value).is_some() {
-			Err(RuntimeError{msg: format!("Double definition of const: {}", lex)})
+	pub fn declare_const(&mut self, name: &Token, value: i32) -> Result<(), RuntimeError> {
+		let lex = name.lexeme;
+		if self.consts.insert(lex, value).is_some() {
+			Err(RuntimeError::new(format!("Double definition of const: {}", lex)))
 		} else { Ok(()) }
 	}
 
-	pub fn get_proc(&self, name: Token) -> Result<Vec<Stmt>, RuntimeError> {
-		if let Some(v) = self.procedures.get(&name.lexeme) { Ok(v.clone()) }
-		else {
-			if self.parent.is_some() {
-				return self.parent.as_ref().unwrap().borrow_mut().get_proc(name)
-			}
-			Err(RuntimeError{msg: format!("Undefined procedure: {}", name.lexeme)})
+	// A procedure's declaration, and how many environments out from this one it was declared in.
+	// A module's procedure is returned with a depth of 0.
+	pub fn get_proc(&self, name: &Token) -> Result<(StmtId, usize), RuntimeError> {
+		if let Some((module, member)) = self.qualified(name)? {
+			return module.borrow().get_proc(&member)
 		}
+		let mut depth = 0;
+		self.find(|env| {
+			let found = env.procedures.get(&name.lexeme).map(|p| (*p, depth));
+			depth += 1;
+			found
+		}).ok_or_else(|| RuntimeError::new(format!("Undefined procedure: {}", name.lexeme)))
 	}
-	pub fn define_proc(&mut self, name: Token, def: Vec<Stmt>) -> Result<(), RuntimeError> {
-		if self.procedures.insert(name.lexeme.clone(), def).is_some() {
-			Err(RuntimeError{msg: format!("Double definition of procedure: {}", name.lexeme)})
+	pub fn define_proc(&mut self, name: &Token, def: StmtId) -> Result<(), RuntimeError> {
+		if self.procedures.insert(name.lexeme, def).is_some() {
+			Err(RuntimeError::new(format!("Double definition of procedure: {}", name.lexeme)))
 		} else { Ok(()) }
 	}
+
+	pub fn define_type(&mut self, name: &Token, fields: &[Token]) -> Result<(), RuntimeError> {
+		let fields = fields.iter().map(|f| f.lexeme).collect();
+		if self.types.insert(name.lexeme, fields).is_some() {
+			Err(RuntimeError::new(format!("Double definition of type: {}", name.lexeme)))
+		} else { Ok(()) }
+	}
+	fn get_type(&self, name: &Token) -> Result<Vec<Symbol>, RuntimeError> {
+		self.find(|env| env.types.get(&name.lexeme).cloned())
+			.ok_or_else(|| RuntimeError::new(format!("Undefined type: {}", name.lexeme)))
+	}
+
+	pub fn declare_typed(&mut self, name: &Token, kind: &Token) -> Result<(), RuntimeError> {
+		if Type::builtin_symbol(kind.lexeme).is_some() { self.declare_var(name) }
+		else { self.declare_record(name, kind) }
+	}
+	fn declare_record(&mut self, name: &Token, kind: &Token) -> Result<(), RuntimeError> {
+		let fields = self.get_type(kind)?.into_iter().map(|f| (f, None)).collect();
+		self.declare(name, Binding::Record(fields))
+	}
+	pub fn get_record(&self, name: &Token) -> Result<Vec<(Symbol, Option<i32>)>, RuntimeError> {
+		if let Some((module, member)) = self.qualified(name)? {
+			return module.borrow().get_record(&member)
+		}
+		let lex = name.lexeme;
+		self.find(|env| {
+			if let Some(i) = env.names.get(&lex) { Some(Self::record(&env.slots[*i], lex).cloned()) }
+			else if env.consts.contains_key(&lex) {
+				Some(Err(RuntimeError::new(format!("Not a record: {}", lex))))
+			}
+			else { None }
+		}).unwrap_or_else(|| Err(RuntimeError::new(format!("Undeclared record: {}", lex))))
+	}
+	pub fn get_record_slot(&self, name: &Token, slot: Slot) -> Result<Vec<(Symbol, Option<i32>)>, RuntimeError> {
+		self.at(slot, |b| Self::record(b, name.lexeme).cloned())
+	}
+	fn record(binding: &Binding, name: Symbol) -> Result<&Vec<(Symbol, Option<i32>)>, RuntimeError> {
+		match binding {
+			Binding::Record(fields) => Ok(fields),
+			Binding::Value(_) => Err(RuntimeError::new(format!("Not a record: {}", name))),
+		}
+	}
+	pub fn get_field(&self, name: &Token, field: &Token) -> Result<Option<i32>, RuntimeError> {
+		Self::field(&self.get_record(name)?, name, field)
+	}
+	pub fn get_field_slot(&self, name: &Token, field: &Token, slot: Slot) -> Result<Option<i32>, RuntimeError> {
+		self.at(slot, |b| Self::field(Self::record(b, name.lexeme)?, name, field))
+	}
+	fn field(fields: &[(Symbol, Option<i32>)], name: &Token, field: &Token) -> Result<Option<i32>, RuntimeError> {
+		fields.iter()
+			.find(|(f, _)| *f == field.lexeme)
+			.map(|(_, v)| *v)
+			.ok_or(RuntimeError::new(format!("Record {} has no field: {}", name.lexeme, field.lexeme)))
+	}
+	pub fn assign_field(&mut self, name: &Token, field: &Token, value: Option<i32>) -> Result<(), RuntimeError> {
+		let lex = name.lexeme;
+		self.find_mut(|env| {
+			if let Some(i) = env.names.get(&lex) {
+				Some(Self::set_field(&mut env.slots[*i], name, field, value))
+			}
+			else if env.consts.contains_key(&lex) {
+				Some(Err(RuntimeError::new(format!("Not a record: {}", lex))))
+			}
+			else { None }
+		}).unwrap_or_else(|| Err(RuntimeError::new(format!("Assigning to undeclared record: {}", lex))))
+	}
+	pub fn assign_field_slot(&mut self, name: &Token, field: &Token, slot: Slot, value: Option<i32>) -> Result<(), RuntimeError> {
+		self.at_mut(slot, |b| Self::set_field(b, name, field, value))
+	}
+	fn set_field(binding: &mut Binding, name: &Token, field: &Token, value: Option<i32>) -> Result<(), RuntimeError> {
+		let Binding::Record(fields) = binding else {
+			return Err(RuntimeError::new(format!("Not a record: {}", name.lexeme)))
+		};
+		let slot = fields.iter_mut().find(|(f, _)| *f == field.lexeme)
+			.ok_or(RuntimeError::new(format!("Record {} has no field: {}", name.lexeme, field.lexeme)))?;
+		slot.1 = value;
+		Ok(())
+	}
+}
+// Dropping the innermost of a long chain of environments would otherwise
+// drop each parent in turn recursively
+impl Drop for Environment {
+	fn drop(&mut self) {
+		let mut next = self.parent.take();
+		while let Some(env) = next {
+			match Rc::try_unwrap(env) {
+				Ok(env) => next = env.into_inner().parent.take(),
+				Err(_) => break,
+			}
+		}
+	}
 }
@@ -1,20 +1,63 @@
-use std::{fmt::Display, error::Error};
+use std::{fmt::Display, error::Error, cell::RefCell, rc::Rc};
 
 use crate::token::{Token, TokenType};
 
-static mut FAILED: bool = false;
-fn report(line: usize, location: String, message: String) {
-	eprintln!("Error @ line {line}, {location}: {message}");
+/// An error found before the program runs: while scanning, parsing, loading modules or type checking.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+	pub file: Rc<str>,
+	pub line: usize,
+	pub location: Option<String>, // e.g. "at `x`"
+	/// Where on the line the offending token sits, when the error is about one.
+	pub span: Option<Span>,
+	pub message: String,
 }
-pub fn error(token: Token, message: String) {
-	unsafe { FAILED = true; }
-	if token.kind == TokenType::EOF {
-		report(token.line, "at the end".to_string(), message)
-	} else {
-		report(token.line, format!("at `{}`", token.lexeme), message)
+/// Columns counted in characters, the first being 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+	pub column: usize,
+	pub width: usize,
+}
+impl Diagnostic {
+	/// The line of `source` the error is on, with the span marked underneath,
+	/// e.g. for printing below the message; `None` without a span.
+	pub fn excerpt(&self, source: &str) -> Option<String> {
+		let span = self.span?;
+		let line = source.lines().nth(self.line.checked_sub(1)?)?;
+		// keep tabs, so that the marks line up with what they point at
+		let pad: String = line.chars().take(span.column - 1).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+		Some(format!("{line}\n{pad}{}", "^".repeat(span.width.max(1))))
+	}
+}
+impl Display for Diagnostic {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.location {
+			Some(location) => write!(f, "Error @ {}:{}, {location}: {}", self.file, self.line, self.message),
+			None => write!(f, "Error @ {}:{}: {}", self.file, self.line, self.message),
+		}
 	}
 }
-pub fn had_error() -> bool { unsafe { FAILED } }
+
+// Diagnostics are collected per thread, and drained by whoever drives the pipeline
+thread_local! {
+	static DIAGNOSTICS: RefCell<Vec<Diagnostic>> = const { RefCell::new(Vec::new()) };
+}
+fn report(diagnostic: Diagnostic) {
+	DIAGNOSTICS.with(|d| d.borrow_mut().push(diagnostic));
+}
+pub fn error(token: Token, message: String) {
+	let location =
+		if token.kind == TokenType::EOF { "at the end".to_string() }
+		else { format!("at `{}`", token.text()) };
+	// tokens the compiler made up have no column
+	let span = (token.column > 0).then(|| Span { column: token.column, width: token.text().chars().count() });
+	report(Diagnostic { file: token.file, line: token.line, location: Some(location), span, message })
+}
+pub fn error_at(file: Rc<str>, line: usize, message: String) {
+	report(Diagnostic { file, line, location: None, span: None, message })
+}
+pub fn had_error() -> bool { DIAGNOSTICS.with(|d| !d.borrow().is_empty()) }
+pub fn take_diagnostics() -> Vec<Diagnostic> { DIAGNOSTICS.with(|d| d.take()) }
 
 #[derive(Debug)]
 pub struct ParseError;
@@ -25,11 +68,67 @@ impl Display for ParseError {
 }
 impl Error for ParseError {}
 
+/// What stopped a running program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeErrorKind {
+	/// The program did something invalid, e.g. divided by zero.
+	Error,
+	/// More statements were executed than [`crate::Limits::max_statements`] allows.
+	StatementLimit,
+	/// Calls nested deeper than [`crate::Limits::max_call_depth`] allows.
+	CallDepth,
+	/// The program ran longer than [`crate::Limits::timeout`].
+	Timeout,
+	/// The host called [`crate::limits::CancelHandle::cancel`].
+	Cancelled,
+}
+
 #[derive(Debug)]
-pub struct RuntimeError { pub msg: String }
+pub struct RuntimeError {
+	pub msg: String,
+	pub kind: RuntimeErrorKind,
+	pub location: Option<(Rc<str>, usize)>, // file and line of the statement that failed
+}
+impl RuntimeError {
+	pub fn new(msg: String) -> Self {
+		RuntimeError { msg, kind: RuntimeErrorKind::Error, location: None }
+	}
+	pub fn of_kind(kind: RuntimeErrorKind, msg: String) -> Self {
+		RuntimeError { msg, kind, location: None }
+	}
+	// Attributes the error to a statement, unless a statement nested in it already claimed it
+	pub fn located(mut self, location: Option<(Rc<str>, usize)>) -> Self {
+		if self.location.is_none() { self.location = location; }
+		self
+	}
+}
 impl Display for RuntimeError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		f.write_str(&format!("Runtime error: {}", self.msg))
+		match &self.location {
+			Some((file, line)) => write!(f, "Runtime error @ {file}:{line}: {}", self.msg),
+			None => write!(f, "Runtime error: {}", self.msg),
+		}
 	}
 }
 impl Error for RuntimeError {}
+
+/// Why a program did not run to completion.
+#[derive(Debug)]
+pub enum Diagnostics {
+	/// Errors found before running; nothing was executed.
+	Compile(Vec<Diagnostic>),
+	/// The error that stopped the running program.
+	Runtime(RuntimeError),
+}
+impl Display for Diagnostics {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Diagnostics::Compile(errors) => {
+				let lines: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+				f.write_str(&lines.join("\n"))
+			},
+			Diagnostics::Runtime(e) => e.fmt(f),
+		}
+	}
+}
+impl Error for Diagnostics {}
@@ -1,25 +1,22 @@
-use std::fmt::Display;
+use crate::{token::Token, ast::ExprId, builtins::Builtin};
 
-use crate::token::Token;
+// Where a variable lives, as found by the resolver: how many frames out
+// from the current one, and its index among that frame's variables
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slot {
+	pub depth: usize,
+	pub index: usize,
+}
 
+// Expressions live in an `Ast`, referring to their operands by id
 #[derive(Clone)]
 pub enum Expr {
 	Literal(Token),
-	Grouping(Box<Expr>),
-	Unary(Token, Box<Expr>),
-	Binary(Box<Expr>, Token, Box<Expr>),
+	Grouping(ExprId),
+	Unary(Token, ExprId),
+	Binary(ExprId, Token, ExprId),
 
 	Variable(Token),
-}
-impl Display for Expr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-		use Expr::*;
-		match self {
-			Literal(l) => f.write_str(&l.lexeme),
-			Grouping(a) => f.write_str(&format!("(group {a})")),
-			Unary(o, a) => f.write_str(&format!("({} {a})", o.lexeme)),
-			Binary(a, o, b) => f.write_str(&format!("({} {a} {b})", o.lexeme)),
-			Variable(name) => f.write_str(&format!("`{}`", name.lexeme)),
-		}
-    }
+	Get(Token, Token), // record field access
+	Call(Token, Option<Builtin>, Vec<ExprId>), // with the built-in function it names, if any
 }
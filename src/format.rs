@@ -0,0 +1,717 @@
+use crate::{token::{Token, TokenType}, error::{error, ParseError}};
+
+// A token as written, along with the comments around it
+#[derive(Clone, Debug)]
+pub struct Leaf {
+	pub token: Token,
+	pub comments: Vec<Comment>, // on lines of their own, before the token
+	pub trailing: Option<String>, // after the token, on the same line
+	pub blank: bool, // an empty line comes right before the token
+}
+#[derive(Clone, Debug)]
+pub struct Comment {
+	pub text: String,
+	pub blank: bool, // an empty line comes right before the comment
+}
+
+// A syntax tree keeping every token of the source, so that it can be written back out
+// without losing anything but the layout. `List`s are of items each but the last followed by a `,`.
+pub type List<T> = Vec<(T, Option<Leaf>)>;
+
+pub struct Cst {
+	pub items: Vec<Item>, // everything before the final `.`
+	pub end: Leaf,
+	pub eof: Leaf, // holds the comments after the program
+}
+
+pub enum Item {
+	Import(Leaf, Leaf, Leaf), // `import`, the module's name or path, `;`
+	Export(Leaf, List<Leaf>, Leaf),
+	Const(Leaf, List<(Leaf, Leaf, Expr)>, Leaf), // name, `=`, value
+	Type(Leaf, List<Record>, Leaf),
+	Var(Leaf, List<(Leaf, Option<(Leaf, Leaf)>)>, Leaf), // name, and optionally `:` and a type
+	Procedure(Leaf, Leaf, Leaf, Vec<Item>, Leaf), // `procedure`, name, `;`, block, `;`
+	Statement(Box<Stmt>),
+}
+
+pub struct Record {
+	pub name: Leaf,
+	pub equals: Leaf,
+	pub record: Leaf,
+	pub fields: List<Leaf>,
+	pub end: Leaf,
+}
+
+pub enum Stmt {
+	Empty,
+	Scope(Leaf, Vec<(Stmt, Option<Leaf>)>, Leaf), // statements, each maybe followed by a `;`
+	Write(Leaf, List<Expr>), // `!`, `write` or `writeln`, and what they print
+	PrintVar(Leaf, Expr),
+	If(Leaf, Expr, Leaf, Box<Stmt>),
+	While(Leaf, Expr, Leaf, Box<Stmt>),
+	Call(Leaf, Expr, Option<(Leaf, List<Expr>, Leaf)>),
+	Case(Leaf, Expr, Leaf, Vec<Arm>, Leaf),
+	Assign(Expr, Leaf, Expr),
+	Expression(Expr),
+}
+
+pub struct Arm {
+	pub labels: List<Expr>, // none for the `else` arm
+	pub head: Leaf, // the `:` after the labels, or the `else`
+	pub body: Stmt,
+	pub semicolon: Option<Leaf>,
+}
+
+pub enum Expr {
+	Leaf(Leaf), // a name, number, string or boolean
+	Name(Leaf, Leaf, Leaf), // `a.b`, a record field or a module's member
+	Unary(Leaf, Box<Expr>),
+	Binary(Box<Expr>, Leaf, Box<Expr>),
+	Group(Leaf, Box<Expr>, Leaf),
+	Call(Box<Expr>, Leaf, List<Expr>, Leaf),
+}
+
+// Attaches the COMMENT tokens of a scanned source to the tokens around them
+pub fn leaves(tokens: Vec<Token>) -> Vec<Leaf> {
+	let mut leaves: Vec<Leaf> = Vec::new();
+	let mut comments = Vec::new();
+	let mut last_line = None; // where the previous token or comment ended
+	for token in tokens {
+		// strings may span lines, and carry the line they end on
+		let first_line = token.line - token.text().matches('\n').count();
+		let blank = last_line.is_some_and(|l| first_line > l + 1);
+		last_line = Some(token.line);
+		if token.kind != TokenType::COMMENT {
+			leaves.push(Leaf { token, comments: std::mem::take(&mut comments), trailing: None, blank });
+			continue
+		}
+		let text = token.text().trim_end().to_string();
+		match leaves.last_mut() {
+			Some(prev) if comments.is_empty() && prev.trailing.is_none() && prev.token.line == token.line => prev.trailing = Some(text),
+			_ => comments.push(Comment { text, blank }),
+		}
+	}
+	leaves
+}
+
+// Parses a program, from tokens scanned with their comments.
+// Only the syntax is checked: names are not resolved, and imports are not loaded.
+pub fn parse(tokens: Vec<Token>) -> Result<Cst, ParseError> {
+	Reader { leaves: leaves(tokens), current: 0 }.program()
+}
+
+struct Reader {
+	leaves: Vec<Leaf>,
+	current: usize,
+}
+impl Reader {
+	fn peek(&self) -> &Leaf { &self.leaves[self.current] }
+	fn check(&self, kind: TokenType) -> bool { self.peek().token.kind == kind }
+	fn next(&mut self) -> Leaf {
+		let leaf = self.peek().clone();
+		if leaf.token.kind != TokenType::EOF { self.current += 1; }
+		leaf
+	}
+	fn matches(&mut self, kinds: &[TokenType]) -> Option<Leaf> {
+		if kinds.contains(&self.peek().token.kind) { Some(self.next()) } else { None }
+	}
+	fn expect(&mut self, kind: TokenType, message: &str) -> Result<Leaf, ParseError> {
+		self.matches(&[kind]).ok_or_else(|| self.error(message))
+	}
+	fn error(&self, message: &str) -> ParseError {
+		error(self.peek().token.clone(), message.to_string());
+		ParseError
+	}
+	// the same rules as the parser's: a `.` between two identifiers accesses a member, any other one ends the program
+	fn is_field_access(&self) -> bool {
+		use TokenType::*;
+		self.check(DOT)
+			&& self.current > 0 && self.leaves[self.current-1].token.kind == IDENTIFIER
+			&& self.leaves.get(self.current+1).is_some_and(|l| l.token.kind == IDENTIFIER)
+	}
+	fn is_at_end(&self) -> bool {
+		match self.peek().token.kind {
+			TokenType::EOF => true,
+			TokenType::DOT => !self.is_field_access(),
+			_ => false,
+		}
+	}
+	fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, ParseError>) -> Result<List<T>, ParseError> {
+		let mut items = Vec::new();
+		loop {
+			let value = item(self)?;
+			let comma = self.matches(&[TokenType::COMMA]);
+			let last = comma.is_none();
+			items.push((value, comma));
+			if last { return Ok(items) }
+		}
+	}
+
+	fn program(mut self) -> Result<Cst, ParseError> {
+		use TokenType::*;
+		let mut items = Vec::new();
+		while let Some(import) = self.matches(&[IMPORT]) {
+			let name = self.matches(&[STRING, IDENTIFIER]).ok_or_else(|| self.error("Expected module name or path after IMPORT"))?;
+			let semicolon = self.expect(SEMICOLON, "Expected `;` after import")?;
+			items.push(Item::Import(import, name, semicolon));
+		}
+		if let Some(export) = self.matches(&[EXPORT]) {
+			let names = self.list(|p| p.expect(IDENTIFIER, "Expected exported name"))?;
+			let semicolon = self.expect(SEMICOLON, "Expected `;` after export list")?;
+			items.push(Item::Export(export, names, semicolon));
+		}
+		while !self.is_at_end() {
+			let start = self.current;
+			self.block(&mut items)?;
+			if self.current == start { return Err(self.error("Expected `.` at the end of the program")) }
+		}
+		let end = self.expect(DOT, "Expected `.` at the end of the program")?;
+		if !self.check(EOF) { return Err(self.error("Expected nothing after the program's final `.`")) }
+		Ok(Cst { items, end, eof: self.next() })
+	}
+
+	fn block(&mut self, items: &mut Vec<Item>) -> Result<(), ParseError> {
+		use TokenType::*;
+		if let Some(keyword) = self.matches(&[CONST]) {
+			let consts = self.list(|p| {
+				let name = p.expect(IDENTIFIER, "Expected const name")?;
+				let equals = p.expect(EQU, &format!("Expected `=` after const name: {}", name.token.lexeme))?;
+				Ok((name, equals, p.expression()?))
+			})?;
+			let semicolon = self.expect(SEMICOLON, "Expected `;` after const declaration")?;
+			items.push(Item::Const(keyword, consts, semicolon));
+		}
+		if let Some(keyword) = self.matches(&[TYPE]) {
+			let types = self.list(|p| {
+				let name = p.expect(IDENTIFIER, "Expected type name")?;
+				let equals = p.expect(EQU, &format!("Expected `=` after type name: {}", name.token.lexeme))?;
+				let record = p.expect(RECORD, "Expected RECORD token")?;
+				let fields = p.list(|p| p.expect(IDENTIFIER, "Expected field name"))?;
+				let end = p.expect(END, "Expected END token after record fields")?;
+				Ok(Record { name, equals, record, fields, end })
+			})?;
+			let semicolon = self.expect(SEMICOLON, "Expected `;` after type declaration")?;
+			items.push(Item::Type(keyword, types, semicolon));
+		}
+		if let Some(keyword) = self.matches(&[VAR]) {
+			let vars = self.list(|p| {
+				let name = p.expect(IDENTIFIER, "Expected var name")?;
+				let kind = match p.matches(&[COLON]) {
+					Some(colon) => Some((colon, p.expect(IDENTIFIER, "Expected type name after `:`")?)),
+					None => None,
+				};
+				Ok((name, kind))
+			})?;
+			let semicolon = self.expect(SEMICOLON, "Expected `;` after var declaration")?;
+			items.push(Item::Var(keyword, vars, semicolon));
+		}
+		while let Some(keyword) = self.matches(&[PROCEDURE]) {
+			let name = self.expect(IDENTIFIER, "Expected procedure identifier")?;
+			let semicolon = self.expect(SEMICOLON, "Expected `;` after procedure identifier")?;
+			let mut body = Vec::new();
+			self.block(&mut body)?;
+			let end = self.expect(SEMICOLON, "Expected `;` after procedure block")?;
+			items.push(Item::Procedure(keyword, name, semicolon, body, end));
+		}
+		items.push(Item::Statement(Box::new(self.statement()?)));
+		Ok(())
+	}
+
+	fn statement(&mut self) -> Result<Stmt, ParseError> {
+		use TokenType::*;
+		let Some(keyword) = self.matches(&[BEGIN, BANG, WRITE, WRITELN, QMARK, IF, CALL, WHILE, CASE]) else {
+			if self.check(IMPORT) || self.check(EXPORT) {
+				return Err(self.error("Imports and exports must come at the start of the file"))
+			}
+			if self.check(SEMICOLON) || self.check(END) || self.is_at_end() { return Ok(Stmt::Empty) }
+			let target = self.expression()?;
+			return match self.matches(&[COLON_EQU]) {
+				Some(assign) => Ok(Stmt::Assign(target, assign, self.condition()?)),
+				None => Ok(Stmt::Expression(target)),
+			}
+		};
+		match keyword.token.kind {
+			BEGIN => {
+				let mut statements = Vec::new();
+				loop {
+					let statement = self.statement()?;
+					let Some(semicolon) = self.matches(&[SEMICOLON]) else {
+						statements.push((statement, None));
+						let end = self.expect(END, "Expected END token")?;
+						return Ok(Stmt::Scope(keyword, statements, end))
+					};
+					statements.push((statement, Some(semicolon)));
+					if let Some(end) = self.matches(&[END]) { return Ok(Stmt::Scope(keyword, statements, end)) }
+				}
+			},
+			WRITELN if self.check(SEMICOLON) || self.check(END) || self.is_at_end() => Ok(Stmt::Write(keyword, Vec::new())),
+			BANG | WRITE | WRITELN => {
+				let args = self.list(|p| match p.matches(&[STRING]) {
+					Some(string) => Ok(Expr::Leaf(string)),
+					None => p.expression(),
+				})?;
+				Ok(Stmt::Write(keyword, args))
+			},
+			QMARK => {
+				let name = self.expect(IDENTIFIER, "Expected identifier for `?` expression")?;
+				Ok(Stmt::PrintVar(keyword, self.name(name)))
+			},
+			IF | WHILE => {
+				let condition = self.condition()?;
+				let (word, message) = if keyword.token.kind == IF { (THEN, "Expected THEN token after IF condition") } else { (DO, "Expected DO token after WHILE condition") };
+				let word = self.expect(word, message)?;
+				let body = Box::new(self.statement()?);
+				if keyword.token.kind == IF { Ok(Stmt::If(keyword, condition, word, body)) } else { Ok(Stmt::While(keyword, condition, word, body)) }
+			},
+			CALL => {
+				let name = self.expect(IDENTIFIER, "Expected procedure identifier for CALL expression")?;
+				let name = self.name(name);
+				let args = match self.matches(&[LEFT_PAREN]) {
+					Some(open) => {
+						let (args, close) = self.arguments()?;
+						Some((open, args, close))
+					},
+					None => None,
+				};
+				Ok(Stmt::Call(keyword, name, args))
+			},
+			_ => self.case(keyword),
+		}
+	}
+	fn case(&mut self, keyword: Leaf) -> Result<Stmt, ParseError> {
+		use TokenType::*;
+		let subject = self.expression()?;
+		let of = self.expect(OF, "Expected OF token after CASE expression")?;
+		let mut arms = Vec::new();
+		loop {
+			if let Some(end) = self.matches(&[END]) { return Ok(Stmt::Case(keyword, subject, of, arms, end)) }
+			if let Some(head) = self.matches(&[ELSE]) {
+				let body = self.statement()?;
+				arms.push(Arm { labels: Vec::new(), head, body, semicolon: self.matches(&[SEMICOLON]) });
+				let end = self.expect(END, "Expected END token after ELSE arm")?;
+				return Ok(Stmt::Case(keyword, subject, of, arms, end))
+			}
+			let labels = self.list(|p| {
+				let minus = p.matches(&[MINUS]);
+				if let Some(number) = p.matches(&[NUMBER]) {
+					return Ok(match minus {
+						Some(minus) => Expr::Unary(minus, Box::new(Expr::Leaf(number))),
+						None => Expr::Leaf(number),
+					})
+				}
+				match (minus, p.matches(&[IDENTIFIER])) {
+					(None, Some(name)) => Ok(p.name(name)),
+					_ => Err(p.error("Expected a number or const name as case label")),
+				}
+			})?;
+			let head = self.expect(COLON, "Expected `:` after case labels")?;
+			let body = self.statement()?;
+			let semicolon = self.matches(&[SEMICOLON]);
+			let last = semicolon.is_none() && !self.check(ELSE);
+			arms.push(Arm { labels, head, body, semicolon });
+			if last {
+				let end = self.expect(END, "Expected END token")?;
+				return Ok(Stmt::Case(keyword, subject, of, arms, end))
+			}
+		}
+	}
+
+	// a name, qualified by a module or picking a record's field if followed by `.member`
+	fn name(&mut self, name: Leaf) -> Expr {
+		if !self.is_field_access() { return Expr::Leaf(name) }
+		let dot = self.next();
+		Expr::Name(name, dot, self.next())
+	}
+	fn condition(&mut self) -> Result<Expr, ParseError> {
+		use TokenType::*;
+		if let Some(odd) = self.matches(&[ODD]) {
+			return Ok(Expr::Unary(odd, Box::new(self.expression()?)))
+		}
+		let left = self.expression()?;
+		match self.matches(&[BANG_EQU, EQU_EQU, LESS, LESS_EQU, MORE, MORE_EQU]) {
+			Some(operator) => Ok(Expr::Binary(Box::new(left), operator, Box::new(self.expression()?))),
+			None => Ok(left),
+		}
+	}
+	// left-associative operators of one precedence level, between operands of the next one
+	fn binary(&mut self, operators: &[TokenType], operand: fn(&mut Self) -> Result<Expr, ParseError>) -> Result<Expr, ParseError> {
+		let mut expr = operand(self)?;
+		while let Some(operator) = self.matches(operators) {
+			expr = Expr::Binary(Box::new(expr), operator, Box::new(operand(self)?));
+		}
+		Ok(expr)
+	}
+	fn expression(&mut self) -> Result<Expr, ParseError> {
+		self.binary(&[TokenType::PIPE], |p| p.binary(&[TokenType::CARET], |p| p.binary(&[TokenType::AMPERSAND], Self::shift)))
+	}
+	fn shift(&mut self) -> Result<Expr, ParseError> {
+		use TokenType::*;
+		self.binary(&[LESS_LESS, MORE_MORE], Self::sum)
+	}
+	fn sum(&mut self) -> Result<Expr, ParseError> {
+		use TokenType::*;
+		let first = |p: &mut Self| match p.matches(&[MINUS, PLUS]) {
+			Some(sign) => Ok(Expr::Unary(sign, Box::new(p.factor()?))),
+			None => p.factor(),
+		};
+		let mut expr = first(self)?;
+		while let Some(operator) = self.matches(&[MINUS, PLUS]) {
+			expr = Expr::Binary(Box::new(expr), operator, Box::new(self.factor()?));
+		}
+		Ok(expr)
+	}
+	fn factor(&mut self) -> Result<Expr, ParseError> {
+		use TokenType::*;
+		self.binary(&[STAR, SLASH, PERCENT, MOD], Self::unary)
+	}
+	fn unary(&mut self) -> Result<Expr, ParseError> {
+		match self.matches(&[TokenType::TILDE]) {
+			Some(operator) => Ok(Expr::Unary(operator, Box::new(self.unary()?))),
+			None => self.power(),
+		}
+	}
+	fn power(&mut self) -> Result<Expr, ParseError> {
+		let base = self.primary()?;
+		match self.matches(&[TokenType::STAR_STAR]) {
+			Some(operator) => Ok(Expr::Binary(Box::new(base), operator, Box::new(self.power()?))),
+			None => Ok(base),
+		}
+	}
+	// the arguments following an opening parenthesis, and the closing one
+	fn arguments(&mut self) -> Result<(List<Expr>, Leaf), ParseError> {
+		use TokenType::*;
+		let args = if self.check(RIGHT_PAREN) { Vec::new() } else { self.list(Self::expression)? };
+		let close = self.expect(RIGHT_PAREN, "Missing ')' after arguments")?;
+		Ok((args, close))
+	}
+	fn primary(&mut self) -> Result<Expr, ParseError> {
+		use TokenType::*;
+		if let Some(literal) = self.matches(&[NUMBER, TRUE, FALSE]) {
+			return Ok(Expr::Leaf(literal))
+		}
+		if let Some(name) = self.matches(&[IDENTIFIER]) {
+			let name = self.name(name);
+			return match self.matches(&[LEFT_PAREN]) {
+				Some(open) => {
+					let (args, close) = self.arguments()?;
+					Ok(Expr::Call(Box::new(name), open, args, close))
+				},
+				None => Ok(name),
+			}
+		}
+		if let Some(open) = self.matches(&[LEFT_PAREN]) {
+			let inner = self.expression()?;
+			let close = self.expect(RIGHT_PAREN, "Missing ')' after expression")?;
+			return Ok(Expr::Group(open, Box::new(inner), close))
+		}
+		Err(self.error("Expected an expression"))
+	}
+}
+
+// Writes a program back out laid out the canonical way: one declaration or statement per line,
+// indented with tabs by how deeply they are nested, and single spaces around binary operators,
+// after commas and between words. Comments stay where they were, as do single empty lines
+// between declarations and statements. `;`s are put between statements and `case` arms, and
+// dropped after the last of them, as well as around empty statements.
+// Formatting a program twice gives the same result as formatting it once.
+pub fn print(cst: &Cst) -> String {
+	let mut w = Writer { out: String::new(), depth: 0, at_line_start: true, space: false, opened: true, continued: false };
+	items(&mut w, &cst.items);
+	// the final `.` follows the main statement, unless there is none
+	if !matches!(cst.items.last(), Some(Item::Statement(s)) if !matches!(**s, Stmt::Empty)) { w.break_line(); }
+	w.leaf(&cst.end);
+	w.comments(&cst.eof);
+	w.break_line();
+	w.out
+}
+
+struct Writer {
+	out: String,
+	depth: usize,
+	at_line_start: bool,
+	space: bool, // a space goes before the next token, unless it starts a line
+	opened: bool, // the last line opened a block, so no empty line should follow it
+	continued: bool, // a comment broke the line mid-statement, so the next one is indented a level deeper
+}
+impl Writer {
+	fn text(&mut self, text: &str) {
+		if self.at_line_start { self.out += &"\t".repeat(self.depth + self.continued as usize); }
+		else if self.space { self.out.push(' '); }
+		self.out += text;
+		self.at_line_start = false;
+		self.space = false;
+		self.opened = false;
+	}
+	fn space(&mut self) { self.space = true; }
+	fn break_line(&mut self) {
+		if !self.at_line_start { self.out.push('\n'); }
+		self.at_line_start = true;
+		self.space = false;
+		self.continued = false;
+	}
+	fn blank_line(&mut self) {
+		self.break_line();
+		if !self.opened && !self.out.is_empty() && !self.out.ends_with("\n\n") { self.out.push('\n'); }
+	}
+	// a line opening a block, whose contents are indented one level deeper
+	fn open(&mut self) {
+		self.break_line();
+		self.depth += 1;
+		self.opened = true;
+	}
+	fn close(&mut self) {
+		self.break_line();
+		self.depth -= 1;
+	}
+
+	fn comments(&mut self, leaf: &Leaf) {
+		for comment in &leaf.comments {
+			// comments inside a statement are indented like the rest of it
+			let continued = self.continued || !self.at_line_start;
+			if comment.blank { self.blank_line(); } else { self.break_line(); }
+			self.continued = continued;
+			self.text(&comment.text);
+			self.break_line();
+			self.continued = continued;
+		}
+	}
+	// a leaf's token, with the comment trailing it
+	fn token(&mut self, leaf: &Leaf) {
+		// empty lines are kept before declarations and statements, which are what starts lines
+		if leaf.blank && self.at_line_start && leaf.token.kind != TokenType::END { self.blank_line(); }
+		self.text(&leaf.token.text());
+		self.trailing(leaf);
+	}
+	fn trailing(&mut self, leaf: &Leaf) {
+		let Some(comment) = &leaf.trailing else { return };
+		let continued = self.continued || !self.at_line_start;
+		if self.at_line_start { self.text(comment); }
+		else { self.out += &format!(" {comment}"); }
+		self.break_line();
+		// whatever comes next breaks the line again if it starts a statement of its own
+		self.continued = continued;
+	}
+	fn leaf(&mut self, leaf: &Leaf) {
+		self.comments(leaf);
+		self.token(leaf);
+	}
+	// a leaf left out of the output, keeping its comments
+	fn skip(&mut self, leaf: &Leaf) {
+		self.comments(leaf);
+		self.trailing(leaf);
+	}
+	fn list<T>(&mut self, items: &List<T>, mut item: impl FnMut(&mut Self, &T)) {
+		for (value, comma) in items {
+			item(self, value);
+			if let Some(comma) = comma {
+				self.leaf(comma);
+				self.space();
+			}
+		}
+	}
+}
+
+fn items(w: &mut Writer, items: &[Item]) {
+	for item in items {
+		if matches!(item, Item::Statement(s) if matches!(**s, Stmt::Empty)) { continue }
+		w.break_line();
+		match item {
+			Item::Import(keyword, name, semicolon) => {
+				w.leaf(keyword);
+				w.space();
+				w.leaf(name);
+				w.leaf(semicolon);
+			},
+			Item::Export(keyword, names, semicolon) => {
+				w.leaf(keyword);
+				w.space();
+				w.list(names, |w, name| w.leaf(name));
+				w.leaf(semicolon);
+			},
+			Item::Const(keyword, consts, semicolon) => {
+				w.leaf(keyword);
+				w.space();
+				w.list(consts, |w, (name, equals, value)| {
+					w.leaf(name);
+					w.space();
+					w.leaf(equals);
+					w.space();
+					expression(w, value);
+				});
+				w.leaf(semicolon);
+			},
+			Item::Type(keyword, types, semicolon) => {
+				w.leaf(keyword);
+				w.space();
+				w.list(types, |w, r| {
+					for leaf in [&r.name, &r.equals, &r.record] {
+						w.leaf(leaf);
+						w.space();
+					}
+					w.list(&r.fields, |w, field| w.leaf(field));
+					w.space();
+					w.leaf(&r.end);
+				});
+				w.leaf(semicolon);
+			},
+			Item::Var(keyword, vars, semicolon) => {
+				w.leaf(keyword);
+				w.space();
+				w.list(vars, |w, (name, kind)| {
+					w.leaf(name);
+					if let Some((colon, kind)) = kind {
+						w.leaf(colon);
+						w.space();
+						w.leaf(kind);
+					}
+				});
+				w.leaf(semicolon);
+			},
+			Item::Procedure(keyword, name, semicolon, body, end) => {
+				w.leaf(keyword);
+				w.space();
+				w.leaf(name);
+				w.leaf(semicolon);
+				w.open();
+				self::items(w, body);
+				// the `;` closing the procedure follows its statement, or stands in for it
+				if matches!(body.last(), Some(Item::Statement(s)) if matches!(**s, Stmt::Empty)) { w.break_line(); }
+				w.leaf(end);
+				w.close();
+			},
+			Item::Statement(s) => statement(w, s),
+		}
+	}
+}
+
+fn statement(w: &mut Writer, s: &Stmt) {
+	match s {
+		Stmt::Empty => {},
+		Stmt::Scope(begin, statements, end) => {
+			w.leaf(begin);
+			let last = statements.iter().rposition(|(s, _)| !matches!(s, Stmt::Empty));
+			// nothing but empty statements, and no comments among them, is just `begin end`
+			let commented = |leaf: &Leaf| !leaf.comments.is_empty() || leaf.trailing.is_some();
+			if last.is_none() && !statements.iter().filter_map(|(_, s)| s.as_ref()).any(commented) && end.comments.is_empty() {
+				w.space();
+				w.token(end);
+				return
+			}
+			w.open();
+			for (i, (s, semicolon)) in statements.iter().enumerate() {
+				let empty = matches!(s, Stmt::Empty);
+				if !empty {
+					w.break_line();
+					statement(w, s);
+				}
+				match semicolon {
+					Some(semicolon) if !empty && Some(i) < last => w.leaf(semicolon),
+					Some(semicolon) => w.skip(semicolon),
+					None => {},
+				}
+			}
+			w.comments(end);
+			w.close();
+			w.token(end);
+		},
+		Stmt::Write(keyword, args) => {
+			w.leaf(keyword);
+			if !args.is_empty() { w.space(); }
+			w.list(args, expression);
+		},
+		Stmt::PrintVar(keyword, name) => {
+			w.leaf(keyword);
+			w.space();
+			expression(w, name);
+		},
+		Stmt::If(keyword, condition, word, body) | Stmt::While(keyword, condition, word, body) => {
+			w.leaf(keyword);
+			w.space();
+			expression(w, condition);
+			w.space();
+			w.leaf(word);
+			body_of(w, body);
+		},
+		Stmt::Call(keyword, name, args) => {
+			w.leaf(keyword);
+			w.space();
+			expression(w, name);
+			if let Some((open, args, close)) = args {
+				w.leaf(open);
+				w.list(args, expression);
+				w.leaf(close);
+			}
+		},
+		Stmt::Case(keyword, subject, of, arms, end) => {
+			w.leaf(keyword);
+			w.space();
+			expression(w, subject);
+			w.space();
+			w.leaf(of);
+			w.open();
+			for (i, arm) in arms.iter().enumerate() {
+				w.break_line();
+				w.list(&arm.labels, expression);
+				w.leaf(&arm.head);
+				body_of(w, &arm.body);
+				match &arm.semicolon {
+					Some(semicolon) if i + 1 < arms.len() => w.leaf(semicolon),
+					Some(semicolon) => w.skip(semicolon),
+					None if i + 1 < arms.len() => w.text(";"),
+					None => {},
+				}
+			}
+			w.comments(end);
+			w.close();
+			w.token(end);
+		},
+		Stmt::Assign(target, assign, value) => {
+			expression(w, target);
+			w.space();
+			w.leaf(assign);
+			w.space();
+			expression(w, value);
+		},
+		Stmt::Expression(e) => expression(w, e),
+	}
+}
+
+// the statement after a `then`, `do`, `:` or `else`, on the same line
+fn body_of(w: &mut Writer, s: &Stmt) {
+	if !matches!(s, Stmt::Empty) { w.space(); }
+	statement(w, s);
+}
+
+fn expression(w: &mut Writer, e: &Expr) {
+	match e {
+		Expr::Leaf(leaf) => w.leaf(leaf),
+		Expr::Name(name, dot, member) => {
+			w.leaf(name);
+			w.leaf(dot);
+			w.leaf(member);
+		},
+		Expr::Unary(operator, operand) => {
+			w.leaf(operator);
+			if operator.token.kind == TokenType::ODD { w.space(); }
+			expression(w, operand);
+		},
+		Expr::Binary(left, operator, right) => {
+			expression(w, left);
+			w.space();
+			w.leaf(operator);
+			w.space();
+			expression(w, right);
+		},
+		Expr::Group(open, inner, close) => {
+			w.leaf(open);
+			expression(w, inner);
+			w.leaf(close);
+		},
+		Expr::Call(name, open, args, close) => {
+			expression(w, name);
+			w.leaf(open);
+			w.list(args, expression);
+			w.leaf(close);
+		},
+	}
+}
@@ -0,0 +1,25 @@
+use std::io::BufRead;
+
+/// Where a running program's input comes from, read by `std.read()`.
+///
+/// Besides [`Stdin`], any [`BufRead`] is an input, e.g. `"50\n7\n".as_bytes()`.
+pub trait Input {
+	/// The next line, without its line ending; `None` at the end of input.
+	fn next_line(&mut self) -> std::io::Result<Option<String>>;
+}
+
+/// Reads from the process' standard input.
+pub struct Stdin;
+impl Input for Stdin {
+	fn next_line(&mut self) -> std::io::Result<Option<String>> {
+		std::io::stdin().lock().next_line()
+	}
+}
+
+impl<R: BufRead> Input for R {
+	fn next_line(&mut self) -> std::io::Result<Option<String>> {
+		let mut line = String::new();
+		if self.read_line(&mut line)? == 0 { return Ok(None) }
+		Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+	}
+}
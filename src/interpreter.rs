@@ -1,19 +1,24 @@
-use std::{rc::Rc, cell::RefCell};
+use std::{rc::Rc, cell::RefCell, fmt::Display, collections::HashMap};
 
-use crate::{token::{Literal, TokenType}, parser::Stmt, expressions::Expr, error::RuntimeError, environment::Environment};
+use crate::{token::{Token, Literal, TokenType}, symbol::Symbol, parser::Stmt, expressions::Expr, ast::{Ast, ExprId, StmtId}, resolver::Slots, error::RuntimeError, environment::Environment, builtins, modules::Module, stdlib, natives::NativeProcedure, sink::{Sink, Stdout}, input::{Input, Stdin}, limits::{Limits, Meter, CancelHandle}};
 
 #[allow(unused)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum RuntimeValue {
 	Value(i32),
-	Ident(String),
+	Ident(Symbol),
 	Boolean(bool),
+	Str(String),
 }
 #[allow(unused)]
 impl RuntimeValue {
 	fn from_literal(literal: &Literal) -> Self {
 		match literal {
 			Literal::Number(v) => Self::Value(*v),
-			Literal::Identifier(i) => Self::Ident(i.clone()),
+			Literal::Identifier(i) => Self::Ident(*i),
+			Literal::String(s) => Self::Str(s.clone()),
+			Literal::Boolean(b) => Self::Boolean(*b),
+			Literal::Qualified(..) => unreachable!("members of modules are looked up, not evaluated"),
 		}
 	}
 	fn as_value(&self) -> Option<i32> {
@@ -22,9 +27,9 @@ impl RuntimeValue {
 			_ => None,
 		}
 	}
-	fn as_ident(&self) -> Option<String> {
+	fn as_ident(&self) -> Option<Symbol> {
 		match self {
-			Self::Ident(s) => Some(s.clone()),
+			Self::Ident(s) => Some(*s),
 			_ => None,
 		}
 	}
@@ -35,140 +40,391 @@ impl RuntimeValue {
 		}
 	}
 }
+impl Display for RuntimeValue {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Value(v) => f.write_str(&v.to_string()),
+			Self::Boolean(b) => f.write_str(&b.to_string()),
+			Self::Ident(s) => write!(f, "{s}"),
+			Self::Str(s) => f.write_str(s),
+		}
+	}
+}
 
+type Shared = Rc<RefCell<Environment>>;
+
+// What is left to do once the statement at hand is done
+enum Frame {
+	Run(StmtId, Shared),
+	Loop(StmtId, Shared), // tests the condition of a `while`, running its body again if it holds
+	Return, // a procedure body has finished
+}
 
 pub struct Interpreter {
+	host: Rc<RefCell<Environment>>, // names seeded by the host, enclosing the program's globals
 	env: Rc<RefCell<Environment>>,
+	modules: HashMap<Rc<str>, Rc<RefCell<Environment>>>, // imported modules' environments, by file
+	natives: HashMap<Symbol, NativeProcedure>,
+	out: Box<dyn Sink>,
+	meter: Meter,
+	ast: Rc<Ast>, // the tree being run, which procedures refer into
+	slots: Rc<Slots>,
 }
 
+impl Default for Interpreter {
+	fn default() -> Self { Self::new() }
+}
 impl Interpreter {
 	pub fn new() -> Self {
-		Interpreter { env: Rc::new(RefCell::new(Environment::new())), }
+		let host = Rc::new(RefCell::new(Environment::new()));
+		let mut interpreter = Interpreter {
+			env: Rc::new(RefCell::new(Environment::from_parent(host.clone()))),
+			host,
+			modules: HashMap::new(),
+			natives: HashMap::new(),
+			out: Box::new(Stdout),
+			meter: Meter::new(Limits::default()),
+			ast: Rc::default(),
+			slots: Rc::default(),
+		};
+		for n in stdlib::natives(Box::new(Stdin)) { interpreter.register_native(n); }
+		interpreter
+	}
+	// Makes a Rust procedure callable from PL/0. Procedures declared by the program take precedence.
+	pub fn register_native(&mut self, native: NativeProcedure) {
+		self.natives.insert(native.name.as_str().into(), native);
 	}
-	pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), RuntimeError> {
-		for s in statements {
-			self.execute(s, &mut self.env.clone())?
+	// Seeds a global variable before running; a variable the program declares itself shadows it
+	pub fn set_global(&mut self, name: &str, value: i32) -> Result<(), RuntimeError> {
+		let mut host = self.host.borrow_mut();
+		let name = Self::host_token(name);
+		if host.get_var(&name).is_err() {
+			host.declare_var(&name)?;
 		}
+		host.assign_var(&name, Some(RuntimeValue::Value(value)))
+	}
+	// Seeds a global constant before running
+	pub fn define_const(&mut self, name: &str, value: i32) -> Result<(), RuntimeError> {
+		self.host.borrow_mut().declare_const(&Self::host_token(name), value)
+	}
+	// Current value of a global integer variable or constant, None if there is no such
+	// global, it was never assigned, or it holds a boolean
+	pub fn global(&self, name: &str) -> Option<i32> {
+		self.env.borrow().get_var(&Self::host_token(name)).ok()??.as_value()
+	}
+	fn host_token(name: &str) -> Token {
+		let name = Symbol::intern(name);
+		Token::new(TokenType::IDENTIFIER, name, Some(Literal::Identifier(name)), 0, 0, "<host>".into())
+	}
+	pub fn set_limits(&mut self, limits: Limits) {
+		self.meter.set_limits(limits);
+	}
+	// Stops the running program when triggered, e.g. from another thread. This is the handle
+	// of the current limits, so one taken before `set_limits` does not stop the new ones.
+	pub fn cancel_handle(&self) -> CancelHandle {
+		self.meter.limits.cancel.clone()
+	}
+	// Sends the program's output somewhere other than stdout
+	pub fn set_output(&mut self, out: Box<dyn Sink>) {
+		self.out = out;
+	}
+	// Has `std.read()` read from somewhere other than stdin
+	pub fn set_input(&mut self, input: Box<dyn Input>) {
+		for n in stdlib::natives(input) { self.register_native(n); }
+	}
+	fn print(&mut self, text: &str) -> Result<(), RuntimeError> {
+		self.out.write(text).map_err(|e| Self::error(&e.to_string()))
+	}
+	// Runs statements from `ast`, with variable accesses placed by the resolver in `slots`.
+	// Procedures refer to their nodes by id, so every run on one interpreter must use the same tree.
+	pub fn interpret(&mut self, ast: &Rc<Ast>, slots: &Rc<Slots>, statements: &[StmtId]) -> Result<(), RuntimeError> {
+		(self.ast, self.slots) = (ast.clone(), slots.clone());
+		self.execute(statements, &self.env.clone())
+	}
+	// Runs an imported module in its own environment, which its importers then refer to
+	pub fn interpret_module(&mut self, ast: &Rc<Ast>, slots: &Rc<Slots>, module: &Module) -> Result<(), RuntimeError> {
+		(self.ast, self.slots) = (ast.clone(), slots.clone());
+		let env = Rc::new(RefCell::new(Environment::new()));
+		self.execute(&module.statements, &env)?;
+		self.modules.insert(module.file.clone(), env);
 		Ok(())
 	}
 	fn error(msg: &str) -> RuntimeError {
-		RuntimeError { msg: msg.to_string() }
+		RuntimeError::new(msg.to_string())
 	}
 
-	fn evaluate(&mut self, expr: Expr, env: &Environment) -> Result<RuntimeValue, RuntimeError> {
-		match expr {
+	fn evaluate(&self, expr: ExprId, env: &Environment) -> Result<RuntimeValue, RuntimeError> {
+		match &self.ast[expr] {
 			Expr::Literal(l) =>
-				Ok(RuntimeValue::from_literal(&l.literal.clone().unwrap())),
+				Ok(RuntimeValue::from_literal(l.literal.as_ref().unwrap())),
 			Expr::Grouping(e) => self.evaluate(*e, env),
 			Expr::Unary(op, e) => {
 				let v = self.evaluate(*e, env)?.as_value().ok_or(Self::error("not a value"))?;
 				use TokenType::*;
 				use RuntimeValue::*;
 				match op.kind {
-					MINUS => Ok(Value(-v)),
 					ODD => Ok(Boolean(v % 2 == 1)),
-					_ => Err(Self::error(&format!("Invalid unary operator: {}", op.lexeme))),
+					_ => Ok(Value(builtins::unary(op.kind, v).map_err(|e| Self::error(&e))?)),
 				}
 			},
 			Expr::Binary(a, op, b) => {
-				let va = self.evaluate(*a, env)?.as_value().ok_or(Self::error("not a value"))?;
-				let vb = self.evaluate(*b, env)?.as_value().ok_or(Self::error("not a value"))?;
+				let (ra, rb) = (self.evaluate(*a, env)?, self.evaluate(*b, env)?);
+				if let (RuntimeValue::Boolean(ba), RuntimeValue::Boolean(bb)) = (&ra, &rb) {
+					match op.kind {
+						TokenType::EQU_EQU => return Ok(RuntimeValue::Boolean(ba == bb)),
+						TokenType::BANG_EQU => return Ok(RuntimeValue::Boolean(ba != bb)),
+						_ => {},
+					}
+				}
+				let va = ra.as_value().ok_or(Self::error("not a value"))?;
+				let vb = rb.as_value().ok_or(Self::error("not a value"))?;
 				use TokenType::*;
 				use RuntimeValue::*;
 				match op.kind {
-					PLUS  => Ok(Value(va + vb)),
-					MINUS => Ok(Value(va - vb)),
-					STAR  => Ok(Value(va * vb)),
-					SLASH => Ok(Value(va / vb)),
 					EQU_EQU  => Ok(Boolean(va == vb)),
 					BANG_EQU => Ok(Boolean(va != vb)),
 					LESS_EQU => Ok(Boolean(va <= vb)),
 					MORE_EQU => Ok(Boolean(va >= vb)),
 					LESS => Ok(Boolean(va < vb)),
 					MORE => Ok(Boolean(va > vb)),
-					_ => Err(Self::error(&format!("Invalid binary operator: {}", op.lexeme))),
+					_ => Ok(Value(builtins::binary(op.kind, va, vb).map_err(|e| Self::error(&e))?)),
 				}
 			},
 			Expr::Variable(name) => {
-				if let Some(v) = env.get_var(name.clone())? {
-					Ok(RuntimeValue::Value(v))
+				let v = match self.slots.exprs.get(expr) {
+					Some(slot) => env.get_slot(name, *slot)?,
+					None => env.get_var(name)?,
+				};
+				if let Some(v) = v {
+					Ok(v)
 				} else {
 					Err(Self::error(&format!("Use of unitialised variable: {}", name.lexeme)))
 				}
 			},
+			Expr::Get(name, field) => {
+				let v = match self.slots.exprs.get(expr) {
+					Some(slot) => env.get_field_slot(name, field, *slot)?,
+					None => env.get_field(name, field)?,
+				};
+				if let Some(v) = v {
+					Ok(RuntimeValue::Value(v))
+				} else {
+					Err(Self::error(&format!("Use of unitialised field: {}.{}", name.lexeme, field.lexeme)))
+				}
+			},
+			Expr::Call(name, builtin, args) => {
+				let mut values = Vec::new();
+				for a in args {
+					values.push(self.evaluate(*a, env)?.as_value().ok_or(Self::error("not a value"))?);
+				}
+				if let Some(builtin) = builtin {
+					let v = builtin.call(&values).map_err(|e| Self::error(&e))?;
+					return Ok(RuntimeValue::Value(v))
+				}
+				let native = self.natives.get(&name.lexeme)
+					.ok_or(Self::error(&format!("Unknown function: {}", name.lexeme)))?;
+				match native.call(&values)? {
+					Some(v) => Ok(RuntimeValue::Value(v)),
+					None => Err(Self::error(&format!("`{}` returns no value", name.lexeme))),
+				}
+			},
 		}
 	}
-	fn execute(&mut self, s: Stmt, env: &mut Rc<RefCell<Environment>>) -> Result<(), RuntimeError> {
-		match s {
+	// Statements run off an explicit stack of frames rather than by recursion,
+	// so deep PL/0 recursion grows the heap instead of the Rust stack.
+	fn execute(&mut self, statements: &[StmtId], env: &Shared) -> Result<(), RuntimeError> {
+		let ast = self.ast.clone();
+		let mut stack = Vec::new();
+		Self::push_block(&mut stack, statements, env);
+		while let Some(frame) = stack.pop() {
+			let result = match &frame {
+				Frame::Run(s, env) => {
+					self.meter.step()
+						.and_then(|_| self.run(&ast, *s, env, &mut stack))
+						.map_err(|e| e.located(Self::location(ast.stmt_token(*s))))
+				},
+				Frame::Loop(s, env) => {
+					let Stmt::While(condition, body) = &ast[*s] else { unreachable!("loop frames hold `while` statements") };
+					match self.test(*condition, env) {
+						Ok(true) => {
+							stack.push(Frame::Loop(*s, env.clone()));
+							stack.push(Frame::Run(*body, env.clone()));
+							Ok(())
+						},
+						Ok(false) => Ok(()),
+						Err(e) => Err(e.located(Self::location(Some(ast.expr_token(*condition))))),
+					}
+				},
+				Frame::Return => {
+					self.meter.leave_call();
+					Ok(())
+				},
+			};
+			if let Err(e) = result {
+				// calls still in progress are abandoned along with the program
+				for f in stack {
+					if let Frame::Return = f { self.meter.leave_call(); }
+				}
+				return Err(e)
+			}
+		}
+		Ok(())
+	}
+	fn push_block(stack: &mut Vec<Frame>, statements: &[StmtId], env: &Shared) {
+		stack.extend(statements.iter().rev().map(|s| Frame::Run(*s, env.clone())));
+	}
+	fn location(token: Option<&Token>) -> Option<(Rc<str>, usize)> {
+		token.map(|t| (t.file.clone(), t.line))
+	}
+	fn test(&self, condition: ExprId, env: &Shared) -> Result<bool, RuntimeError> {
+		self.evaluate(condition, &env.borrow())?
+			.as_bool().ok_or(Self::error("not a boolean"))
+	}
+	// Executes one statement, pushing whatever it runs next onto `stack`
+	fn run(&mut self, ast: &Ast, s: StmtId, env: &Shared, stack: &mut Vec<Frame>) -> Result<(), RuntimeError> {
+		let slot = self.slots.stmts.get(s).copied();
+		match &ast[s] {
 			Stmt::Print(e) => {
-				let v = self.evaluate(e, &env.borrow())?;
-				if let Some(val) = v.as_value() {
-					println!("> {val}");
-					return Ok(())
+				let v = self.evaluate(*e, &env.borrow())?;
+				if let RuntimeValue::Value(_) | RuntimeValue::Boolean(_) = v {
+					self.print(&format!("> {v}\n"))
 				} else {
 					Err(Self::error("Expected to find value")) // me irl amirite
 				}
 			},
 			Stmt::PrintVar(name) => {
-				let val = env.borrow().get_var(name.clone())?;
-				let val = match val { Some(i) => i.to_string(), None => "unassigned".to_string() };
-				println!("> {}: {val}", name.lexeme);
-				Ok(())
+				let record = match slot {
+					Some(slot) => env.borrow().get_record_slot(name, slot),
+					None => env.borrow().get_record(name),
+				};
+				if let Ok(fields) = record {
+					let fields: Vec<String> = fields.into_iter()
+						.map(|(f, v)| match v { Some(i) => format!("{f}: {i}"), None => format!("{f}: unassigned") })
+						.collect();
+					return self.print(&format!("> {}: {{{}}}\n", name.lexeme, fields.join(", ")))
+				}
+				let val = match slot {
+					Some(slot) => env.borrow().get_slot(name, slot)?,
+					None => env.borrow().get_var(name)?,
+				};
+				let val = match val { Some(v) => v.to_string(), None => "unassigned".to_string() };
+				self.print(&format!("> {}: {val}\n", name.lexeme))
+			},
+			Stmt::Write(args, newline, prefix) => {
+				let mut text = String::new();
+				if *prefix { text.push_str("> "); }
+				for a in args {
+					match self.evaluate(*a, &env.borrow())? {
+						RuntimeValue::Ident(_) => return Err(Self::error("Expected to find value or string")),
+						v => text.push_str(&v.to_string()),
+					}
+				}
+				if *newline { text.push('\n'); }
+				self.print(&text)
 			},
 			Stmt::Const(consts) => {
 				for (name, value) in consts {
-					let v = RuntimeValue::from_literal(&value.literal.unwrap())
-						.as_value().expect("Not a value");
-					env.borrow_mut().declare_const(name, v)?;
+					env.borrow_mut().declare_const(name, *value)?;
 				}
 				Ok(())
 			}
+			Stmt::Type(types) => {
+				for (name, fields) in types {
+					env.borrow_mut().define_type(name, fields)?;
+				}
+				Ok(())
+			},
 			Stmt::Var(names) => {
-				for name in names {
-					env.borrow_mut().declare_var(name)?;
+				for (name, kind) in names {
+					match kind {
+						Some(kind) => env.borrow_mut().declare_typed(name, kind)?,
+						None => env.borrow_mut().declare_var(name)?,
+					}
 				}
 				Ok(())
 			},
 			Stmt::Expression(e) => {
-				match self.evaluate(e, &env.borrow()) {
-					Ok(_) => Ok(()),
-					Err(e) => Err(e)
-				}
+				self.evaluate(*e, &env.borrow())?;
+				Ok(())
 			},
 			Stmt::Scope(statements) => {
-				for s in statements {
-					self.execute(s, env)?;
-				}
+				Self::push_block(stack, statements, env);
 				Ok(())
 			}
 			Stmt::Assign(name, e) => {
-				let val = self.evaluate(e, &env.borrow())?
+				let val = match self.evaluate(*e, &env.borrow())? {
+					v @ (RuntimeValue::Value(_) | RuntimeValue::Boolean(_)) => v,
+					_ => return Err(Self::error("not a value")),
+				};
+				match slot {
+					Some(slot) => env.borrow_mut().assign_slot(name, slot, Some(val)),
+					None => env.borrow_mut().assign_var(name, Some(val)),
+				}
+			},
+			Stmt::Set(name, field, e) => {
+				let val = self.evaluate(*e, &env.borrow())?
 					.as_value().ok_or(Self::error("not a value"))?;
-				env.borrow_mut().assign_var(name, Some(val))?;
-				Ok(())
+				match slot {
+					Some(slot) => env.borrow_mut().assign_field_slot(name, field, slot, Some(val)),
+					None => env.borrow_mut().assign_field(name, field, Some(val)),
+				}
 			},
 			Stmt::If(condition, then_branch) => {
-				if self.evaluate(condition, &env.borrow_mut())?
-					.as_bool().ok_or(Self::error("not a boolean"))? {
-					self.execute(*then_branch, env)?;
+				if self.test(*condition, env)? {
+					stack.push(Frame::Run(*then_branch, env.clone()));
 				}
 				Ok(())
 			},
-			Stmt::While(condition, branch) => {
-				while self.evaluate(condition.clone(), &env.borrow_mut())?
-					.as_bool().ok_or(Self::error("not a boolean"))? {
-					self.execute(*branch.clone(), env)?;
+			Stmt::While(..) => {
+				stack.push(Frame::Loop(s, env.clone()));
+				Ok(())
+			},
+			Stmt::Case(subject, table) => {
+				let v = self.evaluate(*subject, &env.borrow())?
+					.as_value().ok_or(Self::error("not a value"))?;
+				if let Some(arm) = table.select(v) {
+					stack.push(Frame::Run(table.arms[arm], env.clone()));
 				}
 				Ok(())
 			},
-			Stmt::Proc(name, body) => {
-				env.borrow_mut().define_proc(name, *body)?;
+			Stmt::Import(name, file) => {
+				let module = self.modules.get(file)
+					.ok_or(Self::error(&format!("Module not loaded: {file}")))?;
+				env.borrow_mut().import_module(name, module.clone());
 				Ok(())
 			},
-			Stmt::Call(name) => {
-				let proc = env.borrow_mut().get_proc(name)?;
-				let call_env = Environment::from_parent(env.clone());
-				self.execute(Stmt::Scope(proc), &mut Rc::new(RefCell::new(call_env)))
+			Stmt::Proc(name, _) => {
+				env.borrow_mut().define_proc(name, s)?;
+				Ok(())
+			},
+			Stmt::Call(name, args) => {
+				let (proc, depth) = match env.borrow().get_proc(name) {
+					Ok(found) => found,
+					Err(e) => {
+						let Some(native) = self.natives.get(&name.lexeme) else { return Err(e) };
+						let mut values = Vec::new();
+						for a in args {
+							values.push(self.evaluate(*a, &env.borrow())?.as_value().ok_or(Self::error("not a value"))?);
+						}
+						native.call(&values)?;
+						return Ok(())
+					},
+				};
+				if !args.is_empty() {
+					return Err(Self::error(&format!("Procedure {} takes no arguments", name.lexeme)))
+				}
+				let Stmt::Proc(_, body) = &ast[proc] else { unreachable!("procedures are defined by their declarations") };
+				// scoping is lexical: the body sees the environment the procedure was declared in,
+				// which for procedures of other modules is their module's
+				let parent = match name.literal {
+					Some(Literal::Qualified(module, _)) => env.borrow().get_module(module)?,
+					_ => Environment::ancestor(env, depth),
+				};
+				let call_env = Rc::new(RefCell::new(Environment::from_parent(parent)));
+				self.meter.enter_call()?;
+				stack.push(Frame::Return);
+				Self::push_block(stack, body, &call_env);
+				Ok(())
 			},
 		}
 	}
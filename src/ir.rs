@@ -0,0 +1,356 @@
+// A mid-level representation between the tree and anything that runs it: each procedure
+// becomes a graph of basic blocks, joined by explicit jumps and branches, whose instructions
+// define SSA values. Variables only a procedure itself uses are promoted to values, merged
+// by phi nodes where control flow joins; those nested procedures also use stay in memory,
+// as loads and stores, one copy per activation.
+
+use std::{collections::HashMap, fmt::Display, rc::Rc};
+
+use crate::{token::TokenType, symbol::Symbol, builtins::Builtin, printer};
+
+mod lower;
+mod verify;
+mod passes;
+mod eval;
+
+pub use lower::lower;
+pub use verify::verify;
+pub use passes::{optimize, propagate_constants, propagate_copies, eliminate_common_subexpressions, remove_dead_values, prune};
+pub use eval::Interpreter;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FuncId(pub u32);
+
+// A variable kept in memory: the `index`th of `func`'s, in its innermost activation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Var {
+	pub func: FuncId,
+	pub index: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Constant {
+	Int(i32),
+	Bool(bool),
+	Str(Rc<str>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Op {
+	Const(Constant),
+	Undef(Symbol), // the named variable, not assigned yet
+	Copy(Value),
+	Phi(Vec<(BlockId, Value)>), // the value coming from each predecessor
+	Unary(TokenType, Value),
+	Binary(TokenType, Value, Value),
+	Builtin(Builtin, Vec<Value>),
+	Load(Var),
+	Store(Var, Value),
+	Call(FuncId),
+	Print(Value), // `! x`
+	PrintVar(Symbol, Value), // `? x`
+	Write(Vec<Value>, bool, bool), // as `Stmt::Write`: newline, then `> ` prefix
+}
+impl Op {
+	// values the operation reads
+	pub fn operands(&self) -> Vec<Value> {
+		match self {
+			Op::Const(_) | Op::Undef(_) | Op::Load(_) | Op::Call(_) => Vec::new(),
+			Op::Copy(v) | Op::Unary(_, v) | Op::Store(_, v) | Op::Print(v) | Op::PrintVar(_, v) => vec![*v],
+			Op::Phi(incoming) => incoming.iter().map(|(_, v)| *v).collect(),
+			Op::Binary(_, a, b) => vec![*a, *b],
+			Op::Builtin(_, args) | Op::Write(args, _, _) => args.clone(),
+		}
+	}
+	fn operands_mut(&mut self) -> Vec<&mut Value> {
+		match self {
+			Op::Const(_) | Op::Undef(_) | Op::Load(_) | Op::Call(_) => Vec::new(),
+			Op::Copy(v) | Op::Unary(_, v) | Op::Store(_, v) | Op::Print(v) | Op::PrintVar(_, v) => vec![v],
+			Op::Phi(incoming) => incoming.iter_mut().map(|(_, v)| v).collect(),
+			Op::Binary(_, a, b) => vec![a, b],
+			Op::Builtin(_, args) | Op::Write(args, _, _) => args.iter_mut().collect(),
+		}
+	}
+	// whether the operation only computes its value, which is then the same wherever it is computed
+	pub fn is_pure(&self) -> bool {
+		matches!(self, Op::Const(_) | Op::Undef(_) | Op::Copy(_) | Op::Phi(_) | Op::Unary(..) | Op::Binary(..) | Op::Builtin(..))
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Inst {
+	pub op: Op,
+	pub line: usize, // of the source it was lowered from, for runtime errors
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Terminator {
+	Jump(BlockId),
+	Branch(Value, BlockId, BlockId), // to the first block if the value is true
+	Return,
+}
+impl Terminator {
+	pub fn successors(&self) -> Vec<BlockId> {
+		match self {
+			Terminator::Jump(b) => vec![*b],
+			Terminator::Branch(_, t, f) => vec![*t, *f],
+			Terminator::Return => Vec::new(),
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Block {
+	pub insts: Vec<Value>, // phis first
+	pub terminator: Terminator,
+}
+
+// One procedure, or the program's main body. Its entry is the first block.
+#[derive(Clone, Debug)]
+pub struct Function {
+	pub name: Symbol,
+	pub parent: Option<FuncId>, // the procedure it is declared in
+	pub file: Rc<str>,
+	pub vars: Vec<Symbol>, // those kept in memory, by `Var::index`
+	pub insts: Vec<Inst>, // by value; only those listed in a block are live
+	pub blocks: Vec<Block>,
+}
+impl Function {
+	pub fn new(name: Symbol, parent: Option<FuncId>, file: Rc<str>) -> Self {
+		Function { name, parent, file, vars: Vec::new(), insts: Vec::new(), blocks: vec![Block { insts: Vec::new(), terminator: Terminator::Return }] }
+	}
+	pub fn op(&self, v: Value) -> &Op { &self.insts[v.0 as usize].op }
+	pub fn block(&self, b: BlockId) -> &Block { &self.blocks[b.0 as usize] }
+	pub fn block_mut(&mut self, b: BlockId) -> &mut Block { &mut self.blocks[b.0 as usize] }
+	pub fn block_ids(&self) -> impl Iterator<Item = BlockId> {
+		(0..self.blocks.len() as u32).map(BlockId)
+	}
+	pub fn add_block(&mut self) -> BlockId {
+		self.blocks.push(Block { insts: Vec::new(), terminator: Terminator::Return });
+		BlockId(self.blocks.len() as u32 - 1)
+	}
+	// defines a value, leaving it to the caller to place in a block
+	pub fn add_inst(&mut self, op: Op, line: usize) -> Value {
+		self.insts.push(Inst { op, line });
+		Value(self.insts.len() as u32 - 1)
+	}
+	pub fn set_op(&mut self, v: Value, op: Op) { self.insts[v.0 as usize].op = op; }
+
+	pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+		let mut preds = vec![Vec::new(); self.blocks.len()];
+		for b in self.block_ids() {
+			for s in self.block(b).terminator.successors() {
+				if !preds[s.0 as usize].contains(&b) { preds[s.0 as usize].push(b); }
+			}
+		}
+		preds
+	}
+	// blocks reachable from the entry, each after all of its predecessors but those closing a loop
+	pub fn reverse_postorder(&self) -> Vec<BlockId> {
+		let mut order = Vec::new();
+		let mut seen = vec![false; self.blocks.len()];
+		let mut stack = vec![(BlockId(0), 0)];
+		seen[0] = true;
+		while let Some((b, next)) = stack.pop() {
+			let successors = self.block(b).terminator.successors();
+			if let Some(s) = successors.get(next) {
+				stack.push((b, next + 1));
+				if !seen[s.0 as usize] {
+					seen[s.0 as usize] = true;
+					stack.push((*s, 0));
+				}
+			} else {
+				order.push(b);
+			}
+		}
+		order.reverse();
+		order
+	}
+	// immediate dominator of each block; None for the entry and unreachable blocks
+	pub fn dominators(&self) -> Vec<Option<BlockId>> {
+		// Cooper, Harvey and Kennedy's "A Simple, Fast Dominance Algorithm"
+		let order = self.reverse_postorder();
+		let mut position = vec![usize::MAX; self.blocks.len()];
+		for (i, b) in order.iter().enumerate() { position[b.0 as usize] = i; }
+		let preds = self.predecessors();
+		let mut idom: Vec<Option<BlockId>> = vec![None; self.blocks.len()];
+		idom[0] = Some(BlockId(0));
+		let mut changed = true;
+		while changed {
+			changed = false;
+			for b in order.iter().skip(1) {
+				let mut new: Option<BlockId> = None;
+				for p in &preds[b.0 as usize] {
+					if idom[p.0 as usize].is_none() { continue }
+					new = Some(match new {
+						None => *p,
+						Some(mut a) => {
+							let mut b = *p;
+							while a != b {
+								while position[a.0 as usize] > position[b.0 as usize] { a = idom[a.0 as usize].unwrap(); }
+								while position[b.0 as usize] > position[a.0 as usize] { b = idom[b.0 as usize].unwrap(); }
+							}
+							a
+						},
+					});
+				}
+				if new.is_some() && idom[b.0 as usize] != new {
+					idom[b.0 as usize] = new;
+					changed = true;
+				}
+			}
+		}
+		idom[0] = None;
+		idom
+	}
+
+	// makes every instruction and terminator reading `from` read `to` instead
+	pub fn replace_uses(&mut self, from: Value, to: Value) {
+		for inst in &mut self.insts {
+			for v in inst.op.operands_mut() {
+				if *v == from { *v = to; }
+			}
+		}
+		for block in &mut self.blocks {
+			if let Terminator::Branch(v, _, _) = &mut block.terminator {
+				if *v == from { *v = to; }
+			}
+		}
+	}
+	// how many times each live value is read
+	pub fn use_counts(&self) -> HashMap<Value, usize> {
+		let mut counts = HashMap::new();
+		for block in &self.blocks {
+			for v in &block.insts {
+				for operand in self.op(*v).operands() { *counts.entry(operand).or_default() += 1; }
+			}
+			if let Terminator::Branch(v, _, _) = block.terminator { *counts.entry(v).or_default() += 1; }
+		}
+		counts
+	}
+	// drops the blocks no path from the entry reaches, renumbering the rest in order
+	pub fn remove_unreachable(&mut self) -> bool {
+		let mut reachable = self.reverse_postorder();
+		if reachable.len() == self.blocks.len() { return false }
+		reachable.sort();
+		let mut renumbered = vec![None; self.blocks.len()];
+		for (i, b) in reachable.iter().enumerate() { renumbered[b.0 as usize] = Some(BlockId(i as u32)); }
+		let new = |b: BlockId| renumbered[b.0 as usize];
+
+		let blocks = std::mem::take(&mut self.blocks);
+		for (i, mut block) in blocks.into_iter().enumerate() {
+			if renumbered[i].is_none() { continue }
+			block.terminator = match block.terminator {
+				Terminator::Jump(b) => Terminator::Jump(new(b).unwrap()),
+				Terminator::Branch(v, t, f) => Terminator::Branch(v, new(t).unwrap(), new(f).unwrap()),
+				Terminator::Return => Terminator::Return,
+			};
+			for v in &block.insts {
+				if let Op::Phi(incoming) = &mut self.insts[v.0 as usize].op {
+					*incoming = incoming.iter().filter_map(|(b, v)| Some((new(*b)?, *v))).collect();
+				}
+			}
+			self.blocks.push(block);
+		}
+		true
+	}
+}
+
+// A whole program: its main body first, then every procedure, outer ones before those nested in them
+#[derive(Clone, Debug)]
+pub struct Program {
+	pub functions: Vec<Function>,
+}
+impl Program {
+	pub fn function(&self, f: FuncId) -> &Function { &self.functions[f.0 as usize] }
+}
+
+impl Display for Value {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "v{}", self.0) }
+}
+impl Display for BlockId {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result { write!(f, "b{}", self.0) }
+}
+impl Display for Constant {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Constant::Int(v) => write!(f, "{v}"),
+			Constant::Bool(b) => write!(f, "{b}"),
+			Constant::Str(s) => f.write_str(&printer::string(s)),
+		}
+	}
+}
+
+// The textual form, one line per instruction:
+//
+//   procedure count in main
+//   	memory n
+//   b0:
+//   	v0 = const 1
+//   	v1 = phi [b0: v0, b2: v4]
+//   	branch v3, b1, b2
+//
+// Memory variables are named by procedure, as in `load main.n`.
+impl Display for Program {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let list = |values: &[Value]| values.iter().map(Value::to_string).collect::<Vec<_>>().join(", ");
+		let memory = |v: &Var| format!("{}.{}", self.function(v.func).name, self.function(v.func).vars[v.index]);
+		for (i, func) in self.functions.iter().enumerate() {
+			if i > 0 { writeln!(f)?; }
+			match func.parent {
+				Some(parent) => writeln!(f, "procedure {} in {}", func.name, self.function(parent).name)?,
+				None => writeln!(f, "procedure {}", func.name)?,
+			}
+			if !func.vars.is_empty() {
+				writeln!(f, "\tmemory {}", func.vars.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "))?;
+			}
+			for b in func.block_ids() {
+				writeln!(f, "{b}:")?;
+				let block = func.block(b);
+				for v in &block.insts {
+					let text = match func.op(*v) {
+						Op::Const(c) => format!("{v} = const {c}"),
+						Op::Undef(name) => format!("{v} = undef {name}"),
+						Op::Copy(a) => format!("{v} = copy {a}"),
+						Op::Phi(incoming) => {
+							let incoming: Vec<String> = incoming.iter().map(|(b, a)| format!("{b}: {a}")).collect();
+							format!("{v} = phi [{}]", incoming.join(", "))
+						},
+						Op::Unary(op, a) => format!("{v} = {} {a}", operator(*op)),
+						Op::Binary(op, a, b) => format!("{v} = {a} {} {b}", operator(*op)),
+						Op::Builtin(name, args) => format!("{v} = {name}({})", list(args)),
+						Op::Load(var) => format!("{v} = load {}", memory(var)),
+						Op::Store(var, a) => format!("store {}, {a}", memory(var)),
+						Op::Call(callee) => format!("call {}", self.function(*callee).name),
+						Op::Print(a) => format!("print {a}"),
+						Op::PrintVar(name, a) => format!("printvar {name}, {a}"),
+						Op::Write(args, newline, prefix) => format!("write{}{} {}",
+							if *newline { "ln" } else { "" }, if *prefix { " prefixed" } else { "" }, list(args)).trim_end().to_string(),
+					};
+					writeln!(f, "\t{text}")?;
+				}
+				match &block.terminator {
+					Terminator::Jump(to) => writeln!(f, "\tjump {to}")?,
+					Terminator::Branch(v, t, e) => writeln!(f, "\tbranch {v}, {t}, {e}")?,
+					Terminator::Return => writeln!(f, "\treturn")?,
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+fn operator(op: TokenType) -> &'static str {
+	use TokenType::*;
+	match op {
+		PLUS => "+", MINUS => "-", STAR => "*", SLASH => "/", PERCENT => "%", MOD => "mod", STAR_STAR => "**",
+		AMPERSAND => "&", PIPE => "|", CARET => "^", TILDE => "~", LESS_LESS => "<<", MORE_MORE => ">>",
+		EQU_EQU => "==", BANG_EQU => "!=", LESS => "<", LESS_EQU => "<=", MORE => ">", MORE_EQU => ">=",
+		ODD => "odd",
+		_ => "?",
+	}
+}
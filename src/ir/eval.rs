@@ -0,0 +1,214 @@
+use std::{fmt::Display, rc::Rc};
+
+use crate::{token::TokenType, error::RuntimeError, symbol::Symbol, builtins::{self, Builtin}, sink::{Sink, Stdout}, limits::{Limits, Meter}};
+use super::{Program, FuncId, BlockId, Value, Op, Constant, Terminator};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(super) enum Val {
+	Int(i32),
+	Bool(bool),
+	Str(Rc<str>),
+	Undef(Symbol), // the named variable, not assigned yet
+	Void, // what instructions without a value leave
+}
+impl Val {
+	pub(super) fn of(c: &Constant) -> Self {
+		match c {
+			Constant::Int(v) => Val::Int(*v),
+			Constant::Bool(b) => Val::Bool(*b),
+			Constant::Str(s) => Val::Str(s.clone()),
+		}
+	}
+	pub(super) fn constant(&self) -> Option<Constant> {
+		match self {
+			Val::Int(v) => Some(Constant::Int(*v)),
+			Val::Bool(b) => Some(Constant::Bool(*b)),
+			Val::Str(s) => Some(Constant::Str(s.clone())),
+			Val::Undef(_) | Val::Void => None,
+		}
+	}
+	fn int(&self) -> Result<i32, String> {
+		match self {
+			Val::Int(v) => Ok(*v),
+			Val::Undef(name) => Err(uninitialised(*name)),
+			_ => Err("not a value".to_string()),
+		}
+	}
+}
+impl Display for Val {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Val::Int(v) => write!(f, "{v}"),
+			Val::Bool(b) => write!(f, "{b}"),
+			Val::Str(s) => f.write_str(s),
+			Val::Undef(_) | Val::Void => f.write_str("unassigned"),
+		}
+	}
+}
+
+fn uninitialised(name: Symbol) -> String { format!("Use of unitialised variable: {name}") }
+
+// What the pure operations compute, the same way the tree interpreter does
+pub(super) fn unary(op: TokenType, a: &Val) -> Result<Val, String> {
+	let a = a.int()?;
+	match op {
+		TokenType::ODD => Ok(Val::Bool(a % 2 == 1)),
+		_ => builtins::unary(op, a).map(Val::Int),
+	}
+}
+pub(super) fn binary(op: TokenType, a: &Val, b: &Val) -> Result<Val, String> {
+	use TokenType::*;
+	if let (Val::Bool(a), Val::Bool(b)) = (a, b) {
+		match op {
+			EQU_EQU => return Ok(Val::Bool(a == b)),
+			BANG_EQU => return Ok(Val::Bool(a != b)),
+			_ => {},
+		}
+	}
+	let (a, b) = (a.int()?, b.int()?);
+	Ok(match op {
+		EQU_EQU  => Val::Bool(a == b),
+		BANG_EQU => Val::Bool(a != b),
+		LESS_EQU => Val::Bool(a <= b),
+		MORE_EQU => Val::Bool(a >= b),
+		LESS => Val::Bool(a < b),
+		MORE => Val::Bool(a > b),
+		_ => Val::Int(builtins::binary(op, a, b)?),
+	})
+}
+pub(super) fn builtin(builtin: Builtin, args: &[Val]) -> Result<Val, String> {
+	let args = args.iter().map(Val::int).collect::<Result<Vec<_>, _>>()?;
+	builtin.call(&args).map(Val::Int)
+}
+
+// A procedure call in progress
+struct Activation {
+	func: FuncId,
+	block: BlockId,
+	next: usize, // the instruction to run next, or the terminator once past the last
+	values: Vec<Val>,
+}
+
+// Runs a program in the IR directly, to check lowering and optimization against the tree interpreter.
+// Memory variables are kept per procedure, as a stack of activations' copies: as procedures can only
+// be called from where they are visible, a nested procedure's enclosing activation is always the latest.
+pub struct Interpreter {
+	out: Box<dyn Sink>,
+	meter: Meter,
+}
+impl Default for Interpreter {
+	fn default() -> Self { Self::new() }
+}
+impl Interpreter {
+	pub fn new() -> Self {
+		Interpreter { out: Box::new(Stdout), meter: Meter::new(Limits::default()) }
+	}
+	pub fn set_output(&mut self, out: Box<dyn Sink>) {
+		self.out = out;
+	}
+	// Limits apply as to the tree interpreter, counting instructions rather than statements
+	pub fn set_limits(&mut self, limits: Limits) {
+		self.meter.set_limits(limits);
+	}
+
+	pub fn run(&mut self, program: &Program) -> Result<(), RuntimeError> {
+		let mut memory: Vec<Vec<Vec<Val>>> = vec![Vec::new(); program.functions.len()];
+		let mut stack = Vec::new();
+		self.enter(program, FuncId(0), &mut memory, &mut stack);
+		let result = self.execute(program, &mut memory, &mut stack);
+		for _ in 1..stack.len() { self.meter.leave_call(); }
+		result
+	}
+	fn enter(&mut self, program: &Program, func: FuncId, memory: &mut [Vec<Vec<Val>>], stack: &mut Vec<Activation>) {
+		let f = program.function(func);
+		memory[func.0 as usize].push(f.vars.iter().map(|name| Val::Undef(*name)).collect());
+		stack.push(Activation { func, block: BlockId(0), next: 0, values: vec![Val::Void; f.insts.len()] });
+	}
+	fn execute(&mut self, program: &Program, memory: &mut [Vec<Vec<Val>>], stack: &mut Vec<Activation>) -> Result<(), RuntimeError> {
+		while let Some(a) = stack.last_mut() {
+			let func = program.function(a.func);
+			let block = func.block(a.block);
+			let error = |v: Value, msg: String| RuntimeError::new(msg).located(Some((func.file.clone(), func.insts[v.0 as usize].line)));
+			let Some(v) = block.insts.get(a.next).copied() else {
+				match &block.terminator {
+					Terminator::Return => {
+						memory[a.func.0 as usize].pop();
+						stack.pop();
+						if !stack.is_empty() { self.meter.leave_call(); }
+					},
+					Terminator::Jump(to) => Self::jump(a, func, *to),
+					Terminator::Branch(c, t, f) => match &a.values[c.0 as usize] {
+						Val::Bool(true) => Self::jump(a, func, *t),
+						Val::Bool(false) => Self::jump(a, func, *f),
+						Val::Undef(name) => return Err(error(*c, uninitialised(*name))),
+						_ => return Err(error(*c, "not a boolean".to_string())),
+					},
+				}
+				continue
+			};
+			a.next += 1;
+			self.meter.step().map_err(|e| e.located(Some((func.file.clone(), func.insts[v.0 as usize].line))))?;
+			let get = |u: &Value| a.values[u.0 as usize].clone();
+			let defined = |u: &Value| match get(u) { Val::Undef(name) => Err(error(v, uninitialised(name))), val => Ok(val) };
+			let result = match func.op(v) {
+				Op::Const(c) => Val::of(c),
+				Op::Undef(name) => Val::Undef(*name),
+				Op::Copy(u) => get(u),
+				Op::Phi(_) => unreachable!("phis are evaluated on entering their block"),
+				Op::Unary(op, u) => unary(*op, &get(u)).map_err(|e| error(v, e))?,
+				Op::Binary(op, l, r) => binary(*op, &get(l), &get(r)).map_err(|e| error(v, e))?,
+				Op::Builtin(b, args) => builtin(*b, &args.iter().map(get).collect::<Vec<_>>()).map_err(|e| error(v, e))?,
+				Op::Load(var) => memory[var.func.0 as usize].last().expect("the procedure is active")[var.index].clone(),
+				Op::Store(var, u) => {
+					let value = defined(u)?;
+					memory[var.func.0 as usize].last_mut().expect("the procedure is active")[var.index] = value;
+					Val::Void
+				},
+				Op::Call(callee) => {
+					self.meter.enter_call().map_err(|e| e.located(Some((func.file.clone(), func.insts[v.0 as usize].line))))?;
+					self.enter(program, *callee, memory, stack);
+					continue
+				},
+				Op::Print(u) => match defined(u)? {
+					val @ (Val::Int(_) | Val::Bool(_)) => {
+						self.print(&format!("> {val}\n")).map_err(|e| error(v, e))?;
+						Val::Void
+					},
+					_ => return Err(error(v, "Expected to find value".to_string())),
+				},
+				Op::PrintVar(name, u) => {
+					self.print(&format!("> {name}: {}\n", get(u))).map_err(|e| error(v, e))?;
+					Val::Void
+				},
+				Op::Write(args, newline, prefix) => {
+					let mut text = String::new();
+					if *prefix { text.push_str("> "); }
+					for u in args { text += &defined(u)?.to_string(); }
+					if *newline { text.push('\n'); }
+					self.print(&text).map_err(|e| error(v, e))?;
+					Val::Void
+				},
+			};
+			let a = stack.last_mut().expect("the activation ran the instruction");
+			a.values[v.0 as usize] = result;
+		}
+		Ok(())
+	}
+	// moves to `to`, taking the values its phis hold for the block left
+	fn jump(a: &mut Activation, func: &super::Function, to: BlockId) {
+		let from = a.block;
+		let phis: Vec<(Value, Val)> = func.block(to).insts.iter()
+			.map_while(|v| match func.op(*v) { Op::Phi(incoming) => Some((*v, incoming)), _ => None })
+			.map(|(v, incoming)| {
+				let (_, u) = incoming.iter().find(|(b, _)| *b == from).expect("phis have a value for every predecessor");
+				(v, a.values[u.0 as usize].clone())
+			})
+			.collect();
+		a.next = phis.len();
+		for (v, val) in phis { a.values[v.0 as usize] = val; }
+		a.block = to;
+	}
+	fn print(&mut self, text: &str) -> Result<(), String> {
+		self.out.write(text).map_err(|e| e.to_string())
+	}
+}
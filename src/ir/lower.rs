@@ -0,0 +1,420 @@
+use std::{collections::{HashMap, HashSet}, rc::Rc};
+
+use crate::{parser::Stmt, expressions::Expr, ast::{Ast, ExprId, StmtId}, token::{Token, TokenType, Literal}, symbol::Symbol, modules::Module, error};
+use super::{Program, Function, FuncId, BlockId, Value, Var, Op, Constant, Terminator};
+
+#[derive(Clone, Copy)]
+enum Name {
+	Const(i32),
+	Var(FuncId, usize), // the procedure's nth variable
+	Proc(FuncId),
+}
+
+struct Info {
+	name: Symbol,
+	parent: Option<FuncId>,
+	body: Vec<StmtId>,
+	vars: Vec<Symbol>,
+}
+
+// Lowers a program to the IR. What the IR has no form for (records, imported modules and
+// native procedures) is reported as an error, like anything else wrong before running.
+// The host's consts and globals are declared around the program, the globals as `main`'s
+// first variables, starting out with the host's values.
+pub fn lower(ast: &Ast, program: &Module, consts: &[(String, i32)], globals: &[(String, i32)]) -> Program {
+	let hosted: Vec<Symbol> = globals.iter().map(|(name, _)| Symbol::intern(name)).collect();
+	let host = hosted.iter().enumerate().map(|(i, name)| (*name, Name::Var(FuncId(0), i)))
+		.chain(consts.iter().map(|(name, v)| (Symbol::intern(name), Name::Const(*v)))).collect();
+	let main = Info { name: Symbol::intern("main"), parent: None, body: program.statements.clone(), vars: hosted };
+	let initial = globals.iter().map(|(_, v)| *v).collect();
+	let mut lowerer = Lowerer { ast, infos: vec![main], ids: HashMap::new(), scopes: vec![host], initial, escaped: HashSet::new(), memory: Vec::new(), functions: Vec::new() };
+	lowerer.number(FuncId(0));
+	lowerer.escapes(FuncId(0));
+	lowerer.memory = lowerer.infos.iter().enumerate().map(|(f, info)| {
+		let mut next = 0;
+		(0..info.vars.len()).map(|i| lowerer.escaped.contains(&(FuncId(f as u32), i)).then(|| { next += 1; next - 1 })).collect()
+	}).collect();
+	lowerer.functions = (0..lowerer.infos.len()).map(|_| None).collect();
+	lowerer.function(FuncId(0), &program.file);
+	Program { functions: lowerer.functions.into_iter().map(|f| f.expect("every procedure is lowered")).collect() }
+}
+
+struct Lowerer<'a> {
+	ast: &'a Ast,
+	infos: Vec<Info>, // by FuncId
+	ids: HashMap<StmtId, FuncId>, // of each procedure declaration
+	scopes: Vec<HashMap<Symbol, Name>>,
+	initial: Vec<i32>, // values of the host's globals, main's first variables
+	escaped: HashSet<(FuncId, usize)>, // variables used by procedures nested in their own
+	memory: Vec<Vec<Option<usize>>>, // where each procedure's variables are kept in memory, if they are
+	functions: Vec<Option<Function>>,
+}
+impl Lowerer<'_> {
+	// numbers the procedures declared in `f`, and those nested in them
+	fn number(&mut self, f: FuncId) {
+		let body = self.infos[f.0 as usize].body.clone();
+		for s in body {
+			match &self.ast[s] {
+				Stmt::Var(vars) => self.infos[f.0 as usize].vars.extend(vars.iter().map(|(name, _)| name.lexeme)),
+				Stmt::Proc(name, body) => {
+					let id = FuncId(self.infos.len() as u32);
+					self.infos.push(Info { name: name.lexeme, parent: Some(f), body: body.clone(), vars: Vec::new() });
+					self.ids.insert(s, id);
+					self.number(id);
+				},
+				_ => {},
+			}
+		}
+	}
+	fn declare(&mut self, f: FuncId) {
+		let mut names = HashMap::new();
+		let mut index = if f == FuncId(0) { self.initial.len() } else { 0 };
+		for s in &self.infos[f.0 as usize].body {
+			match &self.ast[*s] {
+				Stmt::Const(consts) => {
+					for (name, value) in consts { names.insert(name.lexeme, Name::Const(*value)); }
+				},
+				Stmt::Var(vars) => {
+					for (name, _) in vars {
+						names.insert(name.lexeme, Name::Var(f, index));
+						index += 1;
+					}
+				},
+				Stmt::Proc(name, _) => { names.insert(name.lexeme, Name::Proc(self.ids[s])); },
+				_ => {},
+			}
+		}
+		self.scopes.push(names);
+	}
+	fn lookup(&self, name: &Token) -> Option<Name> {
+		self.scopes.iter().rev().find_map(|s| s.get(&name.lexeme)).copied()
+	}
+
+	// Finds the variables procedures nested in `f` use, which have to stay in memory
+	fn escapes(&mut self, f: FuncId) {
+		self.declare(f);
+		for s in self.infos[f.0 as usize].body.clone() { self.escapes_in(f, s); }
+		self.scopes.pop();
+	}
+	fn escapes_in(&mut self, f: FuncId, s: StmtId) {
+		let ast = self.ast;
+		let mut names = Vec::new();
+		let mut exprs = Vec::new();
+		match &ast[s] {
+			Stmt::Proc(..) => return self.escapes(self.ids[&s]),
+			Stmt::Print(e) | Stmt::Expression(e) => exprs.push(*e),
+			Stmt::PrintVar(name) => names.push(name),
+			Stmt::Write(args, _, _) | Stmt::Call(_, args) => exprs.extend(args),
+			Stmt::Scope(statements) => {
+				for s in statements { self.escapes_in(f, *s); }
+			},
+			Stmt::Assign(name, e) | Stmt::Set(name, _, e) => {
+				names.push(name);
+				exprs.push(*e);
+			},
+			Stmt::If(cond, branch) | Stmt::While(cond, branch) => {
+				exprs.push(*cond);
+				self.escapes_in(f, *branch);
+			},
+			Stmt::Case(subject, table) => {
+				exprs.push(*subject);
+				for arm in &table.arms { self.escapes_in(f, *arm); }
+			},
+			Stmt::Const(_) | Stmt::Type(_) | Stmt::Var(_) | Stmt::Import(..) => {},
+		}
+		while let Some(e) = exprs.pop() {
+			match &ast[e] {
+				Expr::Literal(_) => {},
+				Expr::Grouping(e) | Expr::Unary(_, e) => exprs.push(*e),
+				Expr::Binary(a, _, b) => exprs.extend([*a, *b]),
+				Expr::Variable(name) | Expr::Get(name, _) => names.push(name),
+				Expr::Call(_, _, args) => exprs.extend(args),
+			}
+		}
+		for name in names {
+			if let Some(Name::Var(g, i)) = self.lookup(name) {
+				if g != f { self.escaped.insert((g, i)); }
+			}
+		}
+	}
+
+	fn function(&mut self, f: FuncId, file: &Rc<str>) {
+		self.declare(f);
+		let info = &self.infos[f.0 as usize];
+		let mut func = Function::new(info.name, info.parent, file.clone());
+		func.vars = info.vars.iter().enumerate()
+			.filter(|(i, _)| self.memory[f.0 as usize][*i].is_some())
+			.map(|(_, name)| *name).collect();
+		let mut b = Builder::new(func, info.vars.clone());
+		b.seal(BlockId(0));
+		for (i, name) in info.vars.iter().enumerate() {
+			let initial = self.initial.get(i).filter(|_| f == FuncId(0));
+			match (self.memory[f.0 as usize][i], initial) {
+				(Some(index), Some(v)) => {
+					let v = b.emit(Op::Const(Constant::Int(*v)));
+					b.emit(Op::Store(Var { func: f, index }, v));
+				},
+				(Some(_), None) => {},
+				(None, initial) => {
+					let v = b.emit(initial.map_or(Op::Undef(*name), |v| Op::Const(Constant::Int(*v))));
+					b.write(i, BlockId(0), v);
+				},
+			}
+		}
+		for s in self.infos[f.0 as usize].body.clone() {
+			match &self.ast[s] {
+				Stmt::Proc(..) => self.function(self.ids[&s], file),
+				_ => self.statement(&mut b, f, s),
+			}
+		}
+		self.functions[f.0 as usize] = Some(b.func);
+		self.scopes.pop();
+	}
+
+	fn statement(&mut self, b: &mut Builder, f: FuncId, s: StmtId) {
+		let ast = self.ast;
+		if let Some(token) = ast.stmt_token(s) { b.line = token.line; }
+		match &ast[s] {
+			Stmt::Proc(..) => unreachable!("procedures are only declared at the start of a block"),
+			Stmt::Const(_) | Stmt::Type(_) => {},
+			Stmt::Var(vars) => {
+				for (_, kind) in vars {
+					if let Some(kind) = kind.as_ref().filter(|k| !matches!(&*k.lexeme.as_str(), "integer" | "boolean")) {
+						Self::unsupported(kind, "records");
+					}
+				}
+			},
+			Stmt::Print(e) => {
+				let v = self.expression(b, f, *e);
+				b.emit(Op::Print(v));
+			},
+			Stmt::PrintVar(name) => {
+				let v = self.variable(b, f, name);
+				b.emit(Op::PrintVar(name.lexeme, v));
+			},
+			Stmt::Write(args, newline, prefix) => {
+				let args = args.iter().map(|a| self.expression(b, f, *a)).collect();
+				b.emit(Op::Write(args, *newline, *prefix));
+			},
+			Stmt::Expression(e) => { self.expression(b, f, *e); },
+			Stmt::Scope(statements) => {
+				for s in statements { self.statement(b, f, *s); }
+			},
+			Stmt::Assign(name, e) => {
+				let v = self.expression(b, f, *e);
+				match self.lookup(name) {
+					Some(Name::Var(g, i)) => match self.memory[g.0 as usize][i] {
+						Some(index) => { b.emit(Op::Store(Var { func: g, index }, v)); },
+						None => {
+							let copy = b.emit(Op::Copy(v));
+							b.write(i, b.current, copy);
+						},
+					},
+					Some(_) => error::error(name.clone(), format!("Cannot assign to `{}`", name.lexeme)),
+					None => self.unknown(name),
+				}
+			},
+			Stmt::Set(name, _, _) => Self::unsupported(name, "records"),
+			Stmt::If(cond, branch) => {
+				let cond = self.expression(b, f, *cond);
+				let (then, join) = (b.func.add_block(), b.func.add_block());
+				b.terminate(Terminator::Branch(cond, then, join));
+				b.seal(then);
+				b.current = then;
+				self.statement(b, f, *branch);
+				b.terminate(Terminator::Jump(join));
+				b.seal(join);
+				b.current = join;
+			},
+			Stmt::While(cond, body) => {
+				let header = b.func.add_block();
+				b.terminate(Terminator::Jump(header));
+				b.current = header;
+				let cond = self.expression(b, f, *cond);
+				let (inner, exit) = (b.func.add_block(), b.func.add_block());
+				b.terminate(Terminator::Branch(cond, inner, exit));
+				b.seal(inner);
+				b.current = inner;
+				self.statement(b, f, *body);
+				b.terminate(Terminator::Jump(header));
+				b.seal(header);
+				b.seal(exit);
+				b.current = exit;
+			},
+			Stmt::Case(subject, table) => {
+				// each label is tested in turn, falling through to the `else` arm, if any
+				let subject = self.expression(b, f, *subject);
+				let tests: Vec<BlockId> = table.labels.iter().skip(1).map(|_| b.func.add_block()).collect();
+				let arms: Vec<BlockId> = table.arms.iter().map(|_| b.func.add_block()).collect();
+				let join = b.func.add_block();
+				let otherwise = table.default.map_or(join, |d| arms[d]);
+				for (i, (label, arm)) in table.labels.iter().enumerate() {
+					let label = b.emit(Op::Const(Constant::Int(*label)));
+					let test = b.emit(Op::Binary(TokenType::EQU_EQU, subject, label));
+					let next = tests.get(i).copied().unwrap_or(otherwise);
+					b.terminate(Terminator::Branch(test, arms[*arm], next));
+					if let Some(next) = tests.get(i) {
+						b.seal(*next);
+						b.current = *next;
+					}
+				}
+				if table.labels.is_empty() { b.terminate(Terminator::Jump(otherwise)); }
+				for (i, arm) in table.arms.iter().enumerate() {
+					b.seal(arms[i]);
+					b.current = arms[i];
+					self.statement(b, f, *arm);
+					b.terminate(Terminator::Jump(join));
+				}
+				b.seal(join);
+				b.current = join;
+			},
+			Stmt::Call(name, args) => match self.lookup(name) {
+				Some(Name::Proc(callee)) if args.is_empty() => { b.emit(Op::Call(callee)); },
+				Some(Name::Proc(_)) => error::error(name.clone(), format!("Procedure {} takes no arguments", name.lexeme)),
+				_ => Self::unsupported(name, "native procedures"),
+			},
+			Stmt::Import(name, _) => Self::unsupported(name, "imported modules"),
+		}
+	}
+
+	fn expression(&mut self, b: &mut Builder, f: FuncId, e: ExprId) -> Value {
+		let ast = self.ast;
+		match &ast[e] {
+			Expr::Literal(l) => b.emit(Op::Const(match l.literal.as_ref() {
+				Some(Literal::Number(v)) => Constant::Int(*v),
+				Some(Literal::Boolean(v)) => Constant::Bool(*v),
+				Some(Literal::String(s)) => Constant::Str(s.as_str().into()),
+				Some(Literal::Identifier(_) | Literal::Qualified(..)) | None => unreachable!("literals hold values"),
+			})),
+			Expr::Grouping(e) => self.expression(b, f, *e),
+			Expr::Unary(op, a) => {
+				let a = self.expression(b, f, *a);
+				b.emit(Op::Unary(op.kind, a))
+			},
+			Expr::Binary(a, op, c) => {
+				let a = self.expression(b, f, *a);
+				let c = self.expression(b, f, *c);
+				b.emit(Op::Binary(op.kind, a, c))
+			},
+			Expr::Variable(name) => self.variable(b, f, name),
+			Expr::Get(name, _) => {
+				Self::unsupported(name, "records");
+				b.emit(Op::Undef(name.lexeme))
+			},
+			Expr::Call(name, builtin, args) => {
+				let args = args.iter().map(|a| self.expression(b, f, *a)).collect();
+				match builtin {
+					Some(builtin) => b.emit(Op::Builtin(*builtin, args)),
+					None => {
+						Self::unsupported(name, "native functions");
+						b.emit(Op::Undef(name.lexeme))
+					},
+				}
+			},
+		}
+	}
+	fn variable(&mut self, b: &mut Builder, f: FuncId, name: &Token) -> Value {
+		match self.lookup(name) {
+			Some(Name::Const(v)) => b.emit(Op::Const(Constant::Int(v))),
+			Some(Name::Var(g, i)) => match self.memory[g.0 as usize][i] {
+				Some(index) => b.emit(Op::Load(Var { func: g, index })),
+				None => {
+					debug_assert_eq!(f, g, "variables other procedures use are kept in memory");
+					b.read(i, b.current)
+				},
+			},
+			Some(Name::Proc(_)) => {
+				error::error(name.clone(), format!("`{}` is a procedure, not a value", name.lexeme));
+				b.emit(Op::Undef(name.lexeme))
+			},
+			None => {
+				self.unknown(name);
+				b.emit(Op::Undef(name.lexeme))
+			},
+		}
+	}
+	fn unknown(&self, name: &Token) {
+		if matches!(name.literal, Some(Literal::Qualified(..))) { Self::unsupported(name, "imported modules") }
+		else { error::error(name.clone(), format!("`{}` is not declared", name.lexeme)) }
+	}
+	fn unsupported(at: &Token, what: &str) {
+		error::error(at.clone(), format!("The IR has no form for {what}"));
+	}
+}
+
+// Builds one function's graph, turning variables into SSA values as it goes, following
+// Braun et al.'s "Simple and Efficient Construction of Static Single Assignment Form":
+// a block whose predecessors are not all known yet (a loop header, before its body is
+// lowered) is unsealed, and reading a variable there leaves a phi to be completed once it is sealed.
+struct Builder {
+	func: Function,
+	current: BlockId,
+	line: usize,
+	names: Vec<Symbol>, // of the variables, by index
+	preds: Vec<Vec<BlockId>>,
+	defs: HashMap<(usize, BlockId), Value>, // each variable's value at the end of a block, as far as lowered
+	sealed: HashSet<BlockId>,
+	incomplete: HashMap<BlockId, Vec<(usize, Value)>>, // phis of unsealed blocks, with their variables
+}
+impl Builder {
+	fn new(func: Function, names: Vec<Symbol>) -> Self {
+		Builder { func, current: BlockId(0), line: 0, names, preds: Vec::new(), defs: HashMap::new(), sealed: HashSet::new(), incomplete: HashMap::new() }
+	}
+	fn emit(&mut self, op: Op) -> Value {
+		let v = self.func.add_inst(op, self.line);
+		self.func.block_mut(self.current).insts.push(v);
+		v
+	}
+	fn terminate(&mut self, terminator: Terminator) {
+		for s in terminator.successors() {
+			self.preds.resize(self.func.blocks.len(), Vec::new());
+			self.preds[s.0 as usize].push(self.current);
+		}
+		self.func.block_mut(self.current).terminator = terminator;
+	}
+	fn preds(&self, block: BlockId) -> Vec<BlockId> {
+		self.preds.get(block.0 as usize).cloned().unwrap_or_default()
+	}
+
+	fn write(&mut self, var: usize, block: BlockId, value: Value) {
+		self.defs.insert((var, block), value);
+	}
+	fn read(&mut self, var: usize, block: BlockId) -> Value {
+		if let Some(v) = self.defs.get(&(var, block)) { return *v }
+		let preds = self.preds(block);
+		let value = if !self.sealed.contains(&block) {
+			let phi = self.phi(block);
+			self.incomplete.entry(block).or_default().push((var, phi));
+			phi
+		} else if preds.len() == 1 {
+			self.read(var, preds[0])
+		} else if preds.is_empty() {
+			let undef = self.func.add_inst(Op::Undef(self.names[var]), self.line);
+			self.func.block_mut(block).insts.insert(0, undef);
+			undef
+		} else {
+			// defined before its operands are read, so that loops reading it back find it
+			let phi = self.phi(block);
+			self.write(var, block, phi);
+			self.complete(var, phi, block);
+			phi
+		};
+		self.write(var, block, value);
+		value
+	}
+	fn phi(&mut self, block: BlockId) -> Value {
+		let phi = self.func.add_inst(Op::Phi(Vec::new()), self.line);
+		self.func.block_mut(block).insts.insert(0, phi);
+		phi
+	}
+	fn complete(&mut self, var: usize, phi: Value, block: BlockId) {
+		let incoming = self.preds(block).into_iter().map(|p| (p, self.read(var, p))).collect();
+		self.func.set_op(phi, Op::Phi(incoming));
+	}
+	fn seal(&mut self, block: BlockId) {
+		for (var, phi) in self.incomplete.remove(&block).unwrap_or_default() {
+			self.complete(var, phi, block);
+		}
+		self.sealed.insert(block);
+	}
+}
@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Program, Function, FuncId, BlockId, Value, Op, Terminator, eval::{self, Val}};
+
+// Runs every pass over every procedure until none of them finds anything more to do,
+// then prunes what the program no longer uses
+pub fn optimize(program: &mut Program) {
+	for func in &mut program.functions {
+		loop {
+			let mut changed = propagate_constants(func);
+			changed |= propagate_copies(func);
+			changed |= eliminate_common_subexpressions(func);
+			changed |= remove_dead_values(func);
+			if !changed { break }
+		}
+	}
+	prune(program);
+}
+
+// Drops the procedures no call from the main body reaches any more, and the memory variables
+// no load or store is left to use, renumbering the rest in order
+pub fn prune(program: &mut Program) -> bool {
+	let live = |func: &Function| func.blocks.iter().flat_map(|b| b.insts.clone()).collect::<Vec<_>>();
+	let mut reached = vec![false; program.functions.len()];
+	let mut work = vec![FuncId(0)];
+	while let Some(f) = work.pop() {
+		if std::mem::replace(&mut reached[f.0 as usize], true) { continue }
+		let func = program.function(f);
+		// a procedure can only be called while the one it is declared in is active
+		work.extend(func.parent);
+		work.extend(live(func).into_iter().filter_map(|v| match func.op(v) { Op::Call(callee) => Some(*callee), _ => None }));
+	}
+	let mut used: Vec<Vec<bool>> = program.functions.iter().map(|f| vec![false; f.vars.len()]).collect();
+	for (func, _) in program.functions.iter().zip(&reached).filter(|(_, r)| **r) {
+		for v in live(func) {
+			if let Op::Load(var) | Op::Store(var, _) = func.op(v) { used[var.func.0 as usize][var.index] = true; }
+		}
+	}
+	if reached.iter().chain(used.iter().flatten()).all(|kept| *kept) { return false }
+
+	// new index of each kept item
+	let renumber = |kept: &[bool]| {
+		let mut next = 0;
+		kept.iter().map(|k| k.then(|| { next += 1; next - 1 })).collect::<Vec<Option<usize>>>()
+	};
+	let funcs = renumber(&reached);
+	let vars: Vec<_> = used.iter().map(|u| renumber(u)).collect();
+	let new_func = |f: FuncId| funcs[f.0 as usize].map(|i| FuncId(i as u32));
+	for (f, mut func) in std::mem::take(&mut program.functions).into_iter().enumerate() {
+		if !reached[f] { continue }
+		func.parent = func.parent.and_then(new_func);
+		func.vars = func.vars.iter().zip(&used[f]).filter(|(_, u)| **u).map(|(name, _)| *name).collect();
+		// instructions no block lists any more are left as they are
+		for inst in &mut func.insts {
+			match &mut inst.op {
+				Op::Call(callee) => if let Some(new) = new_func(*callee) { *callee = new },
+				Op::Load(var) | Op::Store(var, _) => if let (Some(f), Some(index)) = (new_func(var.func), vars[var.func.0 as usize][var.index]) {
+					*var = super::Var { func: f, index };
+				},
+				_ => {},
+			}
+		}
+		program.functions.push(func);
+	}
+	true
+}
+
+// Folds operations whose operands are all constants, and turns branches on constants into
+// jumps, dropping the blocks no longer reached. Operations that would fail are left to fail at runtime.
+pub fn propagate_constants(func: &mut Function) -> bool {
+	let mut changed = false;
+	for b in func.reverse_postorder() {
+		for v in func.block(b).insts.clone() {
+			let constant = |u: &Value| match func.op(*u) { Op::Const(c) => Some(Val::of(c)), _ => None };
+			let folded = match func.op(v) {
+				Op::Unary(op, a) => constant(a).and_then(|a| eval::unary(*op, &a).ok()),
+				Op::Binary(op, a, c) => constant(a).zip(constant(c)).and_then(|(a, c)| eval::binary(*op, &a, &c).ok()),
+				Op::Builtin(builtin, args) => args.iter().map(constant).collect::<Option<Vec<_>>>()
+					.and_then(|args| eval::builtin(*builtin, &args).ok()),
+				_ => None,
+			};
+			if let Some(c) = folded.and_then(|v| v.constant()) {
+				func.set_op(v, Op::Const(c));
+				changed = true;
+			}
+		}
+		if let Terminator::Branch(c, t, f) = func.block(b).terminator {
+			if let Op::Const(super::Constant::Bool(taken)) = func.op(c) {
+				let (to, dropped) = if *taken { (t, f) } else { (f, t) };
+				func.block_mut(b).terminator = Terminator::Jump(to);
+				if dropped != to { remove_incoming(func, dropped, b); }
+				changed = true;
+			}
+		}
+	}
+	func.remove_unreachable() || changed
+}
+fn remove_incoming(func: &mut Function, block: BlockId, from: BlockId) {
+	for v in func.block(block).insts.clone() {
+		if let Op::Phi(incoming) = func.op(v) {
+			let incoming = incoming.iter().filter(|(b, _)| *b != from).cloned().collect();
+			func.set_op(v, Op::Phi(incoming));
+		}
+	}
+}
+
+// Makes everything reading a copy read the original instead. A phi whose incoming
+// values are all the same (bar the phi itself, round a loop) is a copy too.
+pub fn propagate_copies(func: &mut Function) -> bool {
+	let mut changed = false;
+	for b in func.block_ids().collect::<Vec<_>>() {
+		for v in func.block(b).insts.clone() {
+			let original = match func.op(v) {
+				Op::Copy(u) => Some(*u),
+				Op::Phi(incoming) => {
+					let mut others = incoming.iter().map(|(_, u)| *u).filter(|u| *u != v);
+					let first = others.next();
+					first.filter(|first| others.all(|u| u == *first))
+				},
+				_ => None,
+			};
+			if let Some(original) = original {
+				func.replace_uses(v, original);
+				func.block_mut(b).insts.retain(|u| *u != v);
+				changed = true;
+			}
+		}
+	}
+	changed
+}
+
+// Reuses the value of an operation already computed in a dominating block (or earlier in the
+// same one) instead of computing it again. Any failure would have happened the first time.
+pub fn eliminate_common_subexpressions(func: &mut Function) -> bool {
+	let idom = func.dominators();
+	let mut children = vec![Vec::new(); func.blocks.len()];
+	for b in func.block_ids() {
+		if let Some(parent) = idom[b.0 as usize] { children[parent.0 as usize].push(b); }
+	}
+	dominated(func, &children, BlockId(0), &mut HashMap::new())
+}
+// `b` and the blocks it dominates, with what is computed in the blocks dominating it `available`
+fn dominated(func: &mut Function, children: &[Vec<BlockId>], b: BlockId, available: &mut HashMap<Op, Value>) -> bool {
+	let mut changed = false;
+	let mut added = Vec::new();
+	for v in func.block(b).insts.clone() {
+		let op = func.op(v).clone();
+		if !op.is_pure() || matches!(op, Op::Phi(_) | Op::Undef(_)) { continue }
+		match available.get(&op) {
+			Some(earlier) => {
+				func.replace_uses(v, *earlier);
+				func.block_mut(b).insts.retain(|u| *u != v);
+				changed = true;
+			},
+			None => {
+				available.insert(op.clone(), v);
+				added.push(op);
+			},
+		}
+	}
+	for c in &children[b.0 as usize] {
+		changed |= dominated(func, children, *c, available);
+	}
+	for op in added { available.remove(&op); }
+	changed
+}
+
+// Drops values nothing reads, as long as computing them could not fail
+pub fn remove_dead_values(func: &mut Function) -> bool {
+	let mut changed = false;
+	loop {
+		let uses = func.use_counts();
+		let dead: HashSet<Value> = func.blocks.iter().flat_map(|b| b.insts.iter().copied())
+			.filter(|v| !uses.contains_key(v) && matches!(func.op(*v), Op::Const(_) | Op::Undef(_) | Op::Copy(_) | Op::Phi(_)))
+			.collect();
+		if dead.is_empty() { return changed }
+		for block in &mut func.blocks { block.insts.retain(|v| !dead.contains(v)); }
+		changed = true;
+	}
+}
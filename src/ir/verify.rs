@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use super::{Program, Function, FuncId, BlockId, Op, Terminator};
+
+// Checks the invariants every pass relies on, returning the first one broken:
+// jumps and calls lead somewhere, each value is defined once, phis come first in their
+// block with one value per predecessor, and every value is defined before it is read,
+// along every path (its definition dominates its uses).
+pub fn verify(program: &Program) -> Result<(), String> {
+	for (i, func) in program.functions.iter().enumerate() {
+		function(program, FuncId(i as u32), func).map_err(|e| format!("in procedure {}: {e}", func.name))?;
+	}
+	Ok(())
+}
+
+fn function(program: &Program, id: FuncId, func: &Function) -> Result<(), String> {
+	let blocks = func.blocks.len() as u32;
+	for b in func.block_ids() {
+		for s in func.block(b).terminator.successors() {
+			if s.0 >= blocks { return Err(format!("{b} jumps to {s}, which does not exist")) }
+		}
+	}
+
+	// where each value is defined: its block and position in it
+	let mut defined = HashMap::new();
+	for b in func.block_ids() {
+		for (i, v) in func.block(b).insts.iter().enumerate() {
+			if v.0 as usize >= func.insts.len() { return Err(format!("{b} lists {v}, which does not exist")) }
+			if defined.insert(*v, (b, i)).is_some() { return Err(format!("{v} is defined more than once")) }
+		}
+	}
+
+	let preds = func.predecessors();
+	let idom = func.dominators();
+	let reachable = |b: BlockId| b.0 == 0 || idom[b.0 as usize].is_some();
+	let dominates = |a: BlockId, mut b: BlockId| loop {
+		if a == b { return true }
+		match idom[b.0 as usize] { Some(up) => b = up, None => return false }
+	};
+	// whether `v` is available at position `at` of block `b`
+	let available = |v: super::Value, b: BlockId, at: usize| match defined.get(&v) {
+		Some((d, i)) if *d == b => *i < at,
+		Some((d, _)) => dominates(*d, b),
+		None => false,
+	};
+
+	for b in func.block_ids() {
+		let block = func.block(b);
+		let mut phis_done = false;
+		for (i, v) in block.insts.iter().enumerate() {
+			let op = func.op(*v);
+			if let Op::Phi(incoming) = op {
+				if phis_done { return Err(format!("{v} is a phi after other instructions of {b}")) }
+				let mut from: Vec<BlockId> = incoming.iter().map(|(p, _)| *p).collect();
+				from.sort();
+				let mut expected = preds[b.0 as usize].clone();
+				expected.sort();
+				if from != expected { return Err(format!("{v} does not have one value for each predecessor of {b}")) }
+				for (p, u) in incoming {
+					if reachable(*p) && !available(*u, *p, usize::MAX) {
+						return Err(format!("{v} takes {u} from {p}, where it is not defined"))
+					}
+				}
+				continue
+			}
+			phis_done = true;
+			if reachable(b) {
+				for u in op.operands() {
+					if !available(u, b, i) { return Err(format!("{v} reads {u} before it is defined")) }
+				}
+			}
+			match op {
+				Op::Load(var) | Op::Store(var, _) => {
+					let mut scope = Some(id);
+					while scope.is_some_and(|s| s != var.func) { scope = program.function(scope.unwrap()).parent; }
+					if scope.is_none() { return Err(format!("{v} uses a variable of a procedure {} is not nested in", func.name)) }
+					if var.index >= program.function(var.func).vars.len() {
+						return Err(format!("{v} uses a variable {} does not have", program.function(var.func).name))
+					}
+				},
+				Op::Call(callee) if callee.0 as usize >= program.functions.len() => {
+					return Err(format!("{v} calls a procedure which does not exist"))
+				},
+				_ => {},
+			}
+		}
+		if let Terminator::Branch(c, _, _) = block.terminator {
+			if reachable(b) && !available(c, b, usize::MAX) { return Err(format!("{b} branches on {c} before it is defined")) }
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,291 @@
+//! A toy PL/0 interpreter.
+//!
+//! The quickest way in is [`run_source`], which takes a program through every stage:
+//!
+//! ```no_run
+//! let output = plz::run_source("var x; begin x := 6 * 7; ? x end.", plz::Options::default());
+//! assert!(output.is_ok());
+//! ```
+//!
+//! Output goes to stdout unless [`Options::output`] says otherwise:
+//!
+//! ```
+//! let buffer = plz::sink::Buffer::new();
+//! let options = plz::Options { output: Box::new(buffer.clone()), ..Default::default() };
+//! plz::run_source("writeln \"hello\".", options).unwrap();
+//! assert_eq!(buffer.contents(), "hello\n");
+//! ```
+//!
+//! Each stage is also usable on its own: [`Scanner`] turns source into tokens,
+//! [`Parser`] turns tokens into [`Stmt`]s and [`Expr`]s, [`modules::Loader`] does both
+//! for a program and everything it imports, [`TypeChecker`] checks the result,
+//! [`Optimizer`] folds what it can ahead of time, [`DeadCode`] drops what never runs, [`Resolver`] points variable accesses
+//! at their storage, and [`Interpreter`] runs it. Alongside, [`ir`] lowers a program to basic blocks of SSA values,
+//! which it can optimize further and run on its own.
+
+pub mod error;
+pub mod symbol;
+pub mod token;
+pub mod scanner;
+pub mod expressions;
+pub mod ast;
+pub mod parser;
+pub mod interpreter;
+pub mod environment;
+pub mod typecheck;
+pub mod resolver;
+pub mod optimizer;
+pub mod callgraph;
+pub mod deadcode;
+pub mod ir;
+pub mod cfg;
+pub mod dump;
+pub mod format;
+pub mod printer;
+pub mod modules;
+pub mod natives;
+pub mod sink;
+pub mod input;
+pub mod limits;
+mod builtins;
+mod stdlib;
+
+use std::{collections::HashMap, path::PathBuf, rc::Rc};
+
+pub use error::{Diagnostic, Diagnostics, Span, ParseError, RuntimeError, RuntimeErrorKind};
+pub use symbol::Symbol;
+pub use scanner::Scanner;
+pub use parser::{Parser, Stmt};
+pub use expressions::Expr;
+pub use builtins::Builtin;
+pub use ast::{Ast, ExprId, StmtId};
+pub use interpreter::{Interpreter, RuntimeValue};
+pub use environment::Environment;
+pub use typecheck::TypeChecker;
+pub use resolver::{Resolver, Slots};
+pub use optimizer::Optimizer;
+pub use deadcode::{DeadCode, Removed};
+pub use natives::NativeProcedure;
+pub use sink::Sink;
+pub use input::Input;
+pub use limits::Limits;
+
+/// How [`run_source`] should run a program.
+pub struct Options {
+	/// Names the program in diagnostics; its relative imports are resolved against its directory.
+	pub file: PathBuf,
+	/// Directories searched by `import name;`, after the program's own directory.
+	pub search_path: Vec<PathBuf>,
+	/// Rust procedures made callable from the program.
+	pub natives: Vec<NativeProcedure>,
+	/// Receives everything the program prints; stdout by default.
+	pub output: Box<dyn Sink>,
+	/// Where `std.read()` reads from; stdin by default.
+	pub input: Box<dyn Input>,
+	/// Global variables set before the program runs. The program can use them
+	/// without declaring them; a `var` of the same name shadows them.
+	pub globals: Vec<(String, i32)>,
+	/// Global constants defined before the program runs, usable in its constant expressions.
+	pub consts: Vec<(String, i32)>,
+	/// How long the program may run; see [`Limits`].
+	pub limits: Limits,
+	/// Whether to run the [`Optimizer`] and [`DeadCode`] before the program; on by default.
+	pub optimize: bool,
+}
+impl Default for Options {
+	fn default() -> Self {
+		Options { file: PathBuf::from("<input>"), search_path: Vec::new(), natives: Vec::new(), output: Box::new(sink::Stdout), input: Box::new(input::Stdin), globals: Vec::new(), consts: Vec::new(), limits: Limits::default(), optimize: true }
+	}
+}
+
+/// A program that ran to completion.
+pub struct Output {
+	interpreter: Interpreter,
+}
+impl Output {
+	/// The interpreter the program ran in, holding its final state.
+	pub fn interpreter(&self) -> &Interpreter { &self.interpreter }
+	pub fn into_interpreter(self) -> Interpreter { self.interpreter }
+	/// Final value of a global integer variable or constant; see [`Interpreter::global`].
+	pub fn global(&self, name: &str) -> Option<i32> { self.interpreter.global(name) }
+}
+
+// Loads and checks a program along with its imports, optimizing them if asked to.
+// The program itself is the last module.
+fn compile(source: &str, options: &Options) -> Result<(Ast, Vec<modules::Module>, Vec<Removed>), Diagnostics> {
+	error::take_diagnostics(); // leftovers from an earlier failed run
+
+	let host: HashMap<Symbol, Option<i32>> = options.globals.iter().map(|(n, _)| (n.as_str().into(), None))
+		.chain(options.consts.iter().map(|(n, v)| (n.as_str().into(), Some(*v))))
+		.collect();
+	let (mut ast, modules) = modules::Loader::new(options.search_path.clone())
+		.with_host_names(host.clone())
+		.load_program(&options.file, source.to_string());
+	if !error::had_error() {
+		for (i, m) in modules.iter().enumerate() {
+			let checker = TypeChecker::new(&ast).with_natives(&options.natives);
+			if i < modules.len() - 1 { checker.exported() } else { checker }.check(&m.statements);
+		}
+		// undeclared names are looked for before the optimizer can take away code using them
+		let mut resolver = Resolver::new(&ast).with_host_names(host.into_keys()).with_natives(&options.natives);
+		for (i, m) in modules.iter().enumerate() {
+			resolver.resolve(&m.statements, i == modules.len() - 1);
+		}
+	}
+	let errors = error::take_diagnostics();
+	if !errors.is_empty() {
+		return Err(Diagnostics::Compile(errors))
+	}
+
+	if options.optimize {
+		let mut optimizer = Optimizer::new(&mut ast);
+		for m in &modules {
+			optimizer.optimize(&m.statements);
+		}
+	}
+	let mut removed = Vec::new();
+	if options.optimize {
+		let mut dead = DeadCode::new(&mut ast);
+		for (i, m) in modules.iter().enumerate() {
+			dead.eliminate(&m.statements, i == modules.len() - 1);
+		}
+		removed = dead.into_removed();
+	}
+	Ok((ast, modules, removed))
+}
+
+/// Loads, checks and runs a program, along with every module it imports.
+pub fn run_source(source: &str, options: Options) -> Result<Output, Diagnostics> {
+	let (ast, mut modules, _) = compile(source, &options)?;
+	let host = options.globals.iter().chain(&options.consts).map(|(n, _)| Symbol::intern(n));
+	let mut resolver = Resolver::new(&ast).with_host_names(host).with_natives(&options.natives);
+	for (i, m) in modules.iter().enumerate() {
+		resolver.resolve(&m.statements, i == modules.len() - 1);
+	}
+	let slots = Rc::new(resolver.into_slots());
+	let ast = Rc::new(ast);
+
+	let mut interpreter = Interpreter::new();
+	interpreter.set_output(options.output);
+	interpreter.set_input(options.input);
+	interpreter.set_limits(options.limits);
+	for (name, value) in options.consts {
+		interpreter.define_const(&name, value).map_err(Diagnostics::Runtime)?;
+	}
+	for (name, value) in options.globals {
+		interpreter.set_global(&name, value).map_err(Diagnostics::Runtime)?;
+	}
+	for n in options.natives {
+		interpreter.register_native(n);
+	}
+	let program = modules.pop().expect("the program is always loaded");
+	for m in &modules {
+		interpreter.interpret_module(&ast, &slots, m).map_err(Diagnostics::Runtime)?;
+	}
+	interpreter.interpret(&ast, &slots, &program.statements).map_err(Diagnostics::Runtime)?;
+	Ok(Output { interpreter })
+}
+
+/// Loads, checks and optimizes a program, returning the optimized program as PL/0 source.
+/// Its imports are optimized along with it, but left out of the result.
+/// Whatever [`DeadCode`] removed, from the program or its imports, is listed in `#` comments first.
+pub fn optimized_source(source: &str, options: Options) -> Result<String, Diagnostics> {
+	let options = Options { optimize: true, ..options };
+	let (ast, modules, removed) = compile(source, &options)?;
+	let program = modules.last().expect("the program is always loaded");
+	let notes: String = removed.iter().map(|r| format!("# {r}\n")).collect();
+	Ok(notes + &printer::program(&ast, &program.statements))
+}
+
+// Compiles a program and lowers it to the IR, optimized along with the tree if asked
+fn lower(source: &str, options: &Options) -> Result<ir::Program, Diagnostics> {
+	let (ast, modules, _) = compile(source, options)?;
+	let program = modules.last().expect("the program is always loaded");
+	let mut lowered = ir::lower(&ast, program, &options.consts, &options.globals);
+	let errors = error::take_diagnostics();
+	if !errors.is_empty() {
+		return Err(Diagnostics::Compile(errors))
+	}
+	verified(&lowered, "lowering", &program.file)?;
+	if options.optimize {
+		ir::optimize(&mut lowered);
+		verified(&lowered, "optimizing", &program.file)?;
+	}
+	Ok(lowered)
+}
+// Invalid IR is a bug in the compiler rather than in the program: it fails debug builds outright,
+// and is otherwise reported like a compile error instead of crashing the host
+fn verified(program: &ir::Program, stage: &str, file: &Rc<str>) -> Result<(), Diagnostics> {
+	let verified = ir::verify(program);
+	debug_assert!(verified.is_ok(), "{stage} produced invalid IR {}", verified.as_ref().unwrap_err());
+	verified.map_err(|e| Diagnostics::Compile(vec![Diagnostic {
+		file: file.clone(), line: 0, location: None, span: None,
+		message: format!("Internal error: {stage} produced invalid IR {e}"),
+	}]))
+}
+
+/// Loads and checks a program, then lowers it to the [`ir`], returning the IR in its textual form.
+/// Unless [`Options::optimize`] is off, the IR is optimized too. Records, imports and native
+/// procedures have no form in the IR, and are reported as compile errors.
+pub fn ir_source(source: &str, options: Options) -> Result<String, Diagnostics> {
+	Ok(lower(source, &options)?.to_string())
+}
+
+// Compiles a program for tools looking at it as written, returning its own statements
+fn unoptimized(source: &str, options: Options) -> Result<(Ast, Vec<StmtId>), Diagnostics> {
+	let options = Options { optimize: false, ..options };
+	let (ast, mut modules, _) = compile(source, &options)?;
+	let program = modules.pop().expect("the program is always loaded");
+	Ok((ast, program.statements))
+}
+
+/// Loads and checks a program, returning the control flow graph of its main body and of
+/// each of its procedures in Graphviz's DOT language. The graphs follow the program as written,
+/// before any optimization.
+pub fn cfg_dot(source: &str, options: Options) -> Result<String, Diagnostics> {
+	let (ast, statements) = unoptimized(source, options)?;
+	Ok(cfg::dot(&ast, &cfg::build(&ast, &statements)))
+}
+
+/// Loads and checks a program, returning its syntax tree as an indented outline.
+pub fn ast_tree(source: &str, options: Options) -> Result<String, Diagnostics> {
+	let (ast, statements) = unoptimized(source, options)?;
+	Ok(dump::tree(&ast, &statements))
+}
+
+/// Like [`ast_tree`], but in Graphviz's DOT language.
+pub fn ast_dot(source: &str, options: Options) -> Result<String, Diagnostics> {
+	let (ast, statements) = unoptimized(source, options)?;
+	Ok(dump::dot(&ast, &statements))
+}
+
+/// Loads and checks a program, returning which procedures call which in Graphviz's DOT language,
+/// along with where each is declared and, in red, the procedures that are recursive.
+pub fn call_graph_dot(source: &str, options: Options) -> Result<String, Diagnostics> {
+	let (ast, statements) = unoptimized(source, options)?;
+	Ok(callgraph::CallGraph::build(&ast, &statements).dot(&ast))
+}
+
+/// Parses a program along with its `#` comments, and writes it back out laid out the canonical way
+/// described at [`format::print`]. Only the syntax is checked: imports are not loaded.
+pub fn format_source(source: &str, options: Options) -> Result<String, Diagnostics> {
+	error::take_diagnostics();
+	let file: Rc<str> = options.file.display().to_string().into();
+	let tokens = Scanner::new(source.to_string(), file).with_comments().scan_tokens();
+	let cst = format::parse(tokens);
+	let errors = error::take_diagnostics();
+	match cst {
+		Ok(cst) if errors.is_empty() => Ok(format::print(&cst)),
+		_ => Err(Diagnostics::Compile(errors)),
+	}
+}
+
+/// Like [`run_source`], but runs the program lowered to the [`ir`], by [`ir::Interpreter`].
+/// The host's [`Options::globals`] and [`Options::consts`] are seen by the program the same way.
+pub fn run_ir(source: &str, options: Options) -> Result<(), Diagnostics> {
+	let program = lower(source, &options)?;
+	let mut interpreter = ir::Interpreter::new();
+	interpreter.set_output(options.output);
+	interpreter.set_limits(options.limits);
+	interpreter.run(&program).map_err(Diagnostics::Runtime)
+}
@@ -0,0 +1,92 @@
+use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}};
+
+use crate::error::{RuntimeError, RuntimeErrorKind};
+
+/// Bounds on how much work a program may do before it is stopped.
+/// Each limit that is hit stops the program with its own [`RuntimeErrorKind`].
+#[derive(Clone, Debug)]
+pub struct Limits {
+	/// Statements executed, counting every loop iteration and procedure call.
+	pub max_statements: Option<u64>,
+	/// Procedure calls in progress at once; 100 000 by default.
+	pub max_call_depth: Option<usize>,
+	/// Time since the program started running. The clock is read every 1024 statements
+	/// and at every procedure call, so a program may overrun it by that much work.
+	pub timeout: Option<Duration>,
+	/// Lets the host stop the program from another thread; the only cancel flag a run checks.
+	pub cancel: CancelHandle,
+}
+impl Default for Limits {
+	fn default() -> Self {
+		// calls live on the heap, but unbounded recursion should still stop long before memory runs out
+		Limits { max_statements: None, max_call_depth: Some(100_000), timeout: None, cancel: CancelHandle::new() }
+	}
+}
+
+/// Stops a running program at its next statement. Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+impl CancelHandle {
+	pub fn new() -> Self { Self::default() }
+	pub fn cancel(&self) { self.0.store(true, Ordering::Relaxed); }
+	pub fn is_cancelled(&self) -> bool { self.0.load(Ordering::Relaxed) }
+}
+
+// The interpreter's progress against its limits
+#[derive(Default)]
+pub(crate) struct Meter {
+	pub limits: Limits,
+	statements: u64,
+	depth: usize,
+	started: Option<Instant>,
+}
+impl Meter {
+	pub fn new(limits: Limits) -> Self {
+		Meter { limits, ..Default::default() }
+	}
+	// Starts counting afresh against `limits`, and their cancel flag
+	pub fn set_limits(&mut self, limits: Limits) {
+		*self = Meter::new(limits);
+	}
+
+	// called before every statement
+	pub fn step(&mut self) -> Result<(), RuntimeError> {
+		use RuntimeErrorKind::*;
+		self.statements += 1;
+		if let Some(max) = self.limits.max_statements {
+			if self.statements > max {
+				return Err(RuntimeError::of_kind(StatementLimit, format!("Statement limit of {max} exceeded")))
+			}
+		}
+		if self.limits.cancel.is_cancelled() {
+			return Err(RuntimeError::of_kind(Cancelled, "Cancelled by the host".to_string()))
+		}
+		// reading the clock is slow next to running a statement
+		if self.statements % 1024 == 1 { self.check_time()?; }
+		Ok(())
+	}
+	fn check_time(&mut self) -> Result<(), RuntimeError> {
+		if let Some(timeout) = self.limits.timeout {
+			let started = *self.started.get_or_insert_with(Instant::now);
+			if started.elapsed() > timeout {
+				return Err(RuntimeError::of_kind(RuntimeErrorKind::Timeout, format!("Timed out after {timeout:?}")))
+			}
+		}
+		Ok(())
+	}
+
+	// called before every procedure call, which also reads the clock
+	pub fn enter_call(&mut self) -> Result<(), RuntimeError> {
+		self.check_time()?;
+		if let Some(max) = self.limits.max_call_depth {
+			if self.depth >= max {
+				return Err(RuntimeError::of_kind(RuntimeErrorKind::CallDepth, format!("Call depth limit of {max} exceeded")))
+			}
+		}
+		self.depth += 1;
+		Ok(())
+	}
+	pub fn leave_call(&mut self) {
+		self.depth -= 1;
+	}
+}
@@ -1,35 +1,92 @@
-mod error;
-mod token;
-mod scanner;
-mod expressions;
-mod parser;
-mod interpreter;
-mod environment;
+use std::{path::PathBuf, time::Duration};
 
-use crate::error::had_error;
-use interpreter::Interpreter;
-use scanner::Scanner;
-use parser::Parser;
+use plz::{Diagnostics, Options, Limits};
 
 fn main() {
-	let data = std::fs::read_to_string(
-			std::env::args().nth(1)
-			.expect("gib sors")
-		).expect("error while reading source");
-
-	let mut scanner = Scanner::new(data);
-	let tokens = scanner.scan_tokens();
-	let mut parser = Parser::new(tokens);
-	let ast = parser.parse();
-
-	if had_error() {
-		eprintln!("Parsing failed, exiting");
-		std::process::exit(64);
+	// usage: plz [-I dir]... [--max-statements n] [--max-depth n] [--timeout seconds] [--dump-optimized | --dump-ir | --run-ir] file.pl0
+	//        plz cfg | ast [--dot] | calls [-I dir]... file.pl0
+	//        plz fmt [--check] file.pl0
+	// `plz cfg` prints the control flow graph of each procedure in Graphviz's DOT language,
+	// `plz ast` the syntax tree (as an outline, or with --dot in DOT), `plz calls` the call graph in DOT
+	// --dump-optimized prints the program as the optimizer leaves it instead of running it,
+	// after a comment for everything taken out as dead code
+	// --dump-ir prints the program lowered to the IR, --run-ir runs it that way
+	// `plz fmt` rewrites the file laid out the canonical way; with --check it only exits with 1 if that would change it
+	// imports by name are looked up in each `-I` directory, then along $PLZ_PATH
+	let mut args = std::env::args().skip(1).peekable();
+	let command = args.next_if(|a| matches!(a.as_str(), "cfg" | "ast" | "calls" | "fmt"));
+	let mut dot = false;
+	let mut check = false;
+	let mut search_path = Vec::new();
+	let mut limits = Limits::default();
+	let mut file = None;
+	let mut dump_optimized = false;
+	let mut dump_ir = false;
+	let mut run_ir = false;
+	let number = |flag: &str, value: Option<String>| -> u64 {
+		value.and_then(|v| v.parse().ok()).unwrap_or_else(|| panic!("expected a number after {flag}"))
+	};
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-I" => search_path.push(PathBuf::from(args.next().expect("expected a directory after -I"))),
+			"--max-statements" => limits.max_statements = Some(number(&arg, args.next())),
+			"--max-depth" => limits.max_call_depth = Some(number(&arg, args.next()) as usize),
+			"--timeout" => limits.timeout = Some(Duration::from_secs(number(&arg, args.next()))),
+			"--dump-optimized" => dump_optimized = true,
+			"--dump-ir" => dump_ir = true,
+			"--run-ir" => run_ir = true,
+			"--dot" => dot = true,
+			"--check" => check = true,
+			_ => file = Some(PathBuf::from(arg)),
+		}
 	}
+	if let Some(path) = std::env::var_os("PLZ_PATH") {
+		search_path.extend(std::env::split_paths(&path));
+	}
+
+	let file = file.expect("gib sors");
+	let source = std::fs::read_to_string(&file).expect("error while reading source");
 
-	let mut runtime = Interpreter::new();
-	let exc = runtime.interpret(ast);
-	if exc.is_err() {
-		println!("Error! {}", exc.unwrap_err());
+	let options = Options { file: file.clone(), search_path, limits, ..Default::default() };
+	let result =
+		if command.as_deref() == Some("fmt") {
+			plz::format_source(&source, options).map(|formatted| {
+				if formatted == source { return }
+				if check {
+					eprintln!("{} is not formatted", file.display());
+					std::process::exit(1);
+				}
+				std::fs::write(&file, formatted).expect("error while writing source");
+			})
+		}
+		else if let Some(command) = command {
+			let text = match command.as_str() {
+				"cfg" => plz::cfg_dot(&source, options),
+				"ast" if dot => plz::ast_dot(&source, options),
+				"ast" => plz::ast_tree(&source, options),
+				_ => plz::call_graph_dot(&source, options),
+			};
+			text.map(|text| print!("{text}"))
+		}
+		else if dump_optimized { plz::optimized_source(&source, options).map(|program| print!("{program}")) }
+		else if dump_ir { plz::ir_source(&source, options).map(|program| print!("{program}")) }
+		else if run_ir { plz::run_ir(&source, options) }
+		else { plz::run_source(&source, options).map(|_| ()) };
+	match result {
+		Ok(()) => {},
+		Err(Diagnostics::Compile(errors)) => {
+			for e in errors {
+				eprintln!("{e}");
+				// errors in imported modules point into their own files, so each is read back by name
+				let excerpt = std::fs::read_to_string(&*e.file).ok().and_then(|s| e.excerpt(&s));
+				if let Some(excerpt) = excerpt { eprintln!("{excerpt}"); }
+			}
+			eprintln!("Parsing failed, exiting");
+			std::process::exit(64);
+		},
+		Err(Diagnostics::Runtime(e)) => {
+			eprintln!("{e}");
+			std::process::exit(70);
+		},
 	}
 }
@@ -0,0 +1,180 @@
+use std::{collections::HashMap, path::{Path, PathBuf}, rc::Rc};
+
+use crate::{scanner::Scanner, symbol::Symbol, parser::{Parser, Stmt}, ast::{Ast, StmtId}, token::{Token, TokenType, Literal}, error::error, stdlib};
+
+pub struct Module {
+	pub file: Rc<str>,
+	pub statements: Vec<StmtId>, // in the program's `Ast`
+}
+
+// Names a module exports: Some(value) for consts, None for vars and procedures
+type Exports = HashMap<Symbol, Option<i32>>;
+
+pub struct Loader {
+	search_path: Vec<PathBuf>,
+	loaded: HashMap<PathBuf, (Rc<str>, Exports)>,
+	loading: Vec<PathBuf>, // chain of imports being loaded, for cycle detection
+	modules: Vec<Module>,
+	ast: Ast, // nodes of every module loaded
+	host: Exports, // names seeded by the host, visible to the program but not to its imports
+}
+impl Loader {
+	pub fn new(search_path: Vec<PathBuf>) -> Self {
+		Loader { search_path, loaded: HashMap::new(), loading: Vec::new(), modules: Vec::new(), host: HashMap::new(), ast: Ast::new() }
+	}
+	// Names the program may use without declaring them: Some(value) for consts, None for vars
+	pub fn with_host_names(mut self, names: Exports) -> Self {
+		self.host = names;
+		self
+	}
+
+	// Loads a program along with every module it imports, each one coming
+	// after all of its dependencies; the program itself comes last.
+	// `path` names the program in diagnostics, and anchors its relative imports.
+	pub fn load_program(mut self, path: &Path, source: String) -> (Ast, Vec<Module>) {
+		self.load(path, source);
+		(self.ast, self.modules)
+	}
+
+	fn load(&mut self, path: &Path, source: String) -> Exports {
+		let file: Rc<str> = path.display().to_string().into();
+		let key = Self::key(path);
+		self.loading.push(key.clone());
+
+		let tokens = Scanner::new(source, file.clone()).scan_tokens();
+		let mut parser = Parser::new(tokens);
+		if self.loading.len() == 1 {
+			for (name, value) in &self.host { parser.predeclare(*name, *value); }
+		}
+		let is_std = path == Path::new(stdlib::FILE);
+		if is_std {
+			parser.import(stdlib::NAME.into(), stdlib::primitives());
+		}
+		let mut imports: Vec<Token> = Vec::new(); // namespace names
+		let mut import_stmts = Vec::new();
+		for spec in parser.imports() {
+			let Some((name, target)) = self.resolve(path, &spec) else { continue };
+			if imports.iter().any(|n| n.lexeme == name.lexeme) {
+				error(spec, format!("Module `{}` imported twice", name.lexeme));
+				continue
+			}
+
+			let target_key = Self::key(&target);
+			if let Some(start) = self.loading.iter().position(|p| *p == target_key) {
+				let chain: Vec<String> = self.loading[start..].iter()
+					.chain([&target_key])
+					.map(|p| p.display().to_string())
+					.collect();
+				error(spec, format!("Import cycle: {}", chain.join(" -> ")));
+				continue
+			}
+
+			if !self.loaded.contains_key(&target_key) {
+				let source =
+					if target == Path::new(stdlib::FILE) { Ok(stdlib::SOURCE.to_string()) }
+					else { std::fs::read_to_string(&target) };
+				match source {
+					Ok(source) => { self.load(&target, source); },
+					Err(e) => {
+						error(spec, format!("Failed to read module: {e}"));
+						continue
+					},
+				}
+			}
+			let (target_file, exports) = self.loaded[&target_key].clone();
+			parser.import(name.lexeme, exports);
+			imports.push(name.clone());
+			import_stmts.push(Stmt::Import(name, target_file));
+		}
+
+		// dependencies are done adding to the tree by now
+		let mut parser = parser.with_ast(std::mem::take(&mut self.ast));
+		let mut statements = parser.parse();
+		let mut exports = Self::exports(parser.exports(), parser.ast(), &statements);
+		if is_std {
+			exports.extend(stdlib::primitives());
+		}
+		self.ast = parser.into_ast();
+		let imports: Vec<StmtId> = import_stmts.into_iter().map(|i| self.ast.add_stmt(i)).collect();
+		statements.splice(0..0, imports);
+
+		self.loading.pop();
+		self.loaded.insert(key, (file.clone(), exports.clone()));
+		self.modules.push(Module { file, statements });
+		exports
+	}
+
+	// identity of a file, regardless of the path it was reached by
+	fn key(path: &Path) -> PathBuf {
+		path.canonicalize().unwrap_or(path.to_path_buf())
+	}
+
+	// Finds the file an import refers to, and the namespace it is imported as.
+	// `import "dir/util.pl0"` is relative to the importing file, while `import util`
+	// looks for `util.pl0` next to the importing file, then along the search path.
+	// `import std` always refers to the embedded standard library.
+	fn resolve(&self, importer: &Path, spec: &Token) -> Option<(Token, PathBuf)> {
+		let dir = importer.parent().unwrap_or(Path::new("."));
+		let (name, candidates) = match &spec.literal {
+			_ if spec.kind == TokenType::IDENTIFIER && spec.lexeme == stdlib::NAME =>
+				(spec.lexeme.to_string(), vec![PathBuf::from(stdlib::FILE)]),
+			Some(Literal::String(path)) => {
+				let path = dir.join(path);
+				let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+				(stem, vec![path])
+			},
+			_ => {
+				let file = format!("{}.pl0", spec.lexeme);
+				let candidates = std::iter::once(dir.to_path_buf())
+					.chain(self.search_path.iter().cloned())
+					.map(|d| d.join(&file))
+					.collect();
+				(spec.lexeme.to_string(), candidates)
+			},
+		};
+
+		let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+			&& name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+		if !valid {
+			error(spec.clone(), format!("`{name}` is not a valid module name"));
+			return None
+		}
+		if name == stdlib::NAME && spec.kind != TokenType::IDENTIFIER {
+			error(spec.clone(), format!("`{name}` is reserved for the standard library"));
+			return None
+		}
+		let Some(target) = candidates.into_iter().find(|p| p.is_file() || p == Path::new(stdlib::FILE)) else {
+			error(spec.clone(), "Module not found".to_string());
+			return None
+		};
+
+		let name = Symbol::intern(&name);
+		let name = Token::new(TokenType::IDENTIFIER, name, Some(Literal::Identifier(name)), spec.line, spec.column, spec.file.clone());
+		Some((name, target))
+	}
+
+	fn exports(names: &[Token], ast: &Ast, statements: &[StmtId]) -> Exports {
+		let mut top_level = HashMap::new();
+		for s in statements {
+			match &ast[*s] {
+				Stmt::Const(consts) => {
+					for (name, value) in consts { top_level.insert(name.lexeme, Some(*value)); }
+				},
+				Stmt::Var(vars) => {
+					for (name, _) in vars { top_level.insert(name.lexeme, None); }
+				},
+				Stmt::Proc(name, _) => { top_level.insert(name.lexeme, None); },
+				_ => {},
+			}
+		}
+
+		let mut exports = HashMap::new();
+		for name in names {
+			match top_level.get(&name.lexeme) {
+				Some(v) => { exports.insert(name.lexeme, *v); },
+				None => error(name.clone(), "Only top-level consts, vars and procedures can be exported".to_string()),
+			}
+		}
+		exports
+	}
+}
@@ -0,0 +1,31 @@
+use std::{fmt::Debug, rc::Rc};
+
+use crate::error::RuntimeError;
+
+type NativeFn = dyn Fn(&[i32]) -> Result<Option<i32>, String>;
+
+// A procedure implemented in Rust, callable from PL/0 both as a statement
+// (`call log_event(x)`) and, when it returns a value, from expressions (`x := host_time()`).
+// The function receives the evaluated arguments; an `Err` aborts the program with its message.
+#[derive(Clone)]
+pub struct NativeProcedure {
+	pub name: String,
+	pub arity: usize,
+	func: Rc<NativeFn>,
+}
+impl NativeProcedure {
+	pub fn new(name: &str, arity: usize, func: impl Fn(&[i32]) -> Result<Option<i32>, String> + 'static) -> Self {
+		NativeProcedure { name: name.to_string(), arity, func: Rc::new(func) }
+	}
+	pub fn call(&self, args: &[i32]) -> Result<Option<i32>, RuntimeError> {
+		if args.len() != self.arity {
+			return Err(RuntimeError::new(format!("`{}` expects {} argument(s), got {}", self.name, self.arity, args.len())))
+		}
+		(self.func)(args).map_err(|msg| RuntimeError::new(format!("{}: {msg}", self.name)))
+	}
+}
+impl Debug for NativeProcedure {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "NativeProcedure({}/{})", self.name, self.arity)
+	}
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use crate::{parser::Stmt, expressions::Expr, ast::{Ast, ExprId, StmtId}, token::{Token, TokenType, Literal}, symbol::Symbol, builtins};
+
+#[derive(Clone, Copy)]
+enum Value {
+	Int(i32),
+	Bool(bool),
+}
+
+// What a name stands for, as far as folding is concerned
+#[derive(Clone, Copy)]
+enum Name {
+	Const(i32),
+	Integer, // a variable declared `: integer`
+	Other, // anything else, which still shadows outer consts
+}
+
+// Pass run after type checking, rewriting the tree in place: constant expressions
+// are folded, consts substituted, identities like `x * 1` and `x + 0` simplified, and
+// statements behind constant conditions (`if 1 == 2 then ...`, `while 0 > 1 do ...`) dropped.
+// Anything that would fail at runtime, like a division by zero, is left for the runtime to report.
+pub struct Optimizer<'a> {
+	ast: &'a mut Ast,
+	scopes: Vec<HashMap<Symbol, Name>>,
+}
+impl<'a> Optimizer<'a> {
+	pub fn new(ast: &'a mut Ast) -> Self {
+		Optimizer { ast, scopes: Vec::new() }
+	}
+	// Optimizes the statements of one module, whose names are its own
+	pub fn optimize(&mut self, statements: &[StmtId]) {
+		self.scopes = vec![HashMap::new()];
+		for s in statements { self.statement(*s); }
+	}
+
+	fn declare(&mut self, name: &Token, kind: Name) {
+		self.scopes.last_mut().unwrap().insert(name.lexeme, kind);
+	}
+	fn lookup(&self, name: &Token) -> Option<Name> {
+		self.scopes.iter().rev().find_map(|s| s.get(&name.lexeme)).copied()
+	}
+	fn is_empty(&self, s: StmtId) -> bool {
+		matches!(&self.ast[s], Stmt::Scope(statements) if statements.is_empty())
+	}
+
+	fn statement(&mut self, s: StmtId) {
+		match self.ast[s].clone() {
+			Stmt::Proc(name, body) => {
+				self.declare(&name, Name::Other);
+				self.scopes.push(HashMap::new());
+				for s in body { self.statement(s); }
+				self.scopes.pop();
+			},
+			Stmt::Const(consts) => {
+				for (name, value) in consts { self.declare(&name, Name::Const(value)); }
+			},
+			Stmt::Type(types) => {
+				for (name, _) in types { self.declare(&name, Name::Other); }
+			},
+			Stmt::Var(names) => {
+				for (name, kind) in names {
+					let is_integer = kind.is_some_and(|k| k.lexeme == "integer");
+					self.declare(&name, if is_integer { Name::Integer } else { Name::Other });
+				}
+			},
+			Stmt::PrintVar(..) | Stmt::Import(..) => {},
+			Stmt::Print(e) | Stmt::Expression(e) | Stmt::Assign(_, e) | Stmt::Set(_, _, e) => { self.expression(e); },
+			Stmt::Write(args, _, _) | Stmt::Call(_, args) => {
+				for a in args { self.expression(a); }
+			},
+			Stmt::Scope(statements) => {
+				for s in &statements { self.statement(*s); }
+				let kept: Vec<StmtId> = statements.into_iter().filter(|s| !self.is_empty(*s)).collect();
+				self.ast[s] = Stmt::Scope(kept);
+			},
+			Stmt::If(cond, branch) => {
+				let cond = self.expression(cond);
+				self.statement(branch);
+				match cond {
+					Some(Value::Bool(true)) => self.ast[s] = self.ast[branch].clone(),
+					Some(Value::Bool(false)) => self.ast[s] = Stmt::Scope(Vec::new()),
+					_ => {},
+				}
+			},
+			Stmt::While(cond, body) => {
+				if let Some(Value::Bool(false)) = self.expression(cond) {
+					self.ast[s] = Stmt::Scope(Vec::new());
+				} else {
+					self.statement(body);
+				}
+			},
+			Stmt::Case(subject, table) => {
+				self.expression(subject);
+				for arm in table.arms { self.statement(arm); }
+			},
+		}
+	}
+
+	// Folds `expr` as far as it goes, returning its value if it is now a literal
+	fn expression(&mut self, expr: ExprId) -> Option<Value> {
+		use TokenType::*;
+		let value = match self.ast[expr].clone() {
+			Expr::Literal(l) => return match l.literal {
+				Some(Literal::Number(v)) => Some(Value::Int(v)),
+				Some(Literal::Boolean(b)) => Some(Value::Bool(b)),
+				_ => None,
+			},
+			Expr::Grouping(e) => self.expression(e)?,
+			Expr::Unary(op, e) => match (op.kind, self.expression(e)?) {
+				(ODD, Value::Int(v)) => Value::Bool(v % 2 == 1),
+				(_, Value::Int(v)) => Value::Int(builtins::unary(op.kind, v).ok()?),
+				_ => return None,
+			},
+			Expr::Binary(a, op, b) => match (self.expression(a), self.expression(b)) {
+				(Some(a), Some(b)) => Self::binary(op.kind, a, b)?,
+				(Some(Value::Int(v)), None) if self.is_integer(b) && Self::is_identity(op.kind, v, false) => {
+					self.ast[expr] = self.ast[b].clone();
+					return None
+				},
+				(None, Some(Value::Int(v))) if self.is_integer(a) && Self::is_identity(op.kind, v, true) => {
+					self.ast[expr] = self.ast[a].clone();
+					return None
+				},
+				_ => return None,
+			},
+			Expr::Variable(name) => match self.lookup(&name) {
+				Some(Name::Const(v)) => Value::Int(v),
+				_ => return None,
+			},
+			Expr::Get(..) => return None,
+			Expr::Call(_, builtin, args) => {
+				let mut values = Vec::new();
+				for a in args {
+					// every argument gets folded, even when the call cannot be
+					values.push(self.expression(a));
+				}
+				let builtin = builtin?;
+				let values = values.into_iter()
+					.map(|v| match v { Some(Value::Int(v)) => Some(v), _ => None })
+					.collect::<Option<Vec<_>>>()?;
+				Value::Int(builtin.call(&values).ok()?)
+			},
+		};
+		self.ast[expr] = Expr::Literal(Self::literal(self.ast.expr_token(expr), value));
+		Some(value)
+	}
+	fn binary(op: TokenType, a: Value, b: Value) -> Option<Value> {
+		use TokenType::*;
+		use Value::*;
+		Some(match (a, b) {
+			(Bool(a), Bool(b)) => match op {
+				EQU_EQU => Bool(a == b),
+				BANG_EQU => Bool(a != b),
+				_ => return None,
+			},
+			(Int(a), Int(b)) => match op {
+				EQU_EQU  => Bool(a == b),
+				BANG_EQU => Bool(a != b),
+				LESS_EQU => Bool(a <= b),
+				MORE_EQU => Bool(a >= b),
+				LESS => Bool(a < b),
+				MORE => Bool(a > b),
+				_ => Int(builtins::binary(op, a, b).ok()?),
+			},
+			_ => return None,
+		})
+	}
+	// whether `x op v` (or `v op x`, if not `on_right`) is always just `x`
+	fn is_identity(op: TokenType, v: i32, on_right: bool) -> bool {
+		use TokenType::*;
+		match op {
+			PLUS | PIPE | CARET => v == 0,
+			STAR => v == 1,
+			MINUS | LESS_LESS | MORE_MORE => on_right && v == 0,
+			SLASH | STAR_STAR => on_right && v == 1,
+			_ => false,
+		}
+	}
+	// Whether an expression can only evaluate to an integer (or fail); anything else,
+	// like an unannotated variable holding a boolean, has to go through the operator to fail
+	fn is_integer(&self, expr: ExprId) -> bool {
+		use TokenType::*;
+		match &self.ast[expr] {
+			Expr::Literal(l) => l.kind == NUMBER,
+			Expr::Grouping(e) => self.is_integer(*e),
+			Expr::Unary(op, _) => op.kind != ODD,
+			Expr::Binary(_, op, _) => !matches!(op.kind, EQU_EQU | BANG_EQU | LESS | LESS_EQU | MORE | MORE_EQU),
+			Expr::Variable(name) => matches!(self.lookup(name), Some(Name::Const(_) | Name::Integer)),
+			Expr::Get(..) | Expr::Call(..) => true,
+		}
+	}
+	fn literal(at: &Token, value: Value) -> Token {
+		let (line, column, file) = (at.line, at.column, at.file.clone());
+		match value {
+			Value::Int(v) => Token::with_raw(TokenType::NUMBER, &v.to_string(), Some(Literal::Number(v)), line, column, file),
+			Value::Bool(b) => {
+				let kind = if b { TokenType::TRUE } else { TokenType::FALSE };
+				Token::new(kind, Symbol::intern(&b.to_string()), Some(Literal::Boolean(b)), line, column, file)
+			},
+		}
+	}
+}
@@ -1,39 +1,134 @@
+use std::{collections::HashMap, rc::Rc};
+
 use crate::error::ParseError;
-use crate::{token::{Token, TokenType}, expressions::Expr, error::error};
+use crate::token::Literal;
+use crate::builtins::{self, Builtin};
+use crate::{token::{Token, TokenType}, symbol::Symbol, expressions::Expr, ast::{Ast, ExprId, StmtId}, error::error};
 
+// Statements live in an `Ast`, referring to the statements and expressions they contain by id
 #[derive(Clone)]
 pub enum Stmt {
-	Proc(Token, Box<Vec<Stmt>>),
-	Const(Vec<(Token, Token)>),
-	Var(Vec<Token>),
+	Proc(Token, Vec<StmtId>),
+	Const(Vec<(Token, i32)>), // values are resolved while parsing
+	Type(Vec<(Token, Vec<Token>)>), // record types and their field names
+	Var(Vec<(Token, Option<Token>)>), // names with optional type annotations
 
-	Print(Expr),
+	Print(ExprId),
 	PrintVar(Token),
-	Expression(Expr),
-	Scope(Vec<Stmt>), // couldn't call it a 'block' because of the EBNF's naming convention
-	Assign(Token, Expr),
-	If(Expr, Box<Stmt>),
-	While(Expr, Box<Stmt>),
-	Call(Token),
+	Write(Vec<ExprId>, bool, bool), // (arguments, trailing newline, `> ` prefix)
+	Expression(ExprId),
+	Scope(Vec<StmtId>), // couldn't call it a 'block' because of the EBNF's naming convention
+	Assign(Token, ExprId),
+	Set(Token, Token, ExprId), // record field assignment
+	If(ExprId, StmtId),
+	While(ExprId, StmtId),
+	Call(Token, Vec<ExprId>), // arguments are only accepted by native procedures
+	Case(ExprId, CaseTable),
+	Import(Token, Rc<str>), // namespace name, and the file it refers to
+}
+
+#[derive(Clone)]
+pub struct CaseTable {
+	pub labels: Vec<(i32, usize)>, // sorted by label value, indexes into `arms`
+	pub arms: Vec<StmtId>,
+	pub default: Option<usize>, // the `else` arm, also in `arms`
+}
+impl CaseTable {
+	// index of the arm to run for `value`
+	pub fn select(&self, value: i32) -> Option<usize> {
+		match self.labels.binary_search_by_key(&value, |(label, _)| *label) {
+			Ok(i) => Some(self.labels[i].1),
+			Err(_) => self.default,
+		}
+	}
 }
 
 pub struct Parser {
 	tokens: Vec<Token>,
 	current: usize,
+	// names declared in each enclosing block: Some(value) for consts, None for anything else
+	scopes: Vec<HashMap<Symbol, Option<i32>>>,
+	// names exported by each imported module, in the same form
+	modules: HashMap<Symbol, HashMap<Symbol, Option<i32>>>,
+	exports: Vec<Token>,
+	ast: Ast, // where parsed nodes go
+	syntax_error: bool,
 }
 impl Parser {
 	pub fn new(tokens: Vec<Token>) -> Self {
-		Parser { tokens, current: 0 }
+		Parser {
+			tokens, current: 0,
+			scopes: vec![HashMap::new()],
+			modules: HashMap::new(), exports: Vec::new(),
+			ast: Ast::new(),
+			syntax_error: false,
+		}
+	}
+	// Adds parsed nodes to an existing tree, e.g. one shared by all the modules of a program
+	pub fn with_ast(mut self, ast: Ast) -> Self {
+		self.ast = ast;
+		self
+	}
+	pub fn ast(&self) -> &Ast { &self.ast }
+	pub fn into_ast(self) -> Ast { self.ast }
+	// Parses the `import` declarations heading the file, returning what each one names:
+	// a STRING path or an IDENTIFIER to look up. Call before `parse`.
+	pub fn imports(&mut self) -> Vec<Token> {
+		use TokenType::*;
+		let mut out = Vec::new();
+		while self.matches(&[IMPORT]) {
+			if self.matches(&[STRING, IDENTIFIER]) { out.push(self.previous()); }
+			else { self.error("Expected module name or path after IMPORT"); }
+			if self.consume(SEMICOLON, "Expected `;` after import").is_err() {
+				self.synchronise();
+			}
+		}
+		out
+	}
+	// Makes a loaded module's exports reachable as `name.member`
+	pub fn import(&mut self, name: Symbol, exports: HashMap<Symbol, Option<i32>>) {
+		self.modules.insert(name, exports);
 	}
-	pub fn parse(&mut self) -> Vec<Stmt> {
+	pub fn exports(&self) -> &[Token] { &self.exports }
+	// Whether the grammar was broken, rather than only the rules checked along the way,
+	// like constant expressions and case labels; the formatter only checks the grammar
+	pub fn had_syntax_error(&self) -> bool { self.syntax_error }
+	// Makes a name defined outside the program known to it, e.g. one seeded by the host;
+	// `value` is Some for consts, so that they can be used in constant expressions
+	pub fn predeclare(&mut self, name: Symbol, value: Option<i32>) {
+		self.scopes[0].insert(name, value);
+	}
+
+	// Parses the program, returning its top-level statements; their nodes are in `ast`
+	pub fn parse(&mut self) -> Vec<StmtId> {
+		use TokenType::*;
 		let mut out = Vec::new();
+		if self.matches(&[EXPORT]) {
+			while let Ok(name) = self.consume(IDENTIFIER, "Expected exported name") {
+				self.exports.push(name);
+				if !self.matches(&[COMMA]) {
+					let _ = self.consume(SEMICOLON, "Expected `;` after export list");
+					break
+				}
+			}
+		}
 		while !self.is_at_end() {
-			out.append(&mut self.block())
+			let start = self.current;
+			out.append(&mut self.block());
+			// a stray token the empty statement left in place, e.g. a `;` after the main statement
+			if self.current == start {
+				self.error("Expected `.` at the end of the program");
+				self.synchronise();
+			}
+		}
+		if self.peek().kind == TokenType::EOF {
+			self.error("Expected `.` at the end of the program");
 		}
 		out
 	}
 
 	fn error(&mut self, message: &str) -> ParseError {
+		self.syntax_error = true;
 		error(self.peek(), message.to_string());
 		ParseError{}
 	}
@@ -43,7 +138,7 @@ impl Parser {
 		self.advance();
 		while !self.is_at_end() { 
 			match self.peek().kind {
-				CONST | VAR | PROCEDURE | BEGIN => return,
+				CONST | TYPE | VAR | PROCEDURE | BEGIN | IMPORT => return,
 				_ => {}
 			}
 			self.advance();
@@ -52,7 +147,20 @@ impl Parser {
 
 	// token parsing functions
 	fn peek(&self) -> Token{ self.tokens[self.current].clone() }
-	fn is_at_end(&self) -> bool { self.peek().kind == TokenType::DOT }
+	fn is_at_end(&self) -> bool {
+		match self.peek().kind {
+			TokenType::EOF => true,
+			TokenType::DOT => !self.is_field_access(),
+			_ => false,
+		}
+	}
+	// a `.` between two identifiers accesses a record field, any other one ends the program
+	fn is_field_access(&self) -> bool {
+		use TokenType::*;
+		self.tokens[self.current].kind == DOT
+			&& self.current > 0 && self.tokens[self.current-1].kind == IDENTIFIER
+			&& self.tokens.get(self.current+1).is_some_and(|t| t.kind == IDENTIFIER)
+	}
 	fn previous(&mut self) -> Token { self.tokens[self.current-1].clone() }
 	fn advance(&mut self) -> Token {
 		if !self.is_at_end() { self.current += 1; }
@@ -76,42 +184,134 @@ impl Parser {
 		else { Err(self.error(err_msg)) }
 	}
 
+	// name tracking
+	fn declare(&mut self, name: &Token, value: Option<i32>) {
+		self.scopes.last_mut().unwrap().insert(name.lexeme, value);
+	}
+	// Some(Some(value)) for consts, Some(None) for other known names
+	fn lookup(&self, name: &Token) -> Option<Option<i32>> {
+		if let Some(Literal::Qualified(module, member)) = name.literal {
+			return self.modules.get(&module)?.get(&member).copied()
+		}
+		self.scopes.iter().rev().find_map(|s| s.get(&name.lexeme)).copied()
+	}
+	fn const_value(&self, name: &Token) -> Option<i32> {
+		self.lookup(name).flatten()
+	}
+	// Folds `module.member` into a single identifier token, naming the member of an imported module
+	fn qualified(&mut self, name: Token) -> Token {
+		let is_module = self.modules.contains_key(&name.lexeme)
+			&& self.scopes.iter().all(|s| !s.contains_key(&name.lexeme));
+		if !is_module || !self.is_field_access() { return name }
+
+		self.advance();
+		let member = self.advance();
+		let lexeme = Symbol::intern(&format!("{}.{}", name.lexeme, member.lexeme));
+		let literal = Literal::Qualified(name.lexeme, member.lexeme);
+		let token = Token::new(TokenType::IDENTIFIER, lexeme, Some(literal), name.line, name.column, name.file);
+		if self.lookup(&token).is_none() {
+			error(member, format!("Not exported by module `{}`", name.lexeme));
+		}
+		token
+	}
+
+	// constant expressions, evaluated from previously declared consts
+	fn const_eval(&self, expr: ExprId) -> Result<i32, (Token, String)> {
+		match &self.ast[expr] {
+			Expr::Literal(l) => match l.literal {
+				Some(Literal::Number(v)) => Ok(v),
+				_ => Err((l.clone(), "Expected an integer in constant expression".to_string())),
+			},
+			Expr::Grouping(e) => self.const_eval(*e),
+			Expr::Unary(op, e) => {
+				let v = self.const_eval(*e)?;
+				builtins::unary(op.kind, v).map_err(|msg| (op.clone(), msg))
+			},
+			Expr::Binary(a, op, b) => {
+				let (va, vb) = (self.const_eval(*a)?, self.const_eval(*b)?);
+				builtins::binary(op.kind, va, vb).map_err(|msg| (op.clone(), msg))
+			},
+			Expr::Variable(name) => match self.lookup(name) {
+				Some(Some(v)) => Ok(v),
+				Some(None) => Err((name.clone(), "Only consts may be used in constant expressions".to_string())),
+				None => Err((name.clone(), "Undeclared constant".to_string())),
+			},
+			Expr::Get(name, _) =>
+				Err((name.clone(), "Only consts may be used in constant expressions".to_string())),
+			Expr::Call(name, builtin, args) => {
+				let args = args.iter().map(|a| self.const_eval(*a)).collect::<Result<Vec<_>, _>>()?;
+				let builtin = builtin.ok_or_else(|| (name.clone(), format!("Unknown function: {}", name.lexeme)))?;
+				builtin.call(&args).map_err(|msg| (name.clone(), msg))
+			},
+		}
+	}
+
 	// recursive descent functions
-	fn block(&mut self) -> Vec<Stmt> {
+	fn block(&mut self) -> Vec<StmtId> {
 		use TokenType::*;
 		let mut out = Vec::new();
 
 		if self.matches(&[CONST]) {
 			let consts = self.const_declaration();
-			if let Some(c) = consts.ok() { out.push(c); }
+			if let Ok(c) = consts { out.push(c); }
+		}
+		if self.matches(&[TYPE]) {
+			let types = self.type_declaration();
+			if let Ok(t) = types { out.push(t); }
 		}
 		if self.matches(&[VAR]) {
 			let vars = self.var_declaration();
-			if let Some(v) = vars.ok() { out.push(v); }
+			if let Ok(v) = vars { out.push(v); }
 		}
 		while self.matches(&[PROCEDURE]) {
 			let proc = self.proc_declaration();
-			if let Some(p) = proc.ok() { out.push(p); }
+			if let Ok(p) = proc { out.push(p); }
 		}
 		
-		let stmt = self.statement();
-		if stmt.is_err() { self.synchronise(); }
-		else { out.push(stmt.unwrap()); }
+		match self.statement() {
+			Ok(stmt) => out.push(stmt),
+			Err(_) => self.synchronise(),
+		}
 
 		out
 	}
 
-	fn statement(&mut self) -> Result<Stmt, ParseError> {
+	fn stmt(&mut self, stmt: Stmt) -> Result<StmtId, ParseError> {
+		Ok(self.ast.add_stmt(stmt))
+	}
+	fn expr(&mut self, expr: Expr) -> Result<ExprId, ParseError> {
+		Ok(self.ast.add_expr(expr))
+	}
+
+	fn statement(&mut self) -> Result<StmtId, ParseError> {
 		use TokenType::*;
 		if self.matches(&[BEGIN]) {
 			return self.scope();
 		}
 		if self.matches(&[BANG]) {
-			return Ok(Stmt::Print(self.expression()?));
+			let mut args = self.write_list()?;
+			let is_string = matches!(&self.ast[args[0]], Expr::Literal(t) if t.kind == STRING);
+			if args.len() == 1 && !is_string {
+				return self.stmt(Stmt::Print(args.remove(0)));
+			}
+			return self.stmt(Stmt::Write(args, true, true));
+		}
+		if self.matches(&[WRITE]) {
+			let args = self.write_list()?;
+			return self.stmt(Stmt::Write(args, false, false));
+		}
+		if self.matches(&[WRITELN]) {
+			// a bare `writeln` just ends the current line
+			let args =
+				if self.check(SEMICOLON) || self.check(END) || self.is_at_end() { Vec::new() }
+				else { self.write_list()? };
+			return self.stmt(Stmt::Write(args, true, false));
 		}
 		if self.matches(&[QMARK]) {
 			if self.matches(&[IDENTIFIER]) {
-				return Ok(Stmt::PrintVar(self.previous()));
+				let name = self.previous();
+				let name = self.qualified(name);
+				return self.stmt(Stmt::PrintVar(name));
 			}
 			return Err(self.error("Expected identifier for `?` expression"));
 		}
@@ -120,51 +320,104 @@ impl Parser {
 		}
 		if self.matches(&[CALL]) {
 			if self.matches(&[IDENTIFIER]) {
-				return Ok(Stmt::Call(self.previous()));
+				let name = self.previous();
+				let name = self.qualified(name);
+				let args =
+					if self.matches(&[LEFT_PAREN]) { self.arguments()? }
+					else { Vec::new() };
+				return self.stmt(Stmt::Call(name, args));
 			}
 			return Err(self.error("Expected procedure identifier for CALL expression"));
 		}
 		if self.matches(&[WHILE]) {
 			return self.while_statement();
 		}
-		if self.matches(&[CALL]) {
-			todo!("statement types");
+		if self.matches(&[CASE]) {
+			return self.case_statement();
+		}
+		if self.check(IMPORT) || self.check(EXPORT) {
+			return Err(self.error("Imports and exports must come at the start of the file"));
 		}
-		return Ok(self.assignment_or_expr()?);
+		if self.check(SEMICOLON) || self.check(END) || self.is_at_end() {
+			// the empty statement
+			return self.stmt(Stmt::Scope(Vec::new()));
+		}
+		self.assignment_or_expr()
 	}
-	fn const_declaration(&mut self) -> Result<Stmt, ParseError> {
+	fn const_declaration(&mut self) -> Result<StmtId, ParseError> {
 		use TokenType::*;
-		let mut consts: Vec<(Token, Token)> = Vec::new();
+		let mut consts: Vec<(Token, i32)> = Vec::new();
 		loop {
 			let name = self.consume(IDENTIFIER, "Expected const name")?;
 			self.consume(EQU, &format!("Expected `=` after const name: {}", name.lexeme))?;
-			let value = self.consume(NUMBER, "Expected const value")?;
+			let value = self.expression()?;
+			let value = match self.const_eval(value) {
+				Ok(v) => v,
+				Err((token, msg)) => {
+					// Report, but keep parsing with a placeholder value
+					error(token, msg);
+					0
+				},
+			};
+			self.declare(&name, Some(value));
 			consts.push((name, value));
 			if !self.matches(&[COMMA]) { break }
 		}
 		self.consume(SEMICOLON, "Expected `;` after const declaration")?;
-		Ok(Stmt::Const(consts))
+		self.stmt(Stmt::Const(consts))
 	}
-	fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+	fn type_declaration(&mut self) -> Result<StmtId, ParseError> {
+		use TokenType::*;
+		let mut types = Vec::new();
+		loop {
+			let name = self.consume(IDENTIFIER, "Expected type name")?;
+			self.consume(EQU, &format!("Expected `=` after type name: {}", name.lexeme))?;
+			self.consume(RECORD, "Expected RECORD token")?;
+			let mut fields: Vec<Token> = Vec::new();
+			loop {
+				let field = self.consume(IDENTIFIER, "Expected field name")?;
+				if fields.iter().any(|f| f.lexeme == field.lexeme) {
+					error(field.clone(), "Duplicate field name".to_string());
+				}
+				fields.push(field);
+				if !self.matches(&[COMMA]) { break }
+			}
+			self.consume(END, "Expected END token after record fields")?;
+			self.declare(&name, None);
+			types.push((name, fields));
+			if !self.matches(&[COMMA]) { break }
+		}
+		self.consume(SEMICOLON, "Expected `;` after type declaration")?;
+		self.stmt(Stmt::Type(types))
+	}
+	fn var_declaration(&mut self) -> Result<StmtId, ParseError> {
 		use TokenType::*;
 		let mut names = Vec::new();
-		names.push(self.consume(IDENTIFIER, "Expected var name")?);
-		while self.matches(&[COMMA]) {
-			names.push(self.consume(IDENTIFIER, "Expected var name after comma")?);
+		loop {
+			let name = self.consume(IDENTIFIER, "Expected var name")?;
+			let kind =
+				if self.matches(&[COLON]) { Some(self.consume(IDENTIFIER, "Expected type name after `:`")?) }
+				else { None };
+			names.push((name, kind));
+			if !self.matches(&[COMMA]) { break }
 		}
+		for (name, _) in &names { self.declare(name, None); }
 		self.consume(SEMICOLON, "Expected `;` after var declaration")?;
-		Ok(Stmt::Var(names))
+		self.stmt(Stmt::Var(names))
 	}
-	fn proc_declaration(&mut self) -> Result<Stmt, ParseError> {
+	fn proc_declaration(&mut self) -> Result<StmtId, ParseError> {
 		use TokenType::*;
 		let name = self.consume(IDENTIFIER, "Expected procedure identifier")?;
 		self.consume(SEMICOLON, "Expected `;` after procedure identifier")?;
+		self.declare(&name, None);
+		self.scopes.push(HashMap::new());
 		let block = self.block();
+		self.scopes.pop();
 		self.consume(SEMICOLON, "Expected `;` after procedure block")?;
-		Ok(Stmt::Proc(name, Box::new(block)))
+		self.stmt(Stmt::Proc(name, block))
 	}
 
-	fn scope(&mut self) -> Result<Stmt, ParseError> {
+	fn scope(&mut self) -> Result<StmtId, ParseError> {
 		use TokenType::*;
 		let mut statements = Vec::new();
 		statements.push(self.statement()?);
@@ -179,62 +432,181 @@ impl Parser {
 			}
 			statements.push(self.statement()?);
 		}
-		Ok(Stmt::Scope(statements))
+		self.stmt(Stmt::Scope(statements))
 	}
-	fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+	fn if_statement(&mut self) -> Result<StmtId, ParseError> {
 		use TokenType::*;
 		let cond = self.condition()?;
 		self.consume(THEN, "Expected THEN token after IF condition")?;
 		let stmt = self.statement()?;
-		Ok(Stmt::If(cond, Box::new(stmt)))
+		self.stmt(Stmt::If(cond, stmt))
 	}
-	fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+	fn while_statement(&mut self) -> Result<StmtId, ParseError> {
 		use TokenType::*;
 		let cond = self.condition()?;
 		self.consume(DO, "Expected DO token after WHILE condition")?;
 		let stmt = self.statement()?;
-		Ok(Stmt::While(cond, Box::new(stmt)))
+		self.stmt(Stmt::While(cond, stmt))
 	}
-	fn assignment_or_expr(&mut self) -> Result<Stmt, ParseError> {
+	fn case_statement(&mut self) -> Result<StmtId, ParseError> {
+		use TokenType::*;
+		let subject = self.expression()?;
+		self.consume(OF, "Expected OF token after CASE expression")?;
+
+		let mut seen: HashMap<i32, Token> = HashMap::new();
+		let mut labels = Vec::new();
+		let mut arms = Vec::new();
+		let mut default = None;
+		while !self.matches(&[END]) {
+			if self.matches(&[ELSE]) {
+				default = Some(arms.len());
+				arms.push(self.statement()?);
+				self.matches(&[SEMICOLON]);
+				self.consume(END, "Expected END token after ELSE arm")?;
+				break
+			}
+
+			loop {
+				let (label, value) = self.case_label()?;
+				if let Some(prev) = seen.get(&value) {
+					// Report, but keep parsing -- the statement itself is well-formed
+					error(label.clone(), format!("Duplicate case label {value} (already used at line {})", prev.line));
+				} else {
+					labels.push((value, arms.len()));
+					seen.insert(value, label);
+				}
+				if !self.matches(&[COMMA]) { break }
+			}
+			self.consume(COLON, "Expected `:` after case labels")?;
+			arms.push(self.statement()?);
+
+			if !self.matches(&[SEMICOLON]) {
+				if self.check(ELSE) { continue }
+				self.consume(END, "Expected END token")?;
+				break
+			}
+		}
+
+		labels.sort_unstable();
+		self.stmt(Stmt::Case(subject, CaseTable { labels, arms, default }))
+	}
+	fn case_label(&mut self) -> Result<(Token, i32), ParseError> {
+		use TokenType::*;
+		let negative = self.matches(&[MINUS]);
+		if self.matches(&[NUMBER]) {
+			let label = self.previous();
+			let value = match label.literal { Some(Literal::Number(v)) => v, _ => 0 };
+			return Ok((label, if negative { -value } else { value }))
+		}
+		if !negative && self.matches(&[IDENTIFIER]) {
+			let label = self.previous();
+			let label = self.qualified(label);
+			return match self.const_value(&label) {
+				Some(v) => Ok((label, v)),
+				None => {
+					error(label.clone(), "Case label is not a constant".to_string());
+					Err(ParseError{})
+				},
+			}
+		}
+		Err(self.error("Expected a number or const name as case label"))
+	}
+	fn write_list(&mut self) -> Result<Vec<ExprId>, ParseError> {
+		use TokenType::*;
+		let mut args = Vec::new();
+		loop {
+			if self.matches(&[STRING]) {
+				let literal = self.previous();
+				args.push(self.expr(Expr::Literal(literal))?);
+			} else {
+				args.push(self.expression()?);
+			}
+			if !self.matches(&[COMMA]) { break }
+		}
+		Ok(args)
+	}
+	fn assignment_or_expr(&mut self) -> Result<StmtId, ParseError> {
 		use TokenType::*;
 		let expr = self.expression()?;
 
 		if self.matches(&[COLON_EQU]) {
-			let value = self.expression()?;
-			match expr {
-				Expr::Variable(name) => Ok(Stmt::Assign(name, value)),
+			// conditions may be stored in boolean variables
+			let value = self.condition()?;
+			match self.ast[expr].clone() {
+				Expr::Variable(name) => self.stmt(Stmt::Assign(name, value)),
+				Expr::Get(name, field) => self.stmt(Stmt::Set(name, field, value)),
 				_ => {
 					// Report, but don't throw Err -- no need to synchronise.
-					self.error(&format!("Invalid lvalue: {expr}"));
+					self.error(&format!("Invalid lvalue: {}", self.ast.show(expr)));
 					// return lvalue as placeholder
-					Ok(Stmt::Expression(expr))
+					self.stmt(Stmt::Expression(expr))
 				}
 			}
-		} else { Ok(Stmt::Expression(expr)) }
+		} else { self.stmt(Stmt::Expression(expr)) }
 	}
 
-	fn condition(&mut self) -> Result<Expr, ParseError> {
+	fn condition(&mut self) -> Result<ExprId, ParseError> {
 		use TokenType::*;
 		if self.matches(&[ODD]) {
-			Ok(Expr::Unary(
-				self.previous(),
-				Box::new(self.expression()?)
-			))
+			let operator = self.previous();
+			let operand = self.expression()?;
+			self.expr(Expr::Unary(operator, operand))
 		} else {
 			self.equality()
 		}
 	}
-	fn equality(&mut self) -> Result<Expr, ParseError> {
+	fn equality(&mut self) -> Result<ExprId, ParseError> {
 		let left = self.expression()?;
 		use TokenType::*;
 		if self.matches(&[BANG_EQU, EQU_EQU, LESS, LESS_EQU, MORE, MORE_EQU]) {
 			let operator = self.previous();
 			let right = self.expression()?;
-			return Ok(Expr::Binary(Box::new(left), operator, Box::new(right)))
+			return self.expr(Expr::Binary(left, operator, right))
+		}
+		// a lone expression, which had better evaluate to a boolean
+		Ok(left)
+	}
+	fn expression(&mut self) -> Result<ExprId, ParseError> {
+		self.bit_or()
+	}
+	fn bit_or(&mut self) -> Result<ExprId, ParseError> {
+		let mut expr = self.bit_xor()?;
+		while self.matches(&[TokenType::PIPE]) {
+			let operator = self.previous();
+			let right = self.bit_xor()?;
+			expr = self.expr(Expr::Binary(expr, operator, right))?;
 		}
-		Err(self.error("Invalid comparison operator"))
+		Ok(expr)
+	}
+	fn bit_xor(&mut self) -> Result<ExprId, ParseError> {
+		let mut expr = self.bit_and()?;
+		while self.matches(&[TokenType::CARET]) {
+			let operator = self.previous();
+			let right = self.bit_and()?;
+			expr = self.expr(Expr::Binary(expr, operator, right))?;
+		}
+		Ok(expr)
+	}
+	fn bit_and(&mut self) -> Result<ExprId, ParseError> {
+		let mut expr = self.shift()?;
+		while self.matches(&[TokenType::AMPERSAND]) {
+			let operator = self.previous();
+			let right = self.shift()?;
+			expr = self.expr(Expr::Binary(expr, operator, right))?;
+		}
+		Ok(expr)
+	}
+	fn shift(&mut self) -> Result<ExprId, ParseError> {
+		let mut expr = self.sum()?;
+		use TokenType::*;
+		while self.matches(&[LESS_LESS, MORE_MORE]) {
+			let operator = self.previous();
+			let right = self.sum()?;
+			expr = self.expr(Expr::Binary(expr, operator, right))?;
+		}
+		Ok(expr)
 	}
-	fn expression(&mut self) -> Result<Expr, ParseError> {
+	fn sum(&mut self) -> Result<ExprId, ParseError> {
 		use TokenType::*;
 
 		let prefix =
@@ -243,40 +615,90 @@ impl Parser {
 
 		let mut expr = self.factor()?;
 		if let Some(p) = prefix {
-			expr = Expr::Unary(p, Box::new(expr));
+			expr = self.expr(Expr::Unary(p, expr))?;
 		}
 
 		while self.matches(&[MINUS, PLUS]) {
 			let operator = self.previous();
 			let right = self.factor()?;
-			expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+			expr = self.expr(Expr::Binary(expr, operator, right))?;
 		}
 		Ok(expr)
 	}
-	fn factor(&mut self) -> Result<Expr, ParseError> {
-		let mut expr = self.primary()?;
+	fn factor(&mut self) -> Result<ExprId, ParseError> {
+		let mut expr = self.unary()?;
 		use TokenType::*;
-		while self.matches(&[STAR, SLASH]) {
+		while self.matches(&[STAR, SLASH, PERCENT, MOD]) {
 			let operator = self.previous();
-			let right = self.primary()?;
-			expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+			let right = self.unary()?;
+			expr = self.expr(Expr::Binary(expr, operator, right))?;
 		}
 		Ok(expr)
 	}
-	fn primary(&mut self) -> Result<Expr, ParseError> {
+	fn unary(&mut self) -> Result<ExprId, ParseError> {
+		if self.matches(&[TokenType::TILDE]) {
+			let operator = self.previous();
+			let operand = self.unary()?;
+			return self.expr(Expr::Unary(operator, operand))
+		}
+		self.power()
+	}
+	fn power(&mut self) -> Result<ExprId, ParseError> {
+		let base = self.primary()?;
 		use TokenType::*;
-		if self.matches(&[NUMBER]) {
-			Ok(Expr::Literal(self.previous()))
+		if self.matches(&[STAR_STAR]) {
+			let operator = self.previous();
+			// right-associative: 2 ** 3 ** 2 == 2 ** 9
+			let exponent = self.power()?;
+			return self.expr(Expr::Binary(base, operator, exponent))
+		}
+		Ok(base)
+	}
+	fn call(&mut self, name: Token) -> Result<ExprId, ParseError> {
+		let args = self.arguments()?;
+		let builtin = Builtin::named(&name.lexeme.as_str());
+		self.expr(Expr::Call(name, builtin, args))
+	}
+	// the argument list following an opening parenthesis
+	fn arguments(&mut self) -> Result<Vec<ExprId>, ParseError> {
+		use TokenType::*;
+		let mut args = Vec::new();
+		if !self.check(RIGHT_PAREN) {
+			loop {
+				args.push(self.expression()?);
+				if !self.matches(&[COMMA]) { break }
+			}
+		}
+		if !self.matches(&[RIGHT_PAREN]) {
+			return Err(self.error("Missing ')' after arguments"))
+		}
+		Ok(args)
+	}
+	fn primary(&mut self) -> Result<ExprId, ParseError> {
+		use TokenType::*;
+		if self.matches(&[NUMBER, TRUE, FALSE]) {
+			let literal = self.previous();
+			self.expr(Expr::Literal(literal))
 		}
 		else if self.matches(&[IDENTIFIER]) {
-			Ok(Expr::Variable(self.previous()))
+			let name = self.previous();
+			let name = self.qualified(name);
+			if self.matches(&[LEFT_PAREN]) {
+				return self.call(name)
+			}
+			if self.is_field_access() {
+				self.advance();
+				let field = self.advance();
+				return self.expr(Expr::Get(name, field))
+			}
+			self.expr(Expr::Variable(name))
 		}
 		else if self.matches(&[LEFT_PAREN]) {
 			let expr = self.expression()?;
 			if !self.matches(&[RIGHT_PAREN]) {
 				return Err(self.error("Missing ')' after expression"))
 			}
-			Ok(Expr::Grouping(Box::new(expr)))
+			self.expr(Expr::Grouping(expr))
 		}
 		else {
 			Err(self.error("Expected an expression"))
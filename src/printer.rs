@@ -0,0 +1,121 @@
+use crate::{parser::Stmt, expressions::Expr, ast::{Ast, ExprId, StmtId}, token::{TokenType, Literal}};
+
+// Writes a tree back out as PL/0 source, e.g. to show what the optimizer made of a program.
+// Comments and the original layout are gone by then, but the output runs the same as the tree.
+pub fn program(ast: &Ast, statements: &[StmtId]) -> String {
+	let lines: Vec<String> = statements.iter()
+		.map(|s| statement(ast, *s, 0))
+		.filter(|text| !text.is_empty())
+		.collect();
+	lines.join("\n") + ".\n"
+}
+
+fn indent(depth: usize) -> String { "\t".repeat(depth) }
+
+// a statement starting at the current position, with lines after the first indented `depth` times
+pub(crate) fn statement(ast: &Ast, s: StmtId, depth: usize) -> String {
+	let list = |items: Vec<String>| items.join(", ");
+	let exprs = |args: &[ExprId]| list(args.iter().map(|a| expression(ast, *a)).collect());
+	match &ast[s] {
+		Stmt::Import(name, _) => format!("import {};", name.lexeme),
+		Stmt::Const(consts) =>
+			format!("const {};", list(consts.iter().map(|(n, v)| format!("{} = {}", n.lexeme, number(*v))).collect())),
+		Stmt::Type(types) => format!("type {};", list(types.iter().map(|(n, fields)| {
+			let fields: Vec<String> = fields.iter().map(|f| f.lexeme.to_string()).collect();
+			format!("{} = record {} end", n.lexeme, fields.join(", "))
+		}).collect())),
+		Stmt::Var(vars) => format!("var {};", list(vars.iter().map(|(n, kind)| match kind {
+			Some(k) => format!("{}: {}", n.lexeme, k.lexeme),
+			None => n.lexeme.to_string(),
+		}).collect())),
+		Stmt::Proc(name, body) => {
+			let mut out = format!("procedure {};", name.lexeme);
+			for s in body {
+				let text = statement(ast, *s, depth + 1);
+				if !text.is_empty() { out += &format!("\n{}{text}", indent(depth + 1)); }
+			}
+			out + ";"
+		},
+
+		Stmt::Print(e) => format!("! {}", expression(ast, *e)),
+		Stmt::PrintVar(name) => format!("? {}", name.lexeme),
+		Stmt::Write(args, true, true) => format!("! {}", exprs(args)),
+		Stmt::Write(args, true, false) if args.is_empty() => "writeln".to_string(),
+		Stmt::Write(args, true, false) => format!("writeln {}", exprs(args)),
+		Stmt::Write(args, false, _) => format!("write {}", exprs(args)),
+		Stmt::Expression(e) => expression(ast, *e),
+		Stmt::Scope(statements) if statements.is_empty() => String::new(),
+		Stmt::Scope(statements) => {
+			let inner: Vec<String> = statements.iter()
+				.map(|s| format!("{}{}", indent(depth + 1), statement(ast, *s, depth + 1)))
+				.collect();
+			format!("begin\n{}\n{}end", inner.join(";\n"), indent(depth))
+		},
+		Stmt::Assign(name, e) => format!("{} := {}", name.lexeme, expression(ast, *e)),
+		Stmt::Set(name, field, e) => format!("{}.{} := {}", name.lexeme, field.lexeme, expression(ast, *e)),
+		Stmt::If(cond, branch) => format!("if {} then {}", expression(ast, *cond), statement(ast, *branch, depth)).trim_end().to_string(),
+		Stmt::While(cond, body) => format!("while {} do {}", expression(ast, *cond), statement(ast, *body, depth)).trim_end().to_string(),
+		Stmt::Call(name, args) if args.is_empty() => format!("call {}", name.lexeme),
+		Stmt::Call(name, args) => format!("call {}({})", name.lexeme, exprs(args)),
+		Stmt::Case(subject, table) => {
+			let mut arms = Vec::new();
+			for (i, arm) in table.arms.iter().enumerate() {
+				let body = statement(ast, *arm, depth + 1);
+				if table.default == Some(i) {
+					arms.push(format!("{}else {body}", indent(depth + 1)));
+					continue
+				}
+				let labels: Vec<String> = table.labels.iter()
+					.filter(|(_, a)| *a == i)
+					.map(|(v, _)| if *v < 0 { format!("-{}", v.unsigned_abs()) } else { v.to_string() })
+					.collect();
+				arms.push(format!("{}{}: {body}", indent(depth + 1), labels.join(", ")));
+			}
+			format!("case {} of\n{}\n{}end", expression(ast, *subject), arms.join(";\n"), indent(depth))
+		},
+	}
+}
+
+pub(crate) fn expression(ast: &Ast, expr: ExprId) -> String {
+	use TokenType::*;
+	match &ast[expr] {
+		Expr::Literal(l) => match &l.literal {
+			Some(Literal::Number(v)) => number(*v),
+			Some(Literal::String(s)) => string(s),
+			_ => l.text().to_string(),
+		},
+		Expr::Grouping(e) => format!("({})", expression(ast, *e)),
+		Expr::Unary(op, e) if op.kind == ODD => format!("odd {}", expression(ast, *e)),
+		Expr::Unary(op, e) => format!("{}{}", op.lexeme, expression(ast, *e)),
+		Expr::Binary(a, op, b) => format!("{} {} {}", expression(ast, *a), op.lexeme, expression(ast, *b)),
+		Expr::Variable(name) => name.lexeme.to_string(),
+		Expr::Get(name, field) => format!("{}.{}", name.lexeme, field.lexeme),
+		Expr::Call(name, _, args) => {
+			let args: Vec<String> = args.iter().map(|a| expression(ast, *a)).collect();
+			format!("{}({})", name.lexeme, args.join(", "))
+		},
+	}
+}
+
+// Negative values come from consts and folding; the language has no negative literals to write them as
+fn number(v: i32) -> String {
+	if v == i32::MIN { format!("(-{} - 1)", i32::MAX) }
+	else if v < 0 { format!("(-{})", -v) }
+	else { v.to_string() }
+}
+
+pub(crate) fn string(s: &str) -> String {
+	let mut out = String::from("\"");
+	for c in s.chars() {
+		match c {
+			'\n' => out.push_str("\\n"),
+			'\t' => out.push_str("\\t"),
+			'\r' => out.push_str("\\r"),
+			'\0' => out.push_str("\\0"),
+			'\\' => out.push_str("\\\\"),
+			'"' => out.push_str("\\\""),
+			c => out.push(c),
+		}
+	}
+	out + "\""
+}
@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{parser::Stmt, symbol::Symbol, expressions::{Expr, Slot}, ast::{Ast, ExprId, StmtId, NodeMap}, token::{Token, Literal}, natives::NativeProcedure, error::error};
+
+// Names declared by a module, or by one procedure: variables with the index
+// of their slot, anything else with None, as it still shadows outer variables
+#[derive(Default)]
+struct Block {
+	names: HashMap<Symbol, Option<usize>>,
+	slots: usize,
+}
+
+// Where each variable access was resolved to: `Variable` and `Get` expressions,
+// and `PrintVar`, `Assign` and `Set` statements. Nodes missing from it are looked up by name.
+#[derive(Default)]
+pub struct Slots {
+	pub exprs: NodeMap<ExprId, Slot>,
+	pub stmts: NodeMap<StmtId, Slot>,
+}
+
+// Pass run after type checking, pointing every variable access at the slot the
+// interpreter will find the variable in, so that it does not look it up by name.
+// Names it cannot place, like module members and host globals, are left unresolved.
+// Scoping is lexical: a name a procedure uses must be declared where the procedure is,
+// by the host, or as a native procedure; any other is reported as undeclared.
+pub struct Resolver<'a> {
+	ast: &'a Ast,
+	blocks: Vec<Block>,
+	slots: Slots,
+	host: HashSet<Symbol>, // globals and consts seeded by the host, seen only by the program
+	natives: HashSet<Symbol>,
+	is_program: bool,
+}
+impl<'a> Resolver<'a> {
+	pub fn new(ast: &'a Ast) -> Self {
+		Resolver { ast, blocks: Vec::new(), slots: Slots::default(), host: HashSet::new(), natives: HashSet::new(), is_program: false }
+	}
+	pub fn with_host_names(mut self, names: impl IntoIterator<Item = Symbol>) -> Self {
+		self.host = names.into_iter().collect();
+		self
+	}
+	pub fn with_natives(mut self, natives: &[NativeProcedure]) -> Self {
+		self.natives = natives.iter().map(|n| Symbol::intern(&n.name)).collect();
+		self
+	}
+	// Resolves the statements of one module, whose variables live in an environment of their own;
+	// `is_program` for the program itself, the last module, which sees the host's names
+	pub fn resolve(&mut self, statements: &[StmtId], is_program: bool) {
+		self.blocks = vec![Block::default()];
+		self.is_program = is_program;
+		self.declarations(statements);
+		self.statements(statements);
+	}
+	pub fn into_slots(self) -> Slots { self.slots }
+
+	fn declare(&mut self, name: &Token, is_var: bool) {
+		let block = self.blocks.last_mut().unwrap();
+		let slot = if is_var { block.slots += 1; Some(block.slots - 1) } else { None };
+		block.names.insert(name.lexeme, slot);
+	}
+	fn lookup(&self, name: &Token) -> Option<Slot> {
+		self.blocks.iter().rev().enumerate()
+			.find_map(|(depth, b)| b.names.get(&name.lexeme).map(|slot| slot.map(|index| Slot { depth, index })))
+			.flatten()
+	}
+	fn place(&mut self, s: StmtId, name: &Token) {
+		self.check(name, "variable");
+		if let Some(slot) = self.lookup(name) { self.slots.stmts.insert(s, slot); }
+	}
+	// Reports a name declared nowhere the code using it can see
+	fn check(&self, name: &Token, what: &str) {
+		if matches!(name.literal, Some(Literal::Qualified(..))) { return } // checked by the parser
+		let declared = self.blocks.iter().any(|b| b.names.contains_key(&name.lexeme))
+			|| (self.is_program && self.host.contains(&name.lexeme))
+			|| (what == "procedure" && self.natives.contains(&name.lexeme));
+		if !declared {
+			error(name.clone(), format!("Undeclared {what}"));
+		}
+	}
+
+	// Declares a block's names before its procedures are walked, so that they may call ones declared later
+	fn declarations(&mut self, statements: &[StmtId]) {
+		let ast = self.ast;
+		for s in statements {
+			match &ast[*s] {
+				Stmt::Proc(name, _) => self.declare(name, false),
+				Stmt::Const(consts) => for (name, _) in consts { self.declare(name, false); },
+				Stmt::Var(names) => for (name, _) in names { self.declare(name, true); },
+				_ => {},
+			}
+		}
+	}
+
+	fn statements(&mut self, statements: &[StmtId]) {
+		for s in statements { self.statement(*s); }
+	}
+	fn statement(&mut self, s: StmtId) {
+		let ast = self.ast;
+		match &ast[s] {
+			Stmt::Proc(_, body) => {
+				self.blocks.push(Block::default());
+				self.declarations(body);
+				self.statements(body);
+				self.blocks.pop();
+			},
+			Stmt::Const(_) | Stmt::Var(_) | Stmt::Type(_) | Stmt::Import(..) => {},
+			Stmt::Print(e) | Stmt::Expression(e) => self.expression(*e),
+			Stmt::PrintVar(name) => self.place(s, name),
+			Stmt::Write(args, _, _) => {
+				for a in args { self.expression(*a); }
+			},
+			Stmt::Call(name, args) => {
+				self.check(name, "procedure");
+				for a in args { self.expression(*a); }
+			},
+			Stmt::Scope(statements) => self.statements(statements),
+			Stmt::Assign(name, e) | Stmt::Set(name, _, e) => {
+				self.expression(*e);
+				self.place(s, name);
+			},
+			Stmt::If(cond, branch) | Stmt::While(cond, branch) => {
+				self.expression(*cond);
+				self.statement(*branch);
+			},
+			Stmt::Case(subject, table) => {
+				self.expression(*subject);
+				self.statements(&table.arms);
+			},
+		}
+	}
+
+	fn expression(&mut self, expr: ExprId) {
+		let ast = self.ast;
+		match &ast[expr] {
+			Expr::Literal(_) => {},
+			Expr::Grouping(e) | Expr::Unary(_, e) => self.expression(*e),
+			Expr::Binary(a, _, b) => {
+				self.expression(*a);
+				self.expression(*b);
+			},
+			Expr::Variable(name) | Expr::Get(name, _) => {
+				self.check(name, "variable");
+				if let Some(slot) = self.lookup(name) { self.slots.exprs.insert(expr, slot); }
+			},
+			Expr::Call(name, builtin, args) => {
+				if builtin.is_none() { self.check(name, "procedure"); }
+				for a in args { self.expression(*a); }
+			},
+		}
+	}
+}
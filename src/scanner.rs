@@ -1,26 +1,34 @@
-use crate::token::*;
+use std::rc::Rc;
+
+use crate::{token::*, symbol::Symbol, error::error_at};
 
 pub struct Scanner {
 	source: String,
+	file: Rc<str>,
 	tokens: Vec<Token>,
 	start: usize,
 	current: usize,
 	line: usize,
-	failed: bool,
+	line_start: usize, // where the current line begins
+	comments: bool, // whether `#` comments become COMMENT tokens, or are skipped
 }
 impl Scanner {
-	pub fn new(source: String) -> Self {
+	pub fn new(source: String, file: Rc<str>) -> Self {
 		Scanner {
-			source,
+			source, file,
 			tokens: vec![],
-			start: 0, current: 0, line: 1,
-			failed: false,
+			start: 0, current: 0, line: 1, line_start: 0,
+			comments: false,
 		}
 	}
+	// Keeps comments as COMMENT tokens, for tools that write the source back out
+	pub fn with_comments(mut self) -> Self {
+		self.comments = true;
+		self
+	}
 
-	fn error(&mut self, line: usize, msg: String) {
-		eprintln!("{line}: {msg}");
-		self.failed = true;
+	fn error(&self, line: usize, msg: String) {
+		error_at(self.file.clone(), line, msg);
 	}
 	
 	fn is_at_end(&self) -> bool {
@@ -35,8 +43,18 @@ impl Scanner {
 		self.add_token_full(None, kind)
 	}
 	fn add_token_full(&mut self, literal: Option<Literal>, kind: TokenType) {
-		let lex = &self.source[self.start..self.current];
-		self.tokens.push(Token::new(kind, lex.to_string(), literal, self.line))
+		let text = &self.source[self.start..self.current];
+		let column = self.column();
+		let token = match kind {
+			TokenType::NUMBER | TokenType::STRING | TokenType::COMMENT => Token::with_raw(kind, text, literal, self.line, column, self.file.clone()),
+			_ => Token::new(kind, Symbol::intern(text), literal, self.line, column, self.file.clone()),
+		};
+		self.tokens.push(token)
+	}
+	fn column(&self) -> usize {
+		// a string running over several lines has no single column on the line it is reported at
+		if self.start < self.line_start { return 0 }
+		self.source[self.line_start..self.start].chars().count() + 1
 	}
 
 	fn matches(&mut self, expected: char) -> bool {
@@ -51,14 +69,18 @@ impl Scanner {
 		self.source.as_bytes()[self.current] as char
 	}
 
-	fn is_digit(c : char) -> bool { '0' <= c && c <= '9' }
-	fn is_alpha(c : char) -> bool { ('a' <= c && c <= 'z') || ('A' <= c && c <= 'Z') || c == '_' }
+	fn is_digit(c : char) -> bool { c.is_ascii_digit() }
+	fn is_alpha(c : char) -> bool { c.is_ascii_alphabetic() || c == '_' }
 	fn is_alphanumeric(c : char) -> bool { Self::is_alpha(c) || Self::is_digit(c) }
 	fn keyword(s: &str) -> Option<TokenType> {
 		use TokenType::*;
 		match s {
 			"var" => Some(VAR),
 			"const" => Some(CONST),
+			"import" => Some(IMPORT),
+			"export" => Some(EXPORT),
+			"type" => Some(TYPE),
+			"record" => Some(RECORD),
 			"begin" => Some(BEGIN),
 			"end" => Some(END),
 			"while" => Some(WHILE),
@@ -68,6 +90,14 @@ impl Scanner {
 			"procedure" => Some(PROCEDURE),
 			"call" => Some(CALL),
 			"odd" => Some(ODD),
+			"true" => Some(TRUE),
+			"false" => Some(FALSE),
+			"mod" => Some(MOD),
+			"case" => Some(CASE),
+			"of" => Some(OF),
+			"else" => Some(ELSE),
+			"write" => Some(WRITE),
+			"writeln" => Some(WRITELN),
 			_ => None
 		}
 	}
@@ -85,14 +115,59 @@ impl Scanner {
 
 		self.add_token_full(Some(Literal::Number(lit.unwrap_or(-1))), TokenType::NUMBER);
 	}
+	fn string(&mut self) {
+		let line = self.line; // where the string opens, should it never close
+		// collect raw bytes, so that non-ASCII text survives the byte-wise scanning
+		let mut text: Vec<u8> = Vec::new();
+		while self.peek() != '"' && !self.is_at_end() {
+			let c = self.advance();
+			match c {
+				'\\' => {
+					if self.is_at_end() { break }
+					let escaped = match self.advance() {
+						'n' => '\n',
+						't' => '\t',
+						'r' => '\r',
+						'0' => '\0',
+						'\\' => '\\',
+						'"' => '"',
+						o => {
+							self.error(self.line, format!("Unknown escape sequence: \\{o}"));
+							o
+						},
+					};
+					text.push(escaped as u8);
+				},
+				'\n' => {
+					self.line += 1;
+					self.line_start = self.current;
+					text.push(b'\n');
+				},
+				o => text.push(o as u8),
+			}
+		}
+
+		if self.is_at_end() {
+			self.error(line, "Unterminated string literal".to_string());
+			return
+		}
+		self.advance(); // closing quote
+
+		let lit = String::from_utf8_lossy(&text).into_owned();
+		self.add_token_full(Some(Literal::String(lit)), TokenType::STRING);
+	}
 	fn identifier(&mut self) {
 		while Self::is_alphanumeric(self.peek()) && !self.is_at_end() { self.advance(); }
 
 		let substr = &self.source.as_str()[self.start..self.current];
 		if let Some(keyword) = Self::keyword(substr) {
-			self.add_token(keyword);
+			match keyword {
+				TokenType::TRUE => self.add_token_full(Some(Literal::Boolean(true)), keyword),
+				TokenType::FALSE => self.add_token_full(Some(Literal::Boolean(false)), keyword),
+				_ => self.add_token(keyword),
+			}
 		} else {
-			self.add_token_full(Some(Literal::Identifier(substr.to_string())), TokenType::IDENTIFIER);
+			self.add_token_full(Some(Literal::Identifier(Symbol::intern(substr))), TokenType::IDENTIFIER);
 		}
 	}
 
@@ -107,37 +182,54 @@ impl Scanner {
 			'.' => self.add_token(DOT),
 			'+' => self.add_token(PLUS),
 			'-' => self.add_token(MINUS),
-			'*' => self.add_token(STAR),
+			'*' => {
+				let t = if self.matches('*') { STAR_STAR } else { STAR };
+				self.add_token(t);
+			},
+			'%' => self.add_token(PERCENT),
+			'&' => self.add_token(AMPERSAND),
+			'|' => self.add_token(PIPE),
+			'^' => self.add_token(CARET),
+			'~' => self.add_token(TILDE),
 			'/' => self.add_token(SLASH),
 			'?' => self.add_token(QMARK),
+			'"' => self.string(),
 			'#' => {
-				while self.peek() != '\n' { self.advance(); }
+				while self.peek() != '\n' && !self.is_at_end() { self.advance(); }
+				if self.comments { self.add_token(COMMENT); }
 			},
 			'!' => {
 				let t = if self.matches('=') { BANG_EQU } else { BANG };
 				self.add_token(t);
 			},
 			':' => {
-				if self.matches('=') { self.add_token(COLON_EQU); }
-				else {
-					self.error(self.line, "Invalid token; Expected `:=`".to_string());
-				}
+				let t = if self.matches('=') { COLON_EQU } else { COLON };
+				self.add_token(t);
 			},
 			'=' => {
 				let t = if self.matches('=') { EQU_EQU } else { EQU };
 				self.add_token(t);
 			},
 			'>' => {
-				let t = if self.matches('=') { MORE_EQU } else { MORE };
+				let t =
+					if self.matches('=') { MORE_EQU }
+					else if self.matches('>') { MORE_MORE }
+					else { MORE };
 				self.add_token(t);
 			},
 			'<' => {
-				let t = if self.matches('=') { LESS_EQU } else { LESS };
+				let t =
+					if self.matches('=') { LESS_EQU }
+					else if self.matches('<') { LESS_LESS }
+					else { LESS };
 				self.add_token(t);
 			},
 
 			' ' | '\r' | '\t' => (),
-			'\n' => self.line += 1,
+			'\n' => {
+				self.line += 1;
+				self.line_start = self.current;
+			},
 
 			o => {
 				if Self::is_digit(o) {
@@ -155,7 +247,9 @@ impl Scanner {
 			self.start = self.current;
 			self.scan_token();
 		}
-		self.tokens.push(Token::new(TokenType::EOF, "".to_string(), None, self.line));
-		self.tokens.clone()
+		self.start = self.current;
+		let column = self.column();
+		self.tokens.push(Token::new(TokenType::EOF, Symbol::EMPTY, None, self.line, column, self.file.clone()));
+		std::mem::take(&mut self.tokens)
 	}
 }
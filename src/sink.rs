@@ -0,0 +1,44 @@
+use std::{rc::Rc, cell::RefCell, io::Write};
+
+/// Where a running program's output goes.
+///
+/// Besides [`Stdout`] and [`Buffer`], any `FnMut(&str)` closure is a sink.
+pub trait Sink {
+	fn write(&mut self, text: &str) -> std::io::Result<()>;
+}
+
+/// Writes to the process' standard output, flushing after every write
+/// so that prompts show up before the program reads input.
+pub struct Stdout;
+impl Sink for Stdout {
+	fn write(&mut self, text: &str) -> std::io::Result<()> {
+		let mut out = std::io::stdout().lock();
+		out.write_all(text.as_bytes())?;
+		out.flush()
+	}
+}
+
+/// Collects output in memory. Clones share the same text, so a host can
+/// keep one clone and hand the other to the interpreter.
+#[derive(Clone, Default)]
+pub struct Buffer(Rc<RefCell<String>>);
+impl Buffer {
+	pub fn new() -> Self { Self::default() }
+	/// Everything written so far.
+	pub fn contents(&self) -> String { self.0.borrow().clone() }
+	/// Everything written so far, leaving the buffer empty.
+	pub fn take(&self) -> String { std::mem::take(&mut self.0.borrow_mut()) }
+}
+impl Sink for Buffer {
+	fn write(&mut self, text: &str) -> std::io::Result<()> {
+		self.0.borrow_mut().push_str(text);
+		Ok(())
+	}
+}
+
+impl<F: FnMut(&str)> Sink for F {
+	fn write(&mut self, text: &str) -> std::io::Result<()> {
+		self(text);
+		Ok(())
+	}
+}
@@ -0,0 +1,29 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::{input::{Input, Stdin}, natives::NativeProcedure, symbol::Symbol};
+
+// The standard library, imported with `import std;`: PL/0 procedures embedded
+// into the binary, plus the native primitives below, which PL/0 cannot express.
+// There are no array helpers (sorting, searching, filling): PL/0 has no arrays.
+pub const NAME: &str = "std";
+pub const FILE: &str = "<std>";
+pub const SOURCE: &str = include_str!("std.pl0");
+
+// `std.read` reads a line of `input` per call
+pub fn natives(input: Box<dyn Input>) -> Vec<NativeProcedure> {
+	let input = RefCell::new(input);
+	vec![
+		NativeProcedure::new("std.read", 0, move |_| {
+			let line = input.borrow_mut().next_line().map_err(|e| e.to_string())?;
+			let Some(line) = line else { return Err("Unexpected end of input".to_string()) };
+			line.trim().parse::<i32>().map(Some).map_err(|_| format!("Not a number: {}", line.trim()))
+		}),
+	]
+}
+
+// the primitives, in the form modules list their exports
+pub fn primitives() -> HashMap<Symbol, Option<i32>> {
+	natives(Box::new(Stdin)).into_iter()
+		.map(|n| (n.name.trim_start_matches("std.").into(), None))
+		.collect()
+}
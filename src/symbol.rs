@@ -0,0 +1,52 @@
+use std::{cell::RefCell, collections::HashMap, fmt, marker::PhantomData, rc::Rc};
+
+/// An interned string, used for identifiers, keywords and operators.
+/// Symbols are copied, compared and hashed like the integers they are;
+/// equal strings always intern to the same symbol.
+///
+/// Each thread interns into a table of its own, so reading a symbol takes no lock,
+/// and symbols stay on the thread that made them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32, PhantomData<*const ()>);
+
+// The strings are shared with whoever reads them, and freed with the thread's table
+struct Interner {
+	ids: HashMap<Rc<str>, Symbol>,
+	strings: Vec<Rc<str>>,
+}
+thread_local! {
+	static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+		ids: HashMap::from([("".into(), Symbol::EMPTY)]),
+		strings: vec!["".into()],
+	});
+}
+
+impl Symbol {
+	/// The empty string, which every interner starts with.
+	pub const EMPTY: Symbol = Symbol(0, PhantomData);
+	pub fn intern(text: &str) -> Self {
+		INTERNER.with_borrow_mut(|interner| {
+			if let Some(symbol) = interner.ids.get(text) { return *symbol }
+			let text: Rc<str> = text.into();
+			let symbol = Symbol(interner.strings.len() as u32, PhantomData);
+			interner.strings.push(text.clone());
+			interner.ids.insert(text, symbol);
+			symbol
+		})
+	}
+	pub fn as_str(self) -> Rc<str> {
+		INTERNER.with_borrow(|interner| interner.strings[self.0 as usize].clone())
+	}
+}
+impl From<&str> for Symbol {
+	fn from(text: &str) -> Self { Self::intern(text) }
+}
+impl PartialEq<&str> for Symbol {
+	fn eq(&self, text: &&str) -> bool { *self.as_str() == **text }
+}
+impl fmt::Display for Symbol {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(&self.as_str()) }
+}
+impl fmt::Debug for Symbol {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{:?}", self.as_str()) }
+}
@@ -1,9 +1,15 @@
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-#[allow(non_camel_case_types)]
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 pub enum TokenType {
 	LEFT_PAREN, RIGHT_PAREN,
-	SEMICOLON, COMMA, DOT,
+	SEMICOLON, COLON, COMMA, DOT,
 	PLUS, MINUS, STAR, SLASH,
+	PERCENT, STAR_STAR,
+	AMPERSAND, PIPE, CARET, TILDE,
 
 	BANG, // ! - exclamation mark
 	QMARK, // ? - question mark
@@ -11,35 +17,55 @@ pub enum TokenType {
 	BANG_EQU,
 	COLON_EQU,
 	EQU, EQU_EQU,
-	MORE, MORE_EQU,
-	LESS, LESS_EQU,
+	MORE, MORE_EQU, MORE_MORE,
+	LESS, LESS_EQU, LESS_LESS,
 
-	IDENTIFIER, NUMBER,
+	IDENTIFIER, NUMBER, STRING,
+	COMMENT, // only scanned when asked for
 
 	CONST, VAR,
+	IMPORT, EXPORT,
+	TYPE, RECORD,
 	BEGIN, END,
 	WHILE, DO,
 	IF, THEN,
 	PROCEDURE, CALL,
-	ODD,
+	ODD, MOD,
+	TRUE, FALSE,
+	CASE, OF, ELSE,
+	WRITE, WRITELN,
 
 	EOF
 }
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Literal {
 	Number(i32),
-	Identifier(String),
+	Identifier(Symbol),
+	Qualified(Symbol, Symbol), // `module.member`, split when parsed so that running needs no string work
+	String(String),
+	Boolean(bool),
 }
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Token {
 	pub kind: TokenType,
-	pub lexeme: String,
+	pub lexeme: Symbol, // interned for names, keywords and operators; empty for the tokens `raw` is kept for
+	pub raw: Option<Rc<str>>, // numbers, strings and comments as written, kept out of the interner so they are freed with the token
 	pub literal: Option<Literal>,
 	pub line: usize,
+	pub column: usize, // counted in characters from 1; 0 where unknown, e.g. for names the host made up
+	pub file: Rc<str>, // source file the token was scanned from
 }
 impl Token {
-	pub fn new(kind: TokenType, lexeme: String, literal: Option<Literal>, line: usize) -> Self {
-		Token { kind, lexeme, literal, line }
+	pub fn new(kind: TokenType, lexeme: Symbol, literal: Option<Literal>, line: usize, column: usize, file: Rc<str>) -> Self {
+		Token { kind, lexeme, raw: None, literal, line, column, file }
+	}
+	// A number, string or comment token, holding its text itself
+	pub fn with_raw(kind: TokenType, raw: &str, literal: Option<Literal>, line: usize, column: usize, file: Rc<str>) -> Self {
+		Token { raw: Some(raw.into()), ..Token::new(kind, Symbol::EMPTY, literal, line, column, file) }
+	}
+	// The token as written in the source
+	pub fn text(&self) -> Rc<str> {
+		self.raw.clone().unwrap_or_else(|| self.lexeme.as_str())
 	}
 }
 
@@ -0,0 +1,314 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{token::{Token, TokenType, Literal}, symbol::Symbol, parser::Stmt, expressions::Expr, ast::{Ast, ExprId, StmtId}, builtins::Builtin, natives::NativeProcedure, error::error};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Type {
+	Integer,
+	Boolean,
+	Str,
+	Record(Symbol),
+	Any, // variables assigned more than one type, and names the checker cannot see
+}
+impl Type {
+	// names usable after `:` in a var declaration, besides record types
+	pub fn builtin(name: &str) -> Option<Self> {
+		match name {
+			"integer" => Some(Self::Integer),
+			"boolean" => Some(Self::Boolean),
+			_ => None,
+		}
+	}
+	// Like `builtin`, comparing symbols rather than strings, for use while the program runs
+	pub fn builtin_symbol(name: Symbol) -> Option<Self> {
+		// symbols belong to the thread that interned them
+		thread_local! { static NAMES: [Symbol; 2] = ["integer", "boolean"].map(Symbol::intern); }
+		let [integer, boolean] = NAMES.with(|names| *names);
+		if name == integer { Some(Self::Integer) }
+		else if name == boolean { Some(Self::Boolean) }
+		else { None }
+	}
+}
+impl Display for Type {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Type::Integer => f.write_str("integer"),
+			Type::Boolean => f.write_str("boolean"),
+			Type::Str => f.write_str("string"),
+			Type::Record(name) => f.write_str(&format!("record `{name}`")),
+			Type::Any => f.write_str("any"),
+		}
+	}
+}
+
+// an unannotated variable, by its declaration and place in it
+type Untyped = (StmtId, usize);
+
+#[derive(Clone)]
+enum Name {
+	Const,
+	Var(Type),
+	Untyped(Untyped), // typed by what is assigned to it
+	Proc,
+	RecordType(Vec<Symbol>),
+}
+impl Name {
+	fn what(&self) -> &'static str {
+		match self {
+			Name::Const => "const",
+			Name::Var(_) | Name::Untyped(_) => "variable",
+			Name::Proc => "procedure",
+			Name::RecordType(_) => "type",
+		}
+	}
+}
+
+// Static pass run between parsing and execution; mismatches are reported
+// through `error::error`, so they stop the program like parse errors do.
+// An unannotated variable has the type of everything assigned to it,
+// or `Any` when that is more than one type.
+pub struct TypeChecker<'a> {
+	ast: &'a Ast,
+	scopes: Vec<HashMap<Symbol, Name>>,
+	natives: HashMap<Symbol, usize>, // arity of each native procedure
+	exported: bool, // whether importing modules may assign the top-level variables
+	inferred: HashMap<Untyped, Type>,
+	inferring: bool, // errors wait for the types of unannotated variables to settle
+	pending: bool, // an unannotated variable was read before anything was found assigned to it
+}
+impl<'a> TypeChecker<'a> {
+	pub fn new(ast: &'a Ast) -> Self {
+		TypeChecker { ast, scopes: vec![HashMap::new()], natives: HashMap::new(), exported: false, inferred: HashMap::new(), inferring: false, pending: false }
+	}
+	// Checks calls to the host's procedures against the number of arguments they take
+	pub fn with_natives(mut self, natives: &[NativeProcedure]) -> Self {
+		self.natives = natives.iter().map(|n| (Symbol::intern(&n.name), n.arity)).collect();
+		self
+	}
+	// For modules others import, whose top-level variables may be assigned anything from outside
+	pub fn exported(mut self) -> Self {
+		self.exported = true;
+		self
+	}
+	pub fn check(&mut self, statements: &[StmtId]) {
+		// assignments may come after the reads they type, so go over the program until nothing changes
+		self.inferring = true;
+		loop {
+			let before = self.inferred.clone();
+			self.scopes = vec![HashMap::new()];
+			self.block(statements);
+			if self.inferred == before { break }
+		}
+		self.inferring = false;
+		self.scopes = vec![HashMap::new()];
+		self.block(statements);
+	}
+	fn block(&mut self, statements: &[StmtId]) {
+		for s in statements { self.statement(*s); }
+	}
+
+	fn error(&self, token: &Token, message: String) {
+		if !self.inferring { error(token.clone(), message); }
+	}
+	fn declare(&mut self, name: &Token, kind: Name) {
+		// names of different kinds may coincide, as the interpreter keeps each kind apart
+		let what = kind.what();
+		let previous = self.scopes.last_mut().unwrap().insert(name.lexeme, kind);
+		if previous.is_some_and(|p| p.what() == what) {
+			self.error(name, format!("Duplicate {what} name"));
+		}
+	}
+	fn lookup(&self, name: &Token) -> Option<Name> {
+		self.scopes.iter().rev().find_map(|s| s.get(&name.lexeme)).cloned()
+	}
+	fn expect(&mut self, expr: ExprId, expected: Type) {
+		let found = self.expression(expr);
+		if found != Type::Any && found != expected {
+			self.error(self.ast.expr_token(expr), format!("Expected {expected}, found {found}"));
+		}
+	}
+	fn assigned(&mut self, var: Untyped, e: ExprId) {
+		self.pending = false;
+		let found = self.expression(e);
+		// what an unannotated variable holds is not known yet; the next time over it may be
+		if found == Type::Any && self.pending { return }
+		let t = match self.inferred.get(&var) {
+			Some(t) if *t != found => Type::Any,
+			_ => found,
+		};
+		self.inferred.insert(var, t);
+	}
+	fn arity(&self, name: &Token, builtin: Option<Builtin>, args: usize) {
+		let expected = match builtin {
+			Some(b) => b.arity(),
+			None => match self.natives.get(&name.lexeme) {
+				Some(n) => *n,
+				None => return,
+			},
+		};
+		if args != expected {
+			self.error(name, format!("`{}` expects {expected} argument(s), got {args}", name.lexeme));
+		}
+	}
+
+	fn statement(&mut self, s: StmtId) {
+		let ast = self.ast;
+		match &ast[s] {
+			Stmt::Proc(name, body) => {
+				self.declare(name, Name::Proc);
+				self.scopes.push(HashMap::new());
+				self.block(body);
+				self.scopes.pop();
+			},
+			Stmt::Const(consts) => {
+				for (name, _) in consts { self.declare(name, Name::Const); }
+			},
+			Stmt::Type(types) => {
+				for (name, fields) in types {
+					let fields = fields.iter().map(|f| f.lexeme).collect();
+					self.declare(name, Name::RecordType(fields));
+				}
+			},
+			Stmt::Var(names) => {
+				for (i, (name, kind)) in names.iter().enumerate() {
+					let t = match kind {
+						None if self.exported && self.scopes.len() == 1 => Name::Var(Type::Any),
+						None => Name::Untyped((s, i)),
+						Some(k) => Name::Var(match (Type::builtin(&k.lexeme.as_str()), self.lookup(k)) {
+							(Some(t), _) => t,
+							(None, Some(Name::RecordType(_))) => Type::Record(k.lexeme),
+							_ => {
+								self.error(k, "Unknown type".to_string());
+								Type::Any
+							},
+						}),
+					};
+					self.declare(name, t);
+				}
+			},
+			Stmt::Print(e) | Stmt::Expression(e) => { self.expression(*e); },
+			Stmt::PrintVar(..) | Stmt::Import(..) => {},
+			Stmt::Write(args, _, _) => {
+				for a in args { self.expression(*a); }
+			},
+			Stmt::Scope(statements) => self.block(statements),
+			Stmt::Assign(name, e) => {
+				let target = self.lookup(name);
+				if let Some(Name::Untyped(var)) = target { return self.assigned(var, *e) }
+				let found = self.expression(*e);
+				match target {
+					Some(Name::Var(Type::Record(_))) =>
+						self.error(name, "Cannot assign to a whole record".to_string()),
+					Some(Name::Var(t)) if t != Type::Any && found != Type::Any && t != found =>
+						self.error(name, format!("Cannot assign {found} to a variable of type {t}")),
+					Some(Name::Var(_) | Name::Untyped(_)) => {},
+					Some(Name::Const) => self.error(name, "Cannot assign to a const".to_string()),
+					Some(_) => self.error(name, "Cannot assign to a non-variable".to_string()),
+					None => {},
+				}
+			},
+			Stmt::Set(name, field, e) => {
+				self.field(name, field);
+				self.expect(*e, Type::Integer);
+			},
+			Stmt::If(cond, branch) | Stmt::While(cond, branch) => {
+				self.expect(*cond, Type::Boolean);
+				self.statement(*branch);
+			},
+			Stmt::Call(name, args) => {
+				match self.lookup(name) {
+					Some(Name::Proc) if !args.is_empty() =>
+						self.error(name, "Procedures take no arguments".to_string()),
+					Some(Name::Proc) => {},
+					None => self.arity(name, None, args.len()),
+					Some(_) => self.error(name, "Not a procedure".to_string()),
+				}
+				for a in args { self.expect(*a, Type::Integer); }
+			},
+			Stmt::Case(subject, table) => {
+				self.expect(*subject, Type::Integer);
+				for arm in &table.arms { self.statement(*arm); }
+			},
+		}
+	}
+
+	fn field(&mut self, name: &Token, field: &Token) {
+		match self.lookup(name) {
+			Some(Name::Var(Type::Record(kind))) => {
+				let known = self.scopes.iter().rev().find_map(|s| s.get(&kind));
+				if let Some(Name::RecordType(fields)) = known {
+					if !fields.contains(&field.lexeme) {
+						self.error(field, format!("Record type `{kind}` has no such field"));
+					}
+				}
+			},
+			// an unannotated variable is found not to be a record only when run
+			Some(Name::Var(Type::Any) | Name::Untyped(_)) | None => {},
+			Some(_) => self.error(name, "Not a record".to_string()),
+		}
+	}
+
+	fn expression(&mut self, expr: ExprId) -> Type {
+		use TokenType::*;
+		let ast = self.ast;
+		match &ast[expr] {
+			Expr::Literal(l) => match &l.literal {
+				Some(Literal::Number(_)) => Type::Integer,
+				Some(Literal::Boolean(_)) => Type::Boolean,
+				Some(Literal::String(_)) => Type::Str,
+				_ => Type::Any,
+			},
+			Expr::Grouping(e) => self.expression(*e),
+			Expr::Unary(op, e) => {
+				self.expect(*e, Type::Integer);
+				if op.kind == ODD { Type::Boolean } else { Type::Integer }
+			},
+			Expr::Binary(a, op, b) if matches!(op.kind, EQU_EQU | BANG_EQU) => {
+				let (ta, tb) = (self.expression(*a), self.expression(*b));
+				for (e, t) in [(a, &ta), (b, &tb)] {
+					if !matches!(t, Type::Integer | Type::Boolean | Type::Any) {
+						self.error(ast.expr_token(*e), format!("Cannot compare {t}"));
+					}
+				}
+				if ta != Type::Any && tb != Type::Any && ta != tb {
+					self.error(op, format!("Cannot compare {ta} with {tb}"));
+				}
+				Type::Boolean
+			},
+			Expr::Binary(a, op, b) => {
+				self.expect(*a, Type::Integer);
+				self.expect(*b, Type::Integer);
+				match op.kind {
+					LESS | LESS_EQU | MORE | MORE_EQU => Type::Boolean,
+					_ => Type::Integer,
+				}
+			},
+			Expr::Variable(name) => match self.lookup(name) {
+				Some(Name::Const) => Type::Integer,
+				Some(Name::Var(Type::Record(_))) => {
+					self.error(name, "Record used as a value".to_string());
+					Type::Any
+				},
+				Some(Name::Var(t)) => t,
+				Some(Name::Untyped(var)) => self.inferred.get(&var).cloned().unwrap_or_else(|| {
+					self.pending = true;
+					Type::Any
+				}),
+				Some(_) => {
+					self.error(name, "Not a value".to_string());
+					Type::Any
+				},
+				None => Type::Any,
+			},
+			Expr::Get(name, field) => {
+				self.field(name, field);
+				Type::Integer
+			},
+			Expr::Call(name, builtin, args) => {
+				self.arity(name, *builtin, args.len());
+				for a in args { self.expect(*a, Type::Integer); }
+				Type::Integer
+			},
+		}
+	}
+}
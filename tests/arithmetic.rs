@@ -0,0 +1,57 @@
+use plz::{sink::Buffer, token::TokenType, Diagnostics, Options, Parser, Scanner, Stmt};
+
+// An expression as the parser groups it
+fn parsed(expression: &str) -> String {
+	let tokens = Scanner::new(format!("! {expression}."), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	let ast = parser.into_ast();
+	let Stmt::Print(e) = ast[program[0]] else { panic!("expected a print") };
+	ast.show(e)
+}
+// What a program prints, or the runtime error it stops with
+fn run(source: &str) -> Result<String, String> {
+	let buffer = Buffer::new();
+	match plz::run_source(source, Options { output: Box::new(buffer.clone()), ..Default::default() }) {
+		Ok(_) => Ok(buffer.contents()),
+		Err(Diagnostics::Runtime(e)) => Err(e.to_string()),
+		Err(e) => panic!("{e}"),
+	}
+}
+
+#[test]
+fn remainder_and_exponent_are_scanned() {
+	let kinds: Vec<TokenType> = Scanner::new("a % b mod c ** d * e".to_string(), "<input>".into()).scan_tokens().iter().map(|t| t.kind).collect();
+	use TokenType::*;
+	assert_eq!(kinds, [IDENTIFIER, PERCENT, IDENTIFIER, MOD, IDENTIFIER, STAR_STAR, IDENTIFIER, STAR, IDENTIFIER, EOF]);
+}
+
+#[test]
+fn exponent_binds_tighter_than_multiplication_and_to_the_right() {
+	assert_eq!(parsed("2 * 3 ** 2"), "(* 2 (** 3 2))");
+	assert_eq!(parsed("2 ** 3 ** 2"), "(** 2 (** 3 2))");
+	assert_eq!(parsed("-2 ** 2"), "(- (** 2 2))");
+	assert_eq!(parsed("a % b mod c"), "(mod (% `a` `b`) `c`)");
+}
+
+#[test]
+fn remainder_exponent_and_built_ins() {
+	let out = run("var a, b; begin a := 17; b := 5; ! a % b, \" \", a mod b, \" \", -a % b, \" \", 2 ** 10 end.");
+	assert_eq!(out, Ok("> 2 2 -2 1024\n".to_string()));
+	let out = run("var x; begin x := -7; ! abs(x), \" \", min(x, 3), \" \", max(x, 3), \" \", sqrt(50), \" \", gcd(-12, 18), \" \", sign(x) end.");
+	assert_eq!(out, Ok("> 7 -7 3 7 6 -1\n".to_string()));
+}
+
+#[test]
+fn math_errors_stop_the_program_where_they_happen() {
+	let cases = [
+		("var x; begin x := 0;\n! 5 % x end.", "Division by zero"),
+		("var x; begin x := -1;\n! 2 ** x end.", "Negative exponent: -1"),
+		("var x; begin x := 2;\n! x ** 31 end.", "Integer overflow"),
+		("var x; begin x := -4;\n! sqrt(x) end.", "Square root of a negative number: -4"),
+		("var x; begin x := -2147483647 - 1;\n! abs(x) end.", "Integer overflow"),
+	];
+	for (source, message) in cases {
+		assert_eq!(run(source), Err(format!("Runtime error @ <input>:2: {message}")), "{source}");
+	}
+}
@@ -0,0 +1,54 @@
+use plz::{sink::Buffer, token::TokenType, Options, Parser, Scanner, Stmt};
+
+// An expression as the parser groups it
+fn parsed(expression: &str) -> String {
+	let tokens = Scanner::new(format!("! {expression}."), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	let ast = parser.into_ast();
+	let Stmt::Print(e) = ast[program[0]] else { panic!("expected a print") };
+	ast.show(e)
+}
+// What a program prints
+fn run(source: &str) -> String {
+	let buffer = Buffer::new();
+	plz::run_source(source, Options { output: Box::new(buffer.clone()), ..Default::default() }).unwrap_or_else(|e| panic!("{e}"));
+	buffer.contents()
+}
+
+#[test]
+fn shifts_are_told_apart_from_comparisons() {
+	let kinds: Vec<TokenType> = Scanner::new("& | ^ ~ << >> <= >= < >".to_string(), "<input>".into()).scan_tokens().iter().map(|t| t.kind).collect();
+	use TokenType::*;
+	assert_eq!(kinds, [AMPERSAND, PIPE, CARET, TILDE, LESS_LESS, MORE_MORE, LESS_EQU, MORE_EQU, LESS, MORE, EOF]);
+}
+
+#[test]
+fn precedence_runs_from_shifts_through_and_and_xor_to_or() {
+	// sums bind tighter than shifts, shifts than `&`, `&` than `^`, `^` than `|`
+	assert_eq!(parsed("1 << 2 + 1"), "(<< 1 (+ 2 1))");
+	assert_eq!(parsed("6 & 3 << 1"), "(& 6 (<< 3 1))");
+	assert_eq!(parsed("1 | 6 ^ 3 & 5"), "(| 1 (^ 6 (& 3 5)))");
+	assert_eq!(parsed("~1 + 1"), "(+ (~ 1) 1)");
+	assert_eq!(parsed("1 << 2 >> 3"), "(>> (<< 1 2) 3)");
+}
+
+#[test]
+fn bitwise_operators() {
+	let out = run("var a, b; begin a := 12; b := 10; ! a & b, \" \", a | b, \" \", a ^ b, \" \", ~a end.");
+	assert_eq!(out, "> 8 14 6 -13\n");
+}
+
+#[test]
+fn shift_counts_outside_the_word_are_defined() {
+	let out = run("
+		var n, m;
+		begin
+			n := -1; m := 40;
+			! 1 << 31, \" \", 8 >> 1, \" \", -8 >> 1;
+			! 1 << n, \" \", 8 >> n;
+			! 1 << m, \" \", 5 >> m, \" \", -5 >> m
+		end.");
+	// a negative count shifts the other way; 32 or more shifts every bit out
+	assert_eq!(out, "> -2147483648 4 -4\n> 0 16\n> 0 0 -1\n");
+}
@@ -0,0 +1,67 @@
+use plz::{error::take_diagnostics, parser::CaseTable, sink::Buffer, Options, Parser, Scanner, Stmt};
+
+// The table of the first case statement in `source`, and the errors parsing it reported
+fn table(source: &str) -> (Option<CaseTable>, Vec<String>) {
+	let tokens = Scanner::new(source.to_string(), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	let ast = parser.into_ast();
+	let table = program.iter().find_map(|s| match &ast[*s] {
+		Stmt::Case(_, table) => Some(table.clone()),
+		Stmt::Scope(body) => body.iter().find_map(|s| match &ast[*s] { Stmt::Case(_, table) => Some(table.clone()), _ => None }),
+		_ => None,
+	});
+	(table, take_diagnostics().iter().map(|e| e.to_string()).collect())
+}
+// What a program prints
+fn run(source: &str) -> String {
+	let buffer = Buffer::new();
+	plz::run_source(source, Options { output: Box::new(buffer.clone()), ..Default::default() }).unwrap_or_else(|e| panic!("{e}"));
+	buffer.contents()
+}
+
+#[test]
+fn labels_are_sorted_into_a_table_while_parsing() {
+	let (parsed, errors) = table("const two = 2, neg = -3;\nvar x;\ncase x of 0: ! 0; 1, two: ! 1; neg, -1: ! 2 else ! 3 end.");
+	assert!(errors.is_empty(), "{errors:?}");
+	let parsed = parsed.unwrap();
+	assert_eq!(parsed.labels, [(-3, 2), (-1, 2), (0, 0), (1, 1), (2, 1)]);
+	assert_eq!((parsed.arms.len(), parsed.default), (4, Some(3)));
+	assert_eq!([-3, 2, 5].map(|v| parsed.select(v)), [Some(2), Some(1), Some(3)]);
+
+	// without an else arm, an unmatched value selects nothing
+	let (parsed, _) = table("var x;\ncase x of 1: ! 1; end.");
+	assert_eq!(parsed.unwrap().select(2), None);
+}
+
+#[test]
+fn labels_are_checked_statically() {
+	let (_, errors) = table("const one = 1;\nvar x;\nbegin x := 1; case x of\n1: ! 1;\none: ! 2 end end.");
+	assert_eq!(errors, ["Error @ <input>:5, at `one`: Duplicate case label 1 (already used at line 4)"]);
+	let (_, errors) = table("var x, y;\nbegin x := 1; case x of y: ! 1 end end.");
+	assert_eq!(errors[0], "Error @ <input>:2, at `y`: Case label is not a constant");
+	let (_, errors) = table("var x;\nbegin x := 1; case x of 1 + 1: ! 1 end end.");
+	assert_eq!(errors[0], "Error @ <input>:2, at `+`: Expected `:` after case labels");
+}
+
+#[test]
+fn arms_are_picked_by_label() {
+	let out = run("
+		const two = 2, neg = -3;
+		var i;
+		begin
+			i := -3;
+			while i < 5 do begin
+				case i of
+					0: write \"zero\";
+					1, two: write \"small\";
+					neg, -1: write \"negative\"
+				else write \"other\" end;
+				write \" \";
+				i := i + 1
+			end
+		end.");
+	assert_eq!(out, "negative other negative zero small small other other ");
+	assert_eq!(run("var x; begin x := 7; case x of 1: ! 1; 2: ! 2; end; ! x end."), "> 7\n");
+	assert_eq!(run("case 1 of end."), "");
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::{binary_stdout, SourceFile};
+use plz::Options;
+
+fn cfg(source: &str) -> String {
+	plz::cfg_dot(source, Options::default()).unwrap_or_else(|e| panic!("{e}"))
+}
+
+#[test]
+fn conditions_branch_with_true_and_false_edges() {
+	let graph = cfg("var i;\nbegin\n\ti := 0;\n\twhile i < 3 do\n\t\ti := i + 1;\n\tif i == 3 then ! i\nend.");
+	assert_eq!(graph, "\
+digraph cfg {
+	node [shape=box, fontname=\"monospace\"];
+	subgraph cluster_0 {
+		label=\"procedure main\";
+		n0_0 [label=\"entry\", shape=oval];
+		n0_1 [label=\"exit\", shape=oval];
+		n0_2 [label=\"3: i := 0\\l\", shape=box];
+		n0_3 [label=\"4: i < 3\", shape=diamond];
+		n0_4 [label=\"5: i := i + 1\\l\", shape=box];
+		n0_5 [label=\"6: i == 3\", shape=diamond];
+		n0_6 [label=\"6: ! i\\l\", shape=box];
+		n0_0 -> n0_2;
+		n0_2 -> n0_3;
+		n0_3 -> n0_4 [label=\"true\"];
+		n0_4 -> n0_3;
+		n0_3 -> n0_5 [label=\"false\"];
+		n0_5 -> n0_6 [label=\"true\"];
+		n0_6 -> n0_1;
+		n0_5 -> n0_1 [label=\"false\"];
+	}
+}
+");
+}
+
+#[test]
+fn every_procedure_gets_a_graph() {
+	let graph = cfg("
+		var x;
+		procedure outer;
+			procedure inner; writeln \"say \\\"hi\\\"\";
+			case x of 1: call inner; 2: x := 0 end;
+		begin x := 1; call outer end.");
+	assert!(graph.contains("label=\"procedure main\""), "{graph}");
+	assert!(graph.contains("label=\"procedure outer in main\""), "{graph}");
+	assert!(graph.contains("label=\"procedure inner in outer\""), "{graph}");
+	// a case without an else arm can fall through
+	assert!(graph.contains("n1_2 -> n1_1 [label=\"else\"];"), "{graph}");
+	// quotes in statements are escaped for DOT
+	assert!(graph.contains("4: writeln \\\"say \\\\\\\"hi\\\\\\\"\\\"\\l"), "{graph}");
+}
+
+#[test]
+fn the_cfg_command_prints_dot() {
+	let file = SourceFile::new("cfg", "var x; begin x := 1; if x > 0 then ! x end.");
+	let stdout = binary_stdout(&["cfg"], &file, "");
+	assert!(stdout.starts_with("digraph cfg {\n"), "{stdout}");
+	assert!(stdout.contains("shape=diamond"), "{stdout}");
+}
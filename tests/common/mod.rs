@@ -0,0 +1,66 @@
+// Fixtures shared by the integration tests; each test file uses its own share of them
+#![allow(dead_code)]
+
+use std::{io::Write, path::PathBuf, process::{Command, Output, Stdio}};
+
+use plz::{sink::Buffer, Diagnostics, Options};
+
+// Runs a program in-process, returning what it printed
+pub fn output(source: &str, options: Options) -> Result<String, Diagnostics> {
+	let buffer = Buffer::new();
+	plz::run_source(source, Options { output: Box::new(buffer.clone()), ..options }).map(|_| buffer.take())
+}
+// Like `output`, with the default options, for programs that must not fail
+pub fn run(source: &str) -> String {
+	output(source, Options::default()).unwrap_or_else(|e| panic!("{e}"))
+}
+// The compile errors a program is rejected with
+pub fn compile_errors(source: &str) -> Vec<String> {
+	match output(source, Options::default()) {
+		Err(Diagnostics::Compile(errors)) => errors.iter().map(|e| e.to_string()).collect(),
+		other => panic!("expected compile errors, got {other:?}"),
+	}
+}
+// The runtime error a program stops with
+pub fn runtime_error(source: &str) -> String {
+	match output(source, Options::default()) {
+		Err(Diagnostics::Runtime(e)) => e.to_string(),
+		other => panic!("expected a runtime error, got {other:?}"),
+	}
+}
+// Like `output`, with the optimizer on or off
+pub fn run_optimized(source: &str, optimize: bool) -> Result<String, Diagnostics> {
+	output(source, Options { optimize, ..Default::default() })
+}
+
+// A source file in the temp directory, removed again when dropped
+pub struct SourceFile {
+	pub path: PathBuf,
+}
+impl SourceFile {
+	pub fn new(name: &str, source: &str) -> Self {
+		let path = std::env::temp_dir().join(format!("plz-test-{name}-{}.pl0", std::process::id()));
+		std::fs::write(&path, source).unwrap();
+		SourceFile { path }
+	}
+	pub fn contents(&self) -> String { std::fs::read_to_string(&self.path).unwrap() }
+}
+impl Drop for SourceFile {
+	fn drop(&mut self) { let _ = std::fs::remove_file(&self.path); }
+}
+
+// Runs the interpreter binary with `args` followed by the file, feeding it `stdin`
+pub fn binary(args: &[&str], file: &SourceFile, stdin: &str) -> Output {
+	let mut child = Command::new(env!("CARGO_BIN_EXE_plz"))
+		.args(args).arg(&file.path)
+		.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped())
+		.spawn().unwrap();
+	child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+	child.wait_with_output().unwrap()
+}
+// Like `binary`, for runs that must succeed, returning their stdout
+pub fn binary_stdout(args: &[&str], file: &SourceFile, stdin: &str) -> String {
+	let output = binary(args, file, stdin);
+	assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+	String::from_utf8(output.stdout).unwrap()
+}
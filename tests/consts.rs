@@ -0,0 +1,55 @@
+use plz::{error::take_diagnostics, sink::Buffer, Options, Parser, Scanner, Stmt};
+
+// The consts a program declares at the top, as the parser computed them, and the errors it reported
+fn consts(source: &str) -> (Vec<(String, i32)>, Vec<String>) {
+	let tokens = Scanner::new(source.to_string(), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	let ast = parser.into_ast();
+	let values = program.iter().flat_map(|s| match &ast[*s] {
+		Stmt::Const(consts) => consts.iter().map(|(name, v)| (name.lexeme.to_string(), *v)).collect(),
+		_ => Vec::new(),
+	}).collect();
+	(values, take_diagnostics().iter().map(|e| e.to_string()).collect())
+}
+// What a program prints, with the optimizer on or off
+fn run(source: &str, optimize: bool) -> String {
+	let buffer = Buffer::new();
+	plz::run_source(source, Options { output: Box::new(buffer.clone()), optimize, ..Default::default() }).unwrap_or_else(|e| panic!("{e}"));
+	buffer.contents()
+}
+
+#[test]
+fn consts_are_computed_while_parsing_from_earlier_consts() {
+	let (values, errors) = consts("const n = 10, m = n * 2 + 1, neg = -5, big = max(m, 3) ** 2 % 100, root = sqrt(m);\n.");
+	assert!(errors.is_empty(), "{errors:?}");
+	let expected = [("n", 10), ("m", 21), ("neg", -5), ("big", 41), ("root", 4)].map(|(n, v)| (n.to_string(), v));
+	assert_eq!(values, expected);
+	// folded away by the optimizer or not, the program sees the same values
+	let source = "const n = 10, m = n * 2 + 1;\n! n, \" \", m.";
+	assert_eq!(run(source, true), "> 10 21\n");
+	assert_eq!(run(source, false), "> 10 21\n");
+}
+
+#[test]
+fn a_procedure_may_shadow_a_const_with_one_computed_from_it() {
+	let out = run("const k = 2;\nprocedure p;\nconst k = k * 3, j = -k;\n! k, \" \", j;\nbegin call p; ! k end.", true);
+	assert_eq!(out, "> 6 -6\n> 2\n");
+}
+
+#[test]
+fn only_consts_and_valid_arithmetic_are_allowed() {
+	let cases = [
+		("const c = d + 1;\n.", "Error @ <input>:1, at `d`: Undeclared constant"),
+		("var v;\nprocedure p;\nconst c = v + 1;\n! c;\ncall p.", "Error @ <input>:3, at `v`: Only consts may be used in constant expressions"),
+		("type t = record f end; var r: t;\nprocedure p;\nconst c = r.f;\n! c;\ncall p.", "Error @ <input>:3, at `r`: Only consts may be used in constant expressions"),
+		("const a = 1, b = a / (a - 1);\n.", "Error @ <input>:1, at `/`: Division by zero"),
+		("const c = 2 ** 31;\n.", "Error @ <input>:1, at `**`: Integer overflow"),
+		("const c = sqrt(-1);\n.", "Error @ <input>:1, at `sqrt`: Square root of a negative number: -1"),
+	];
+	for (source, expected) in cases {
+		assert_eq!(consts(source).1, [expected], "{source}");
+	}
+	// conditions are not constant expressions
+	assert_eq!(consts("const c = 1 == 1;\n.").1[0], "Error @ <input>:1, at `==`: Expected `;` after const declaration");
+}
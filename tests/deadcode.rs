@@ -0,0 +1,89 @@
+mod common;
+
+use common::run_optimized;
+use plz::{Diagnostics, Options};
+
+fn optimized(source: &str) -> String {
+	plz::optimized_source(source, Options::default()).unwrap_or_else(|e| panic!("{e}"))
+}
+
+#[test]
+fn unreachable_procedures_and_unused_variables_are_removed() {
+	let program = optimized("
+		var g;
+		procedure used;
+			var a, b;
+			procedure inner; begin end;
+			begin a := 1; ! a end;
+		procedure never;
+			begin call used end;
+		procedure helper; ! 2;
+		procedure later;
+			begin call helper end;
+		begin g := 1; call used; call later end.");
+	assert_eq!(program, "\
+		# Removed @ <input>:5: procedure `inner`, which is never called\n\
+		# Removed @ <input>:7: procedure `never`, which is never called\n\
+		# Removed @ <input>:4: variable `b`, which is never used\n\
+		var g;\n\
+		procedure used;\n\tvar a;\n\tbegin\n\t\ta := 1;\n\t\t! a\n\tend;\n\
+		procedure helper;\n\t! 2;\n\
+		procedure later;\n\tbegin\n\t\tcall helper\n\tend;\n\
+		begin\n\tg := 1;\n\tcall used;\n\tcall later\nend.\n");
+	// the notes are comments, so the output still runs, and has nothing left to remove
+	assert_eq!(optimized(&program), program.lines().filter(|l| !l.starts_with('#')).map(|l| format!("{l}\n")).collect::<String>());
+}
+
+#[test]
+fn statements_after_endless_loops_are_removed() {
+	let program = optimized("
+		var i;
+		procedure spin;
+			begin
+				while true do i := i + 1;
+				! 1;
+				begin ! 2; ! 3 end
+			end;
+		begin i := 0; if i > 0 then call spin end.");
+	assert!(program.starts_with("# Removed @ <input>:5: 2 statements after a loop that never ends\n"), "{program}");
+	assert!(program.contains("while true do i := i + 1\n"), "{program}");
+	assert!(!program.contains("! 1"), "{program}");
+}
+
+#[test]
+fn removing_dead_code_does_not_change_what_a_program_does() {
+	let source = "
+		import std;
+		var x, unused;
+		procedure square;
+			var spare;
+			begin x := x * x end;
+		procedure nobody;
+			var y;
+			begin y := 1; call square end;
+		begin
+			x := 7;
+			call square;
+			std.a := 5;
+			call std.factorial;
+			writeln x, \" \", std.result
+		end.";
+	assert_eq!(run_optimized(source, true).unwrap(), run_optimized(source, false).unwrap());
+	assert_eq!(run_optimized(source, true).unwrap(), "49 120\n");
+	// globals are kept for the host to read, even when the program never uses them
+	assert!(optimized(source).contains("var x, unused;"));
+
+	// duplicate declarations are errors whether or not the code they are in could be removed
+	for (source, expected) in [
+		("procedure p; ;
+procedure p; ;
+.", "Error @ <input>:2, at `p`: Duplicate procedure name"),
+		("procedure q; var y, y; begin end;
+begin call q end.", "Error @ <input>:1, at `y`: Duplicate variable name"),
+	] {
+		for optimize in [true, false] {
+			let Err(Diagnostics::Compile(errors)) = run_optimized(source, optimize) else { panic!("expected compile errors for {source}") };
+			assert_eq!(errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(), [expected]);
+		}
+	}
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{binary_stdout, SourceFile};
+use plz::Options;
+
+#[test]
+fn the_tree_outlines_statements_and_expressions() {
+	let tree = plz::ast_tree("var x;\nbegin\n\tx := 2 * 3;\n\tcase x of 1, 2: ! x else ? x end\nend.", Options::default())
+		.unwrap_or_else(|e| panic!("{e}"));
+	assert_eq!(tree, "\
+Program
+  Var @1
+    x
+  Scope @3
+    Assign x @3
+      Binary * @3
+        Literal 2 @3
+        Literal 3 @3
+    Case @4
+      Variable x @4
+      Arm 1, 2
+        Print @4
+          Variable x @4
+      Arm else
+        PrintVar x @4
+");
+
+	let dot = plz::ast_dot("var x; x := 1.", Options::default()).unwrap_or_else(|e| panic!("{e}"));
+	assert!(dot.starts_with("digraph ast {\n"), "{dot}");
+	assert!(dot.contains("\tn0 [label=\"Program\"];\n"), "{dot}");
+	assert!(dot.contains("\tn0 -> n1;\n"), "{dot}");
+}
+
+#[test]
+fn the_call_graph_marks_nesting_and_cycles() {
+	let graph = plz::call_graph_dot("
+		var n;
+		procedure even;
+			procedure helper; n := n - 1;
+			begin if n > 0 then begin call helper; call other end end;
+		procedure other;
+			begin if n > 0 then call even end;
+		procedure once; ! n;
+		begin n := 4; call even; call once end.", Options::default()).unwrap_or_else(|e| panic!("{e}"));
+	let node = |name: &str| graph.lines()
+		.find(|l| l.contains(&format!("[label=\"{name}\"")))
+		.and_then(|l| l.trim().split(' ').next())
+		.unwrap_or_else(|| panic!("no node for {name} in {graph}"))
+		.to_string();
+	let (even, helper, other, once) = (node("even"), node("helper"), node("other"), node("once"));
+
+	// nesting
+	assert!(graph.contains(&format!("\t{even} -> {helper} [style=dashed, color=gray, arrowhead=none];\n")), "{graph}");
+	assert!(graph.contains(&format!("\tmain -> {once} [style=dashed, color=gray, arrowhead=none];\n")), "{graph}");
+	// calls, with the mutual recursion of `even` and `other` in red
+	assert!(graph.contains(&format!("\tmain -> {even};\n")), "{graph}");
+	assert!(graph.contains(&format!("\t{even} -> {helper};\n")), "{graph}");
+	assert!(graph.contains(&format!("\t{even} -> {other} [color=red, penwidth=2];\n")), "{graph}");
+	assert!(graph.contains(&format!("\t{other} -> {even} [color=red, penwidth=2];\n")), "{graph}");
+	assert!(graph.contains("[label=\"other\", color=red, fontcolor=red]"), "{graph}");
+	assert!(graph.contains("[label=\"once\"];"), "{graph}");
+}
+
+#[test]
+fn the_ast_and_calls_commands_print_dumps() {
+	let file = SourceFile::new("dump", "procedure p; call p; call p.");
+	let tree = binary_stdout(&["ast"], &file, "");
+	let dot = binary_stdout(&["ast", "--dot"], &file, "");
+	let calls = binary_stdout(&["calls"], &file, "");
+
+	assert!(tree.starts_with("Program\n  Proc p @1\n"), "{tree}");
+	assert!(dot.starts_with("digraph ast {\n"), "{dot}");
+	assert!(calls.contains("[label=\"p\", color=red, fontcolor=red]"), "{calls}");
+}
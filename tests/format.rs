@@ -0,0 +1,226 @@
+mod common;
+
+use std::{collections::HashMap, path::Path};
+
+use common::{run, binary, SourceFile};
+
+use plz::{error::take_diagnostics, token::TokenType, Diagnostics, Options, Parser, Scanner, Symbol};
+
+fn format(source: &str) -> String {
+	plz::format_source(source, Options::default()).unwrap_or_else(|e| panic!("{e}"))
+}
+
+#[test]
+fn programs_are_laid_out_the_canonical_way() {
+	let source = "\
+const two=2 ;
+var   x,total ;
+procedure   add ;
+  begin total:=total+x ;x:=x-1
+    end;
+begin x:=3;total:=0;
+while x>0 do call add;
+  case total mod 4 of 1,two : ! -total*(3+4) ; 3: begin end else ? total; end;
+if odd x then ! \"odd\" ; if x>=0 then
+begin ;
+  writeln \"total: \",total ;
+end
+end.";
+	assert_eq!(format(source), "\
+const two = 2;
+var x, total;
+procedure add;
+	begin
+		total := total + x;
+		x := x - 1
+	end;
+begin
+	x := 3;
+	total := 0;
+	while x > 0 do call add;
+	case total mod 4 of
+		1, two: ! -total * (3 + 4);
+		3: begin end;
+		else ? total
+	end;
+	if odd x then ! \"odd\";
+	if x >= 0 then begin
+		writeln \"total: \", total
+	end
+end.
+");
+}
+
+#[test]
+fn comments_and_single_blank_lines_are_kept() {
+	let source = "\
+# counts down
+var   x ;   # the counter
+
+
+
+procedure   tick ;
+  begin # once
+  # step
+  x:=x -1;  # last
+  end;
+begin x:=2;
+
+call tick ; # then again
+call tick
+end. # done";
+	assert_eq!(format(source), "\
+# counts down
+var x; # the counter
+
+procedure tick;
+	begin # once
+		# step
+		x := x - 1 # last
+	end;
+begin
+	x := 2;
+
+	call tick; # then again
+	call tick
+end. # done
+");
+}
+
+#[test]
+fn lines_a_comment_breaks_are_indented_one_level_deeper() {
+	let source = "var x;\nbegin x := 1 + # one\n2;\nx := x *\n# twice\n2; # done\nif x > 1 then # big\n! x;\n! x end.";
+	let formatted = format(source);
+	assert_eq!(formatted, "\
+var x;
+begin
+	x := 1 + # one
+		2;
+	x := x *
+		# twice
+		2; # done
+	if x > 1 then # big
+		! x;
+	! x
+end.
+");
+	assert_eq!(format(&formatted), formatted);
+}
+
+#[test]
+fn formatting_is_idempotent_and_keeps_what_programs_do() {
+	let programs = [
+		"var n, result; procedure fact; var k; procedure step; result := result * k;
+		begin k := n; if k > 1 then begin n := n - 1; call fact end; call step end;
+		begin n := 5; result := 1; call fact; ! result end.",
+		"type point = record x, y end; var p: point, i;
+		begin p.x := 1; p.y := 2; i := 0; # sum up
+			while i < 3 do begin p.x := p.x + p.y ** 2; # grows
+			i := i + 1 end; writeln \"x: \", p.x, \"\\n\", gcd(p.x, 12) end.",
+		"var x; begin x := 7; case x % 3 of 0: ! 0; 1: ; else write \"many \
+lines\" end; writeln; ? x; ; end.",
+	];
+	for source in programs {
+		let formatted = format(source);
+		assert_eq!(format(&formatted), formatted);
+		assert_eq!(run(&formatted), run(source), "{formatted}");
+	}
+	// a comment may end the file without a newline
+	assert_eq!(run("! 1. # done"), "> 1\n");
+}
+
+#[test]
+fn syntax_errors_are_reported() {
+	match plz::format_source("var x; begin x := end.", Options::default()) {
+		Err(Diagnostics::Compile(errors)) => assert!(errors[0].to_string().ends_with("at `end`: Expected an expression"), "{}", errors[0]),
+		other => panic!("expected a compile error, got {:?}", other.map_err(|e| e.to_string())),
+	}
+}
+
+#[test]
+fn the_fmt_command_rewrites_files_and_checks_them() {
+	let file = SourceFile::new("fmt", "var x;begin x:=1;! x end.");
+	let fmt = |args: &[&str]| binary(args, &file, "").status;
+
+	let unformatted = fmt(&["fmt", "--check"]);
+	let unchanged = file.contents();
+	let rewritten = fmt(&["fmt"]);
+	let formatted = file.contents();
+	let checked = fmt(&["fmt", "--check"]);
+
+	assert!(!unformatted.success());
+	assert_eq!(unchanged, "var x;begin x:=1;! x end.");
+	assert!(rewritten.success());
+	assert_eq!(formatted, "var x;\nbegin\n\tx := 1;\n\t! x\nend.\n");
+	assert!(checked.success());
+}
+
+// The string literals in a Rust source, unescaped
+fn literals(rust: &str) -> Vec<String> {
+	let mut out = Vec::new();
+	let mut chars = rust.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'/' if chars.peek() == Some(&'/') => { for c in chars.by_ref() { if c == '\n' { break } } },
+			// a char literal, which may be a quote; anything else after `'` is a lifetime
+			'\'' => {
+				let mut ahead = chars.clone();
+				let width = if ahead.next() == Some('\\') { 3 } else { 2 };
+				if ahead.nth(width - 2) == Some('\'') { chars.nth(width - 1); }
+			},
+			'"' => {
+				let mut literal = String::new();
+				while let Some(c) = chars.next() {
+					match c {
+						'"' => break,
+						'\\' => match chars.next() {
+							Some('n') => literal.push('\n'),
+							Some('t') => literal.push('\t'),
+							Some('\n') => while chars.next_if(|c| c.is_whitespace()).is_some() {},
+							Some(c) => literal.push(c),
+							None => {},
+						},
+						c => literal.push(c),
+					}
+				}
+				out.push(literal);
+			},
+			_ => {},
+		}
+	}
+	out
+}
+// Whether the interpreter's parser and the formatter's find `source` grammatical
+fn accepted(source: &str) -> (bool, bool) {
+	let mut parser = Parser::new(Scanner::new(source.to_string(), "<input>".into()).scan_tokens());
+	// modules are not loaded, but their names must be known for `module.member` to parse as one
+	for spec in parser.imports() {
+		let name = match spec.kind {
+			TokenType::STRING => Path::new(spec.text().trim_matches('"')).file_stem().unwrap().to_string_lossy().as_ref().into(),
+			_ => spec.lexeme,
+		};
+		parser.import(name, HashMap::new());
+	}
+	parser.import(Symbol::intern("std"), HashMap::new()); // the standard library reads `std.read()`
+	parser.parse();
+	let tokens = Scanner::new(source.to_string(), "<input>".into()).with_comments().scan_tokens();
+	let formatted = plz::format::parse(tokens).is_ok();
+	take_diagnostics();
+	(!parser.had_syntax_error(), formatted)
+}
+
+#[test]
+fn the_formatter_accepts_what_the_parser_does() {
+	// the formatter reads the grammar again to keep every token, so every program the
+	// tests use, valid or not, and those shipped as files, must get the same verdict from both
+	let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+	let mut sources = vec![std::fs::read_to_string(root.join("src/std.pl0")).unwrap(), std::fs::read_to_string(root.join("test.pl0")).unwrap()];
+	for entry in std::fs::read_dir(root.join("tests")).unwrap() {
+		let path = entry.unwrap().path();
+		if path.extension().is_some_and(|e| e == "rs") { sources.extend(literals(&std::fs::read_to_string(path).unwrap())); }
+	}
+	let programs = sources.iter().filter(|s| accepted(s).0).count();
+	assert!(programs > 100, "only {programs} programs found");
+	let disagreements: Vec<_> = sources.iter().filter(|s| accepted(s).0 != accepted(s).1).collect();
+	assert!(disagreements.is_empty(), "{disagreements:#?}");
+}
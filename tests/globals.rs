@@ -0,0 +1,45 @@
+use plz::{sink::Buffer, Diagnostics, Options};
+
+fn options() -> Options {
+	Options { output: Box::new(Buffer::new()), ..Default::default() }
+}
+
+#[test]
+fn seeded_globals_and_consts_are_visible_to_the_program() {
+	let options = Options {
+		globals: vec![("n".to_string(), 5)],
+		consts: vec![("step".to_string(), 3)],
+		..options()
+	};
+	let out = plz::run_source("
+		const limit = step * 10;
+		var total;
+		begin
+			total := 0;
+			while n > 0 do begin total := total + step; n := n - 1 end;
+			if total > limit then total := limit
+		end.", options).unwrap();
+	assert_eq!(out.global("total"), Some(15));
+	assert_eq!(out.global("n"), Some(0));
+	assert_eq!(out.global("limit"), Some(30));
+	assert_eq!(out.global("step"), Some(3));
+	assert_eq!(out.global("missing"), None);
+}
+
+#[test]
+fn program_declarations_shadow_seeded_globals() {
+	let options = Options { globals: vec![("x".to_string(), 1)], ..options() };
+	let out = plz::run_source("var x; x := 2.", options).unwrap();
+	assert_eq!(out.global("x"), Some(2));
+
+	let mut interpreter = out.into_interpreter();
+	interpreter.set_global("y", 7).unwrap();
+	assert_eq!(interpreter.global("y"), Some(7));
+}
+
+#[test]
+fn seeded_consts_cannot_be_assigned() {
+	let options = Options { consts: vec![("c".to_string(), 1)], ..options() };
+	let result = plz::run_source("c := 2.", options);
+	assert!(matches!(result, Err(Diagnostics::Runtime(_))));
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use common::output;
+use plz::{ir::{self, Function, Op, Constant, Terminator, Program}, sink::Buffer, Diagnostics, Options, Symbol};
+
+fn ir_source(source: &str, optimize: bool) -> String {
+	plz::ir_source(source, Options { optimize, ..Default::default() }).unwrap_or_else(|e| panic!("{e}"))
+}
+// what a program prints, run by the tree interpreter and then through the IR
+fn both(source: &str, optimize: bool) -> (Result<String, String>, Result<String, String>) {
+	both_with(source, || Options { optimize, ..Default::default() })
+}
+fn both_with(source: &str, options: impl Fn() -> Options) -> (Result<String, String>, Result<String, String>) {
+	let tree = output(source, options()).map_err(|e| e.to_string());
+	let lowered = Buffer::new();
+	let lowered = plz::run_ir(source, Options { output: Box::new(lowered.clone()), ..options() })
+		.map(|_| lowered.contents()).map_err(|e| e.to_string());
+	(tree, lowered)
+}
+
+#[test]
+fn loops_merge_variables_with_phis() {
+	let program = ir_source("var i; begin i := 0; while i < 3 do i := i + 1; ! i end.", false);
+	assert!(program.contains("= phi [b0: "), "{program}");
+	assert!(program.contains("branch "), "{program}");
+
+	// promoted variables fold away entirely once their values are known
+	let program = ir_source("var x, y; begin x := 2 * 3; y := x + 1; ! y; ! x + 1 end.", true);
+	assert_eq!(program, "procedure main\nb0:\n\tv5 = const 7\n\tprint v5\n\tprint v5\n\treturn\n");
+}
+
+#[test]
+fn common_subexpressions_are_computed_once() {
+	let program = ir_source("
+		var n, m, r;
+		procedure bump; n := n + 1;
+		begin
+			n := 3; call bump;
+			m := n;
+			r := m * m + m * m;
+			! r
+		end.", true);
+	// `n` stays in memory, as `bump` uses it; `m` is a value, and `m * m` needs computing once
+	assert_eq!(program.matches(" * ").count(), 1, "{program}");
+	assert_eq!(program.matches("load main.n").count(), 2, "{program}");
+}
+
+#[test]
+fn procedures_and_memory_no_longer_used_are_pruned() {
+	// only the IR finds `x == 1` false, leaving `p` uncalled and `y` unused
+	let source = "
+		var x, y, z;
+		procedure p; y := y + 1;
+		procedure q; begin z := 2; ! z end;
+		begin x := 0; if x == 1 then call p; call q end.";
+	let program = ir_source(source, true);
+	assert!(!program.contains("procedure p"), "{program}");
+	assert!(program.contains("\tmemory z\n") && program.contains("procedure q in main\n"), "{program}");
+	assert!(program.contains("call q") && program.contains("store main.z, "), "{program}");
+	assert!(ir_source(source, false).contains("\tmemory y, z\n"));
+	assert_eq!(both(source, true), (Ok("> 2\n".to_string()), Ok("> 2\n".to_string())));
+}
+
+#[test]
+fn the_ir_runs_programs_like_the_tree_does() {
+	let programs = [
+		// recursion, with a nested procedure reaching its caller's activation
+		"var n, result;
+		procedure fact;
+			var k;
+			procedure step; result := result * k;
+			begin
+				k := n;
+				if k > 1 then begin n := n - 1; call fact end;
+				call step
+			end;
+		begin n := 5; result := 1; call fact; ! result end.",
+		// loops, booleans and the built-in functions
+		"var i, total, done: boolean;
+		begin
+			i := 0; total := 0; done := false;
+			while done == false do begin
+				if odd i then total := total + gcd(i, 12) * sign(i);
+				i := i + 1;
+				done := i >= 20
+			end;
+			writeln \"total: \", total, \" max: \", max(total, 100), \" sqrt: \", sqrt(total);
+			? done
+		end.",
+		// case, with labels sharing an arm and an else arm
+		"const two = 2;
+		var i, x;
+		begin
+			i := 0;
+			while i < 6 do begin
+				case i % 4 of 0: x := 10; 1, two: x := 20 else x := i end;
+				write x, \" \";
+				i := i + 1
+			end;
+			writeln;
+			? x
+		end.",
+		// unassigned variables print as such
+		"var a, b; begin ? a; a := 1; ? a; ? b end.",
+	];
+	for source in programs {
+		for optimize in [false, true] {
+			let (tree, lowered) = both(source, optimize);
+			assert!(tree.is_ok(), "{tree:?}");
+			assert_eq!(tree, lowered, "optimize: {optimize}");
+		}
+	}
+}
+
+#[test]
+fn runtime_errors_match_the_tree_interpreter() {
+	let source = "var x;\nprocedure zero; x := 0;\nbegin\n\tcall zero;\n\t! 10 / x\nend.";
+	for optimize in [false, true] {
+		let (tree, lowered) = both(source, optimize);
+		assert_eq!(tree, Err("Runtime error @ <input>:5: Division by zero".to_string()));
+		assert_eq!(tree, lowered);
+	}
+
+	// reading a variable never assigned names it, whether it is kept in memory or not
+	let cases = [
+		("var x, y;\nbegin x := 1;\n! x + y\nend.", "Runtime error @ <input>:3: Use of unitialised variable: y"),
+		("var y;\nprocedure p;\n! y;\ncall p.", "Runtime error @ <input>:3: Use of unitialised variable: y"),
+	];
+	for (source, expected) in cases {
+		for optimize in [false, true] {
+			let (tree, lowered) = both(source, optimize);
+			assert_eq!(tree, Err(expected.to_string()), "{source}");
+			assert_eq!(tree, lowered, "{source}");
+		}
+	}
+
+	// the host's globals and consts are seen the same way
+	let host = |optimize| move || Options { globals: vec![("g".to_string(), 3)], consts: vec![("c".to_string(), 3)], optimize, ..Default::default() };
+	for optimize in [false, true] {
+		let (tree, lowered) = both_with("procedure p; g := g * 2;\nbegin ! g + c; call p; ! g end.", host(optimize));
+		assert_eq!(tree, Ok("> 6\n> 6\n".to_string()));
+		assert_eq!(tree, lowered);
+		let (tree, lowered) = both_with("var x;\nbegin x := g - c;\n! 10 / x\nend.", host(optimize));
+		assert_eq!(tree, Err("Runtime error @ <input>:3: Division by zero".to_string()));
+		assert_eq!(tree, lowered);
+	}
+}
+
+#[test]
+fn what_the_ir_has_no_form_for_is_a_compile_error() {
+	let result = plz::ir_source("type p = record x end; var r: p; r.x := 1.", Options::default());
+	match result {
+		Err(Diagnostics::Compile(errors)) => assert!(errors[0].to_string().ends_with("The IR has no form for records"), "{}", errors[0]),
+		other => panic!("expected a compile error, got {:?}", other.map_err(|e| e.to_string())),
+	}
+}
+
+#[test]
+fn the_verifier_rejects_values_read_before_they_are_defined() {
+	let mut func = Function::new(Symbol::intern("main"), None, "<test>".into());
+	let exit = func.add_block();
+	let one = func.add_inst(Op::Const(Constant::Int(1)), 1);
+	let print = func.add_inst(Op::Print(one), 1);
+	func.block_mut(ir::BlockId(0)).insts.push(print);
+	func.block_mut(ir::BlockId(0)).terminator = Terminator::Jump(exit);
+	func.block_mut(exit).insts.push(one);
+	let mut program = Program { functions: vec![func] };
+	assert_eq!(ir::verify(&program), Err("in procedure main: v1 reads v0 before it is defined".to_string()));
+
+	// defined first, in the block that dominates the other, it is fine
+	let func = &mut program.functions[0];
+	func.block_mut(exit).insts.clear();
+	func.block_mut(ir::BlockId(0)).insts.insert(0, one);
+	assert_eq!(ir::verify(&program), Ok(()));
+}
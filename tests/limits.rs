@@ -0,0 +1,108 @@
+use std::{rc::Rc, thread, time::Duration};
+
+use plz::{sink::Buffer, Diagnostics, Interpreter, Limits, Options, RuntimeErrorKind, Parser, Scanner};
+
+fn run(source: &str, limits: Limits) -> Result<plz::Output, Diagnostics> {
+	let options = Options { output: Box::new(Buffer::new()), limits, ..Default::default() };
+	plz::run_source(source, options)
+}
+fn runtime_error(result: Result<plz::Output, Diagnostics>) -> plz::RuntimeError {
+	match result {
+		Err(Diagnostics::Runtime(e)) => e,
+		Err(e) => panic!("{e}"),
+		Ok(_) => panic!("the program should have failed"),
+	}
+}
+
+#[test]
+fn infinite_loops_hit_the_statement_limit() {
+	let limits = Limits { max_statements: Some(10_000), ..Default::default() };
+	let e = runtime_error(run("var x;\nbegin\n\tx := 0;\n\twhile 1 == 1 do x := x + 1\nend.", limits));
+	assert_eq!(e.kind, RuntimeErrorKind::StatementLimit);
+	assert_eq!(e.location.map(|(_, line)| line), Some(4));
+}
+
+#[test]
+fn unbounded_recursion_hits_the_call_depth_limit() {
+	let limits = Limits { max_call_depth: Some(5), ..Default::default() };
+	let e = runtime_error(run("procedure p;\n\tcall p;\ncall p.", limits));
+	assert_eq!(e.kind, RuntimeErrorKind::CallDepth);
+	assert_eq!(e.location.map(|(_, line)| line), Some(2));
+}
+
+#[test]
+fn long_runs_time_out() {
+	let limits = Limits { timeout: Some(Duration::from_millis(50)), ..Default::default() };
+	let e = runtime_error(run("while 1 == 1 do .", limits));
+	assert_eq!(e.kind, RuntimeErrorKind::Timeout);
+}
+
+#[test]
+fn ordinary_errors_are_located() {
+	let e = runtime_error(run("var x;\nbegin\n\tx := 1;\n\tx := x / 0\nend.", Limits::default()));
+	assert_eq!(e.kind, RuntimeErrorKind::Error);
+	assert_eq!(e.to_string(), "Runtime error @ <input>:4: Division by zero");
+}
+
+#[test]
+fn the_host_can_cancel_from_another_thread() {
+	let tokens = Scanner::new("while 1 == 1 do .".to_string(), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	let ast = Rc::new(parser.into_ast());
+	let mut interpreter = Interpreter::new();
+	let cancel = interpreter.cancel_handle();
+	let canceller = thread::spawn(move || {
+		thread::sleep(Duration::from_millis(20));
+		cancel.cancel();
+	});
+	let e = interpreter.interpret(&ast, &Rc::default(), &program).unwrap_err();
+	canceller.join().unwrap();
+	assert_eq!(e.kind, RuntimeErrorKind::Cancelled);
+}
+
+#[test]
+fn the_cancel_handle_is_the_one_in_the_limits() {
+	let tokens = Scanner::new("while 1 == 1 do .".to_string(), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	let ast = Rc::new(parser.into_ast());
+	let mut interpreter = Interpreter::new();
+	let limits = Limits { timeout: Some(Duration::from_secs(60)), ..Default::default() };
+	let theirs = limits.cancel.clone();
+	interpreter.set_limits(limits);
+	interpreter.cancel_handle().cancel();
+	assert!(theirs.is_cancelled());
+	let e = interpreter.interpret(&ast, &Rc::default(), &program).unwrap_err();
+	assert_eq!(e.kind, RuntimeErrorKind::Cancelled);
+}
+
+#[test]
+fn the_clock_is_also_read_at_every_call() {
+	// each call runs few statements, and sleeps in a native procedure
+	let nap = plz::NativeProcedure::new("nap", 0, |_| { thread::sleep(Duration::from_millis(10)); Ok(None) });
+	let limits = Limits { timeout: Some(Duration::from_millis(30)), ..Default::default() };
+	let options = Options { output: Box::new(Buffer::new()), natives: vec![nap], limits, ..Default::default() };
+	let e = runtime_error(plz::run_source("procedure p; call nap;
+begin call p; call p; call p; call p; call p; call p end.", options));
+	assert_eq!(e.kind, RuntimeErrorKind::Timeout);
+}
+
+#[test]
+fn deep_recursion_does_not_overflow_the_stack() {
+	let out = run("
+		var n, depth;
+		procedure down;
+		begin
+			if n > 0 then begin n := n - 1; depth := depth + 1; call down end
+		end;
+		begin n := 99000; depth := 0; call down end.", Limits::default()).unwrap();
+	// close to the default call depth limit, far past what the native stack could hold
+	assert_eq!(out.global("depth"), Some(99000));
+
+	let limits = Limits { max_call_depth: Some(1500), ..Default::default() };
+	let e = runtime_error(run("procedure p; call p; call p.", limits));
+	assert_eq!(e.kind, RuntimeErrorKind::CallDepth);
+	let e = runtime_error(run("procedure p; call p; call p.", Limits::default()));
+	assert_eq!(e.to_string(), "Runtime error @ <input>:1: Call depth limit of 100000 exceeded");
+}
@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+
+use plz::{error::take_diagnostics, modules::Loader, sink::Buffer, Diagnostics, Options};
+
+// A directory of source files in the temp directory, removed again when dropped
+struct Project {
+	dir: PathBuf,
+}
+impl Project {
+	fn new(name: &str, files: &[(&str, &str)]) -> Self {
+		let dir = std::env::temp_dir().join(format!("plz-test-{name}-{}", std::process::id()));
+		for (path, source) in files {
+			let path = dir.join(path);
+			std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+			std::fs::write(path, source).unwrap();
+		}
+		Project { dir }
+	}
+	fn path(&self, file: &str) -> String { self.dir.join(file).display().to_string() }
+	// Loads `main.pl0` and what it imports, returning the files in the order they were
+	// loaded, and the errors found along the way
+	fn load(&self) -> (Vec<String>, Vec<String>) {
+		let main = self.dir.join("main.pl0");
+		let (_, modules) = Loader::new(Vec::new()).load_program(&main, std::fs::read_to_string(&main).unwrap());
+		let files = modules.iter().map(|m| m.file.to_string()).collect();
+		(files, take_diagnostics().iter().map(|e| e.to_string()).collect())
+	}
+	fn errors(&self) -> Vec<String> { self.load().1 }
+	// Runs `main.pl0`, looking imports by name up in `search_path` after the project's own directory
+	fn run(&self, search_path: &[&str]) -> Result<String, Diagnostics> {
+		let main = self.dir.join("main.pl0");
+		let search_path = search_path.iter().map(|d| self.dir.join(d)).collect();
+		let buffer = Buffer::new();
+		let options = Options { file: main.clone(), search_path, output: Box::new(buffer.clone()), ..Default::default() };
+		plz::run_source(&std::fs::read_to_string(&main).unwrap(), options).map(|_| buffer.contents())
+	}
+}
+impl Drop for Project {
+	fn drop(&mut self) { let _ = std::fs::remove_dir_all(&self.dir); }
+}
+
+const UTIL: &str = "
+	export swap, a, b, limit;
+	const limit = 10;
+	var a, b;
+	procedure swap;
+		var t;
+		begin t := a; a := b; b := t end;
+	a := 1.";
+
+#[test]
+fn modules_are_imported_by_path_and_by_name() {
+	let project = Project::new("modules-import", &[
+		("util.pl0", UTIL),
+		("lib/math.pl0", "export double, x; var x; procedure double; x := x * 2; ."),
+		("main.pl0", "
+			import util;
+			import \"lib/math.pl0\";
+			begin
+				util.b := 2; call util.swap;
+				! util.a, \" \", util.b, \" \", util.limit;
+				math.x := 21; call math.double; ! math.x
+			end."),
+	]);
+	// a module's main statement runs before the importing program's
+	assert_eq!(project.run(&[]).unwrap(), "> 2 1 10\n> 42\n");
+}
+
+#[test]
+fn modules_are_loaded_after_their_dependencies() {
+	let project = Project::new("modules-order", &[
+		("a.pl0", "import b; import c; export x; var x; ."),
+		("b.pl0", "import c; export y; var y; ."),
+		("c.pl0", "export z; var z; ."),
+		("main.pl0", "import a; import c; ! 1."),
+	]);
+	let (files, errors) = project.load();
+	assert!(errors.is_empty(), "{errors:?}");
+	// each module once, however many import it, and the program last
+	assert_eq!(files, ["c.pl0", "b.pl0", "a.pl0", "main.pl0"].map(|f| project.path(f)));
+}
+
+#[test]
+fn imports_by_name_fall_back_to_the_search_path() {
+	let project = Project::new("modules-search", &[
+		("include/util.pl0", UTIL),
+		("main.pl0", "import util; ! util.limit."),
+	]);
+	assert_eq!(project.run(&["include"]).unwrap(), "> 10\n");
+	// `errors` loads without a search path
+	let errors = project.errors();
+	assert_eq!(errors, [format!("Error @ {}:1, at `util`: Module not found", project.path("main.pl0"))]);
+}
+
+#[test]
+fn each_module_has_its_own_namespace() {
+	let project = Project::new("modules-namespace", &[
+		("a.pl0", "export x, show; var x; procedure show; ! x; x := 1."),
+		("b.pl0", "export x, show; var x; procedure show; ! x; x := 2."),
+		("main.pl0", "import a; import b; var x; begin x := 3; call a.show; call b.show; ! x end."),
+	]);
+	assert_eq!(project.run(&[]).unwrap(), "> 1\n> 2\n> 3\n");
+}
+
+#[test]
+fn only_exported_names_are_reachable() {
+	let project = Project::new("modules-exports", &[
+		("util.pl0", "export a; var a, hidden; a := 1."),
+		("main.pl0", "import util;\n! util.hidden."),
+	]);
+	assert_eq!(project.errors(), [format!("Error @ {}:2, at `hidden`: Not exported by module `util`", project.path("main.pl0"))]);
+}
+
+#[test]
+fn errors_name_the_file_they_are_in() {
+	let project = Project::new("modules-errors", &[
+		("util.pl0", "export a; var a;\na := ."),
+		("main.pl0", "import util; ! util.a."),
+	]);
+	assert_eq!(project.errors(), [format!("Error @ {}:2, at `.`: Expected an expression", project.path("util.pl0"))]);
+}
+
+#[test]
+fn bad_imports_are_compile_errors() {
+	let project = Project::new("modules-cycle", &[
+		("a.pl0", "import b; ."),
+		("b.pl0", "import a; ."),
+		("main.pl0", "import a; ."),
+	]);
+	// the cycle is spelled out with each file's canonical path
+	let dir = project.dir.canonicalize().unwrap();
+	let [a, b] = ["a.pl0", "b.pl0"].map(|f| dir.join(f).display().to_string());
+	assert_eq!(project.errors(), [format!("Error @ {}:1, at `a`: Import cycle: {a} -> {b} -> {a}", project.path("b.pl0"))]);
+
+	let project = Project::new("modules-twice", &[
+		("util.pl0", UTIL),
+		("main.pl0", "import util;\nimport \"util.pl0\";\n."),
+	]);
+	assert_eq!(project.errors(), [format!("Error @ {}:2, at `\"util.pl0\"`: Module `util` imported twice", project.path("main.pl0"))]);
+
+	let project = Project::new("modules-std", &[
+		("std.pl0", UTIL),
+		("main.pl0", "import \"std.pl0\";\n."),
+	]);
+	assert_eq!(project.errors(), [format!("Error @ {}:1, at `\"std.pl0\"`: `std` is reserved for the standard library", project.path("main.pl0"))]);
+}
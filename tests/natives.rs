@@ -0,0 +1,55 @@
+use std::{cell::RefCell, rc::Rc};
+
+use plz::{sink::Buffer, Diagnostics, NativeProcedure, Options};
+
+// What a program prints with the given natives registered, or how it failed
+fn with_natives(source: &str, natives: Vec<NativeProcedure>) -> Result<String, Diagnostics> {
+	let buffer = Buffer::new();
+	plz::run_source(source, Options { natives, output: Box::new(buffer.clone()), ..Default::default() }).map(|_| buffer.contents())
+}
+
+#[test]
+fn natives_are_called_as_statements() {
+	let calls = Rc::new(RefCell::new(Vec::new()));
+	let seen = calls.clone();
+	let log = NativeProcedure::new("log", 2, move |args| {
+		seen.borrow_mut().push(args.to_vec());
+		Ok(None)
+	});
+	let out = with_natives("var x; begin x := 4; call log(x, x * 2); call log(1, 2) end.", vec![log]).unwrap();
+	assert_eq!(out, "");
+	assert_eq!(*calls.borrow(), [vec![4, 8], vec![1, 2]]);
+}
+
+#[test]
+fn natives_returning_values_are_used_in_expressions() {
+	let counter = Rc::new(RefCell::new(0));
+	let next = NativeProcedure::new("next", 0, move |_| {
+		*counter.borrow_mut() += 1;
+		Ok(Some(*counter.borrow()))
+	});
+	let add = NativeProcedure::new("add", 2, |args| Ok(Some(args[0] + args[1])));
+	let out = with_natives("var x; begin x := next() + next(); ! x, \" \", add(x, next()) end.", vec![next, add]).unwrap();
+	assert_eq!(out, "> 3 6\n");
+}
+
+#[test]
+fn calls_with_the_wrong_number_of_arguments_are_compile_errors() {
+	let add = NativeProcedure::new("add", 2, |args| Ok(Some(args[0] + args[1])));
+	let Err(Diagnostics::Compile(errors)) = with_natives("var x;\nbegin ! 1;\nx := add(1) end.", vec![add.clone()]) else { panic!("expected compile errors") };
+	assert_eq!(errors[0].to_string(), "Error @ <input>:3, at `add`: `add` expects 2 argument(s), got 1");
+	let Err(Diagnostics::Compile(errors)) = with_natives("var x;\ncall add(1, 2, 3).", vec![add]) else { panic!("expected compile errors") };
+	assert_eq!(errors[0].to_string(), "Error @ <input>:2, at `add`: `add` expects 2 argument(s), got 3");
+}
+
+#[test]
+fn errors_from_natives_stop_the_program_where_they_were_called() {
+	let check = NativeProcedure::new("check", 1, |args| if args[0] < 0 { Err(format!("{} is negative", args[0])) } else { Ok(None) });
+	let Err(Diagnostics::Runtime(e)) = with_natives("var x;\nbegin x := -2; ! 1;\ncall check(x); ! 2 end.", vec![check]) else { panic!("expected a runtime error") };
+	assert_eq!(e.to_string(), "Runtime error @ <input>:3: check: -2 is negative");
+
+	// a native without a value can only be called as a statement
+	let noop = NativeProcedure::new("noop", 0, |_| Ok(None));
+	let Err(Diagnostics::Runtime(e)) = with_natives("var x;\nx := noop().", vec![noop]) else { panic!("expected a runtime error") };
+	assert_eq!(e.to_string(), "Runtime error @ <input>:2: `noop` returns no value");
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::run_optimized as run;
+use plz::{Diagnostics, Options};
+
+fn optimized(source: &str) -> String {
+	plz::optimized_source(source, Options::default()).unwrap_or_else(|e| panic!("{e}"))
+}
+
+#[test]
+fn constants_are_folded_and_dead_branches_dropped() {
+	let program = optimized("
+		const k = 2 * 3;
+		var x: integer;
+		begin
+			x := k * (4 + 1) - 2;
+			if 1 == 2 then x := 99;
+			while 0 > 1 do x := x + 1;
+			if k > 5 then ! x * 1 + 0
+		end.");
+	assert_eq!(program, "const k = 6;\nvar x: integer;\nbegin\n\tx := 28;\n\t! x\nend.\n");
+	// the output is a program in its own right, which optimizes no further
+	assert_eq!(optimized(&program), program);
+}
+
+#[test]
+fn inner_declarations_shadow_consts() {
+	let program = optimized("
+		const n = 1;
+		var x;
+		procedure p;
+			var n;
+			begin n := 5; x := n + 1 end;
+		begin x := n + 1; call p end.");
+	assert!(program.contains("x := n + 1"), "{program}");
+	assert!(program.contains("x := 2"), "{program}");
+}
+
+#[test]
+fn runtime_errors_are_left_for_runtime() {
+	let source = "var x, b; begin ! 1; x := 7 / (3 - 3) end.";
+	assert!(optimized(source).contains("x := 7 / 0"));
+	match run(source, true) {
+		Err(Diagnostics::Runtime(e)) => assert_eq!(e.to_string(), "Runtime error @ <input>:1: Division by zero"),
+		other => panic!("expected a runtime error, got {:?}", other.map_err(|e| e.to_string())),
+	}
+
+	// `b + 0` is not just `b` when `b` might hold a boolean
+	let source = "var b, y; begin y := 0; b := true; if y == 1 then b := 1; y := b + 0 end.";
+	assert!(optimized(source).contains("y := b + 0"));
+	assert!(matches!(run(source, true), Err(Diagnostics::Runtime(_))));
+}
+
+#[test]
+fn optimizing_does_not_change_what_a_program_does() {
+	let source = "
+		const limit = 10 * 10;
+		var i: integer, total: integer;
+		begin
+			i := 0; total := 0;
+			while i < limit / 10 do begin
+				case i % 3 of 0: total := total + i * 1; 1: total := total - 0 else total := total + 2 ** 3 end;
+				i := i + 1
+			end;
+			if odd 3 then writeln \"total \", total
+		end.";
+	assert_eq!(run(source, true).unwrap(), run(source, false).unwrap());
+}
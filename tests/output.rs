@@ -0,0 +1,21 @@
+use std::{rc::Rc, cell::RefCell};
+
+use plz::{sink::Buffer, Diagnostics, Options};
+
+#[test]
+fn output_is_captured_in_a_buffer() {
+	let buffer = Buffer::new();
+	let options = Options { output: Box::new(buffer.clone()), ..Default::default() };
+	plz::run_source("var x; begin x := 6 * 7; ! x; ? x; write \"x = \", x; writeln end.", options).unwrap();
+	assert_eq!(buffer.contents(), "> 42\n> x: 42\nx = 42\n");
+}
+
+#[test]
+fn output_reaches_a_callback_before_a_runtime_error() {
+	let chunks = Rc::new(RefCell::new(Vec::new()));
+	let sink = chunks.clone();
+	let options = Options { output: Box::new(move |s: &str| sink.borrow_mut().push(s.to_string())), ..Default::default() };
+	let result = plz::run_source("begin ! 1; ! 1 / 0 end.", options);
+	assert!(matches!(result, Err(Diagnostics::Runtime(_))));
+	assert_eq!(*chunks.borrow(), vec!["> 1\n".to_string()]);
+}
@@ -0,0 +1,34 @@
+use plz::{error::take_diagnostics, Ast, Parser, Scanner, Stmt, StmtId};
+
+// A program's statements as parsed, and the errors parsing it reported
+fn parse(source: &str) -> (Ast, Vec<StmtId>, Vec<String>) {
+	let tokens = Scanner::new(source.to_string(), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	(parser.into_ast(), program, take_diagnostics().iter().map(|e| e.to_string()).collect())
+}
+
+#[test]
+fn the_main_statement_may_be_empty() {
+	let (ast, program, errors) = parse("var x; .");
+	assert!(errors.is_empty(), "{errors:?}");
+	assert!(matches!(&ast[program[1]], Stmt::Scope(body) if body.is_empty()));
+	let (ast, program, errors) = parse("var x; begin x := 1; ; ! x; end.");
+	assert!(errors.is_empty(), "{errors:?}");
+	let Stmt::Scope(body) = &ast[program[1]] else { panic!("expected a block") };
+	assert!(matches!(&ast[body[0]], Stmt::Assign(..)));
+	assert!(body.iter().any(|s| matches!(&ast[*s], Stmt::Print(_))));
+}
+
+#[test]
+fn a_stray_token_after_the_main_statement_is_an_error() {
+	// these used to hang the parser, the empty statement consuming nothing where `.` was expected
+	let cases = [
+		("var x; x := 1; .", "Error @ <input>:1, at `;`: Expected `.` at the end of the program"),
+		("var x; x := 1; ; .", "Error @ <input>:1, at `;`: Expected `.` at the end of the program"),
+		("var x;\nbegin x := 1 end; x := 2.", "Error @ <input>:2, at `;`: Expected `.` at the end of the program"),
+	];
+	for (source, expected) in cases {
+		assert_eq!(parse(source).2, [expected], "{source}");
+	}
+}
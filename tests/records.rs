@@ -0,0 +1,79 @@
+use plz::{sink::Buffer, Diagnostics, Expr, Options, Parser, Scanner, Stmt};
+
+// The statements of a program, as parsed
+fn parse(source: &str) -> (plz::Ast, Vec<plz::StmtId>) {
+	let tokens = Scanner::new(source.to_string(), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	(parser.into_ast(), program)
+}
+// What a program prints, or how it failed
+fn run(source: &str) -> Result<String, Diagnostics> {
+	let buffer = Buffer::new();
+	plz::run_source(source, Options { output: Box::new(buffer.clone()), ..Default::default() }).map(|_| buffer.contents())
+}
+fn compile_errors(source: &str) -> Vec<String> {
+	match run(source) {
+		Err(Diagnostics::Compile(errors)) => errors.iter().map(|e| e.to_string()).collect(),
+		other => panic!("expected compile errors, got {other:?}"),
+	}
+}
+
+#[test]
+fn records_are_declared_and_their_fields_accessed() {
+	let (ast, program) = parse("type point = record x, y end, pair = record l, r end;\nvar p: point;\nbegin p.x := 3; ! p.y end.");
+	let Stmt::Type(types) = &ast[program[0]] else { panic!("expected a type declaration") };
+	let types: Vec<(String, Vec<String>)> = types.iter()
+		.map(|(name, fields)| (name.lexeme.to_string(), fields.iter().map(|f| f.lexeme.to_string()).collect()))
+		.collect();
+	assert_eq!(types, [("point".to_string(), vec!["x".to_string(), "y".to_string()]), ("pair".to_string(), vec!["l".to_string(), "r".to_string()])]);
+	let Stmt::Var(vars) = &ast[program[1]] else { panic!("expected a var declaration") };
+	assert_eq!(vars[0].1.as_ref().map(|t| t.lexeme.to_string()), Some("point".to_string()));
+
+	// a dot between names is a field access, not the end of the program
+	let Stmt::Scope(body) = &ast[program[2]] else { panic!("expected a block") };
+	let Stmt::Set(record, field, _) = &ast[body[0]] else { panic!("expected a field assignment") };
+	assert_eq!((record.lexeme.to_string(), field.lexeme.to_string()), ("p".to_string(), "x".to_string()));
+	let Stmt::Print(e) = &ast[body[1]] else { panic!("expected a print") };
+	assert!(matches!(&ast[*e], Expr::Get(..)));
+	assert_eq!(ast.show(*e), "`p.y`");
+}
+
+#[test]
+fn fields_are_read_and_assigned() {
+	let out = run("
+		type point = record x, y end, pair = record l, r end;
+		var p: point, q: point, s: pair;
+		begin
+			p.x := 3; p.y := 4;
+			q.x := p.x * 2;
+			s.l := p.x + p.y; s.r := 0;
+			! p.x * p.x + p.y * p.y;
+			? p; ? q; ? s
+		end.");
+	assert_eq!(out.unwrap(), "> 25\n> p: {x: 3, y: 4}\n> q: {x: 6, y: unassigned}\n> s: {l: 7, r: 0}\n");
+	assert_eq!(run("type t = record a end; var r: t; r.a := 2.").unwrap(), "");
+}
+
+#[test]
+fn misused_records_are_compile_errors() {
+	let cases = [
+		("type t = record a end;\nvar r: t;\nr.b := 1.", "Error @ <input>:3, at `b`: Record type `t` has no such field"),
+		("type t = record a end;\nvar r: t, x;\nx := r.", "Error @ <input>:3, at `r`: Record used as a value"),
+		("type t = record a end;\nvar r: t;\nr := 1.", "Error @ <input>:3, at `r`: Cannot assign to a whole record"),
+		("var x: integer;\nx.a := 1.", "Error @ <input>:2, at `x`: Not a record"),
+		("type t = record a, a end;\n.", "Error @ <input>:1, at `a`: Duplicate field name"),
+	];
+	for (source, expected) in cases {
+		assert_eq!(compile_errors(source), [expected], "{source}");
+	}
+}
+
+#[test]
+fn unassigned_fields_and_untyped_variables_fail_at_runtime() {
+	let Err(Diagnostics::Runtime(e)) = run("type t = record a, b end; var r: t;\nbegin r.a := 1; ! r.b end.") else { panic!("expected a runtime error") };
+	assert_eq!(e.to_string(), "Runtime error @ <input>:2: Use of unitialised field: r.b");
+	// an untyped variable is only found not to be a record when used as one
+	let Err(Diagnostics::Runtime(e)) = run("var x;\nx.a := 1.") else { panic!("expected a runtime error") };
+	assert_eq!(e.to_string(), "Runtime error @ <input>:2: Not a record: x");
+}
@@ -0,0 +1,47 @@
+use plz::{token::{Literal, TokenType}, Ast, Builtin, Expr, ExprId, Parser, Scanner, Stmt, Symbol};
+
+#[test]
+fn only_names_keywords_and_operators_are_interned() {
+	let tokens = Scanner::new("x := 007 + y; write \"hi\\n\"".to_string(), "<input>".into()).scan_tokens();
+	let interned: Vec<Symbol> = tokens.iter().filter(|t| t.raw.is_none()).map(|t| t.lexeme).collect();
+	assert_eq!(interned, ["x", ":=", "+", "y", ";", "write", ""].map(Symbol::intern));
+	// numbers and strings keep their text to themselves, as written
+	let literals: Vec<(TokenType, &str)> = tokens.iter().filter(|t| t.raw.is_some()).map(|t| (t.kind, t.raw.as_deref().unwrap())).collect();
+	assert_eq!(literals, [(TokenType::NUMBER, "007"), (TokenType::STRING, "\"hi\\n\"")]);
+	assert!(tokens.iter().filter(|t| t.raw.is_some()).all(|t| t.lexeme == Symbol::EMPTY));
+	assert_eq!(tokens[2].literal, Some(Literal::Number(7)));
+}
+
+#[test]
+fn each_thread_interns_on_its_own() {
+	let here = Symbol::intern("only here");
+	// a thread running another program starts from a table of its own, which it adds to freely
+	let there = std::thread::spawn(|| {
+		let symbol = Symbol::intern("only there");
+		(symbol == Symbol::intern("only there"), symbol.to_string())
+	}).join().unwrap();
+	assert_eq!(there, (true, "only there".to_string()));
+	assert_eq!(here.as_str(), Symbol::intern("only here").as_str());
+	assert_eq!(here, "only here");
+}
+
+// The calls in an expression, left to right, with the built-in each was found to name
+fn calls(ast: &Ast, e: ExprId, out: &mut Vec<(String, Option<Builtin>)>) {
+	match &ast[e] {
+		Expr::Binary(a, _, b) => { calls(ast, *a, out); calls(ast, *b, out); },
+		Expr::Call(name, builtin, _) => out.push((name.lexeme.to_string(), *builtin)),
+		_ => {},
+	}
+}
+
+#[test]
+fn built_in_calls_are_told_apart_when_parsed() {
+	let tokens = Scanner::new("var x; ! abs(x) + gcd(x, 4) + host(x).".to_string(), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	let ast = parser.into_ast();
+	let Stmt::Print(e) = ast[program[1]] else { panic!("expected a print") };
+	let mut found = Vec::new();
+	calls(&ast, e, &mut found);
+	assert_eq!(found, [("abs".to_string(), Some(Builtin::Abs)), ("gcd".to_string(), Some(Builtin::Gcd)), ("host".to_string(), None)]);
+}
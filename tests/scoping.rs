@@ -0,0 +1,117 @@
+use plz::{error::take_diagnostics, sink::Buffer, Diagnostics, NativeProcedure, Options, Parser, Resolver, Scanner, Symbol};
+
+// What a program prints with the optimizer on or off, or how it failed
+fn run_optimized(source: &str, optimize: bool) -> Result<String, Diagnostics> {
+	let buffer = Buffer::new();
+	plz::run_source(source, Options { output: Box::new(buffer.clone()), optimize, ..Default::default() }).map(|_| buffer.contents())
+}
+fn run(source: &str) -> String {
+	run_optimized(source, true).unwrap_or_else(|e| panic!("{e}"))
+}
+// Runs a program with the optimizer on and off, which must agree
+fn both(source: &str) -> Result<String, String> {
+	let result = |optimize| run_optimized(source, optimize).map_err(|e| e.to_string());
+	let optimized = result(true);
+	assert_eq!(optimized, result(false), "{source}");
+	optimized
+}
+// The errors the resolver reports for a program that parses
+// knowing of the host's `names` and of `natives`
+fn resolve(source: &str, names: &[&str], natives: &[NativeProcedure]) -> Vec<String> {
+	let tokens = Scanner::new(source.to_string(), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	let ast = parser.into_ast();
+	assert!(take_diagnostics().is_empty(), "{source} does not parse");
+	Resolver::new(&ast).with_host_names(names.iter().map(|n| Symbol::intern(n))).with_natives(natives).resolve(&program, true);
+	take_diagnostics().iter().map(|e| e.to_string()).collect()
+}
+
+#[test]
+fn procedures_see_where_they_were_declared_not_where_they_were_called() {
+	let out = run("
+		var x;
+		procedure show; ! x;
+		procedure shadow;
+			var x;
+			begin x := 2; call show; ! x end;
+		begin x := 1; call shadow end.");
+	assert_eq!(out, "> 1\n> 2\n");
+}
+
+#[test]
+fn a_name_only_the_caller_declares_is_undeclared() {
+	// with dynamic scoping, `show` used to print 1 here
+	let cases = [
+		("procedure show; ! y;\nprocedure p; var y; begin y := 1; call show end;\ncall p.", "Error @ <input>:1, at `y`: Undeclared variable"),
+		("procedure set; y := 2;\nprocedure p; var y; begin call set; ! y end;\ncall p.", "Error @ <input>:1, at `y`: Undeclared variable"),
+		// procedures too
+		("procedure p; call q;\nprocedure r; procedure q; ! 1; call p;\ncall r.", "Error @ <input>:1, at `q`: Undeclared procedure"),
+	];
+	for (source, expected) in cases {
+		assert_eq!(resolve(source, &[], &[]), [expected], "{source}");
+		// reported before anything runs, even where the optimizer would drop the code
+		assert_eq!(both(source), Err(expected.to_string()), "{source}");
+	}
+	assert_eq!(both("var x;\nprocedure unused; ! y;\nx := 1."), Err("Error @ <input>:2, at `y`: Undeclared variable".to_string()));
+}
+
+#[test]
+fn procedures_may_call_those_declared_after_them() {
+	let out = both("
+		var n;
+		procedure ping; if n > 0 then begin n := n - 1; ! 0; call pong end;
+		procedure pong; if n > 0 then begin n := n - 1; ! 1; call ping end;
+		begin n := 3; call ping end.");
+	assert_eq!(out.unwrap(), "> 0\n> 1\n> 0\n");
+}
+
+#[test]
+fn host_names_and_natives_are_declared() {
+	let natives = [NativeProcedure::new("log", 1, |_| Ok(None))];
+	let errors = resolve("begin g := c; call log(g); ! abs(g) end.", &["g", "c"], &natives);
+	assert!(errors.is_empty(), "{errors:?}");
+	// but only to the program, not to the modules it imports
+	let errors = resolve("begin g := c; call log(g) end.", &[], &[]);
+	assert_eq!(errors, ["Error @ <input>:1, at `c`: Undeclared variable", "Error @ <input>:1, at `g`: Undeclared variable", "Error @ <input>:1, at `log`: Undeclared procedure", "Error @ <input>:1, at `g`: Undeclared variable"]);
+	let out = plz::run_source("g := g + c.", Options { output: Box::new(Buffer::new()), globals: vec![("g".into(), 1)], consts: vec![("c".into(), 2)], ..Default::default() });
+	assert_eq!(out.unwrap().global("g"), Some(3));
+}
+
+#[test]
+fn every_call_gets_its_own_locals() {
+	// each activation of `count` keeps its own `n`, and `inner` reaches the one that declared it
+	let out = both("
+		var depth;
+		procedure count;
+			var n;
+			procedure inner; ! n;
+			begin
+				n := depth;
+				if depth < 3 then begin depth := depth + 1; call count end;
+				call inner
+			end;
+		begin depth := 1; call count end.");
+	assert_eq!(out.unwrap(), "> 3\n> 2\n> 1\n");
+}
+
+#[test]
+fn nested_procedures_reach_outer_locals() {
+	let out = run("
+		type point = record x, y end;
+		var total;
+		procedure outer;
+			var i, p: point;
+			procedure inner;
+			begin
+				total := total + i;
+				p.x := p.x + 1
+			end;
+			begin
+				i := 0; p.x := 0;
+				while i < 5 do begin i := i + 1; call inner end;
+				? p
+			end;
+		begin total := 0; call outer; ? total end.");
+	assert_eq!(out, "> p: {x: 5, y: unassigned}\n> total: 15\n");
+}
@@ -0,0 +1,64 @@
+use plz::{sink::Buffer, Diagnostics, Options};
+
+// What a program prints given `input`, or how it failed
+fn run_with(source: &str, input: &'static str) -> Result<String, Diagnostics> {
+	let buffer = Buffer::new();
+	let options = Options { output: Box::new(buffer.clone()), input: Box::new(input.as_bytes()), ..Default::default() };
+	plz::run_source(source, options).map(|_| buffer.contents())
+}
+fn run(source: &str) -> String {
+	run_with(source, "").unwrap_or_else(|e| panic!("{e}"))
+}
+
+#[test]
+fn math_helpers() {
+	let out = run("
+		import std;
+		begin
+			std.a := 5; call std.factorial; ! std.result;
+			std.a := 10; call std.fib; ! std.result;
+			std.a := 4; std.b := 6; call std.lcm; ! std.result;
+			std.a := 97; call std.is_prime; ! std.result;
+			std.a := 91; call std.is_prime; ! std.result;
+			std.a := -1204; call std.digits; ! std.result;
+			call std.reverse; ! std.result;
+			! std.maxint, \" \", std.minint
+		end.");
+	assert_eq!(out, "> 120\n> 55\n> 12\n> true\n> false\n> 4\n> -4021\n> 2147483647 -2147483648\n");
+}
+
+#[test]
+fn io_helpers_read_from_the_hosts_input() {
+	let out = run_with("
+		import std;
+		begin
+			write 1; call std.space; write 2; call std.newline;
+			std.a := 1; std.b := 10; call std.read_between; call std.newline;
+			! std.result;
+			! std.read() * 2
+		end.", "50\n7\n 21 \r\n");
+	assert_eq!(out.unwrap(), "1 2\n\
+		Enter a number between 1 and 10: Enter a number between 1 and 10: \n\
+		> 7\n> 42\n");
+}
+
+#[test]
+fn reading_past_the_input_or_a_non_number_fails() {
+	let cases = [
+		("import std;\nbegin ! std.read();\n! std.read() end.", "1\n", "Runtime error @ <input>:3: std.read: Unexpected end of input"),
+		("import std;\n! std.read().", "seven\n", "Runtime error @ <input>:2: std.read: Not a number: seven"),
+	];
+	for (source, input, expected) in cases {
+		let Err(Diagnostics::Runtime(e)) = run_with(source, input) else { panic!("expected a runtime error: {source}") };
+		assert_eq!(e.to_string(), expected, "{source}");
+	}
+}
+
+#[test]
+fn constants_in_const_expressions() {
+	let out = run("
+		import std;
+		const half = std.maxint / 2 + 1;
+		! half.");
+	assert_eq!(out, "> 1073741824\n");
+}
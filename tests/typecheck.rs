@@ -0,0 +1,105 @@
+use plz::{error::take_diagnostics, sink::Buffer, Diagnostics, NativeProcedure, Options, Parser, Scanner, Span, TypeChecker};
+
+// The errors the checker finds in a program that parses, knowing of `natives`
+fn check_with(source: &str, natives: &[NativeProcedure]) -> Vec<String> {
+	let tokens = Scanner::new(source.to_string(), "<input>".into()).scan_tokens();
+	let mut parser = Parser::new(tokens);
+	let program = parser.parse();
+	let ast = parser.into_ast();
+	assert!(take_diagnostics().is_empty(), "{source} does not parse");
+	TypeChecker::new(&ast).with_natives(natives).check(&program);
+	take_diagnostics().iter().map(|e| e.to_string()).collect()
+}
+fn check(source: &str) -> Vec<String> { check_with(source, &[]) }
+// What a program prints, or how it failed
+fn run(source: &str) -> Result<String, Diagnostics> {
+	let buffer = Buffer::new();
+	plz::run_source(source, Options { output: Box::new(buffer.clone()), ..Default::default() }).map(|_| buffer.contents())
+}
+
+#[test]
+fn annotated_and_untyped_variables_mix() {
+	let out = run("
+		var n: integer, done: boolean, u;
+		begin
+			n := 0; done := false;
+			while done == false do begin n := n + 1; done := n > 3 end;
+			if done then ! n;
+			u := done; if u then ! 1;
+			u := 5; ! u
+		end.");
+	assert_eq!(out.unwrap(), "> 4\n> 1\n> 5\n");
+}
+
+#[test]
+fn mismatches_are_reported_before_the_program_runs() {
+	let cases = [
+		("var x: integer;\nif x then ! 1.", "Error @ <input>:2, at `x`: Expected boolean, found integer"),
+		("var b: boolean;\nb := 1.", "Error @ <input>:2, at `b`: Cannot assign integer to a variable of type boolean"),
+		("var b: boolean;\n! b + 1.", "Error @ <input>:2, at `b`: Expected integer, found boolean"),
+		("var n: integer, b: boolean;\nif n == b then ! 1.", "Error @ <input>:2, at `==`: Cannot compare integer with boolean"),
+		("var x:\nreal;\n.", "Error @ <input>:2, at `real`: Unknown type"),
+		("const c = 1;\nc := 2.", "Error @ <input>:2, at `c`: Cannot assign to a const"),
+		("procedure p; begin end;\np := 1.", "Error @ <input>:2, at `p`: Cannot assign to a non-variable"),
+		("procedure p; begin end;\n! p.", "Error @ <input>:2, at `p`: Not a value"),
+		("var x;\ncall x.", "Error @ <input>:2, at `x`: Not a procedure"),
+		("procedure p; begin end;\ncall p(1).", "Error @ <input>:2, at `p`: Procedures take no arguments"),
+		("const c = 1,\nc = 2;\n.", "Error @ <input>:2, at `c`: Duplicate const name"),
+		("type t = record a end,\nt = record b end;\n.", "Error @ <input>:2, at `t`: Duplicate type name"),
+		("var x, y: boolean,\nx: integer;\n.", "Error @ <input>:2, at `x`: Duplicate variable name"),
+	];
+	for (source, expected) in cases {
+		assert_eq!(check(source), [expected], "{source}");
+	}
+	// each kind of name has a namespace of its own, and a procedure's block is a new scope
+	assert_eq!(check("const x = 1; var x; procedure p; var x; x := 2; begin x := 5; call p; ! x end."), Vec::<String>::new());
+	// nothing runs, not even what comes before the mismatch
+	let Err(Diagnostics::Compile(errors)) = run("var b: boolean;\nbegin ! 1;\nb := 2 end.") else { panic!("expected compile errors") };
+	assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn untyped_variables_have_the_type_of_what_is_assigned_to_them() {
+	let cases = [
+		("var x;\nbegin x := 1;\nif x then ! 1 end.", "Error @ <input>:3, at `x`: Expected boolean, found integer"),
+		// however far after the read the assignment comes
+		("var x;\nprocedure p; if x then ! 1;\nbegin x := 1; call p end.", "Error @ <input>:2, at `x`: Expected boolean, found integer"),
+		// and through other untyped variables
+		("var a, b;\nbegin b := a; a := 1 < 2;\n! b + 1 end.", "Error @ <input>:3, at `b`: Expected integer, found boolean"),
+	];
+	for (source, expected) in cases {
+		assert_eq!(check(source), [expected], "{source}");
+	}
+	// a variable assigned both is left to be checked when run
+	let source = "var x, b;\nbegin b := true; x := 1; x := b;\nif x == true then x := 2;\nif x then ! 1 end.";
+	assert!(check(source).is_empty());
+	let Err(Diagnostics::Runtime(e)) = run(source) else { panic!("expected a runtime error") };
+	assert_eq!(e.to_string(), "Runtime error @ <input>:4: not a boolean");
+}
+
+#[test]
+fn calls_are_checked_against_the_arguments_taken() {
+	let natives = [NativeProcedure::new("add", 2, |args| Ok(Some(args[0] + args[1])))];
+	let cases = [
+		("var x;\nx := add(1).", "Error @ <input>:2, at `add`: `add` expects 2 argument(s), got 1"),
+		("call add(1, 2, 3).", "Error @ <input>:1, at `add`: `add` expects 2 argument(s), got 3"),
+		("! max(1) + abs(2, 3).", "Error @ <input>:1, at `max`: `max` expects 2 argument(s), got 1"),
+	];
+	for (source, expected) in cases {
+		assert_eq!(check_with(source, &natives)[0], expected, "{source}");
+	}
+	assert_eq!(check_with("! max(1) + abs(2, 3).", &[]).len(), 2);
+}
+
+#[test]
+fn errors_point_at_the_offending_token() {
+	let source = "var x: integer;\nbegin x := 1;\n\tif x then ! 1 end.";
+	let Err(Diagnostics::Compile(errors)) = run(source) else { panic!("expected compile errors") };
+	assert_eq!(errors[0].span, Some(Span { column: 5, width: 1 }));
+	assert_eq!(errors[0].excerpt(source).unwrap(), "\tif x then ! 1 end.\n\t   ^");
+
+	// columns count characters, not bytes
+	let source = "var b: boolean;\nwrite \"zażółć\", b + 1.";
+	let Err(Diagnostics::Compile(errors)) = run(source) else { panic!("expected compile errors") };
+	assert_eq!(errors[0].span, Some(Span { column: 17, width: 1 }));
+}
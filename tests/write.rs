@@ -0,0 +1,55 @@
+use plz::{error::take_diagnostics, sink::Buffer, token::{Literal, TokenType}, Diagnostics, Options, Scanner};
+
+// The values of the string literals in `source`, and the errors scanning it reported
+fn strings(source: &str) -> (Vec<String>, Vec<String>) {
+	let tokens = Scanner::new(source.to_string(), "<input>".into()).scan_tokens();
+	let values = tokens.iter().filter(|t| t.kind == TokenType::STRING).map(|t| match &t.literal {
+		Some(Literal::String(s)) => s.clone(),
+		other => panic!("a string without its value: {other:?}"),
+	}).collect();
+	(values, take_diagnostics().iter().map(|e| e.to_string()).collect())
+}
+// What a program writes
+fn written(source: &str) -> String {
+	let buffer = Buffer::new();
+	plz::run_source(source, Options { output: Box::new(buffer.clone()), ..Default::default() }).unwrap_or_else(|e| panic!("{e}"));
+	buffer.contents()
+}
+
+#[test]
+fn string_literals_are_scanned_with_their_escapes() {
+	let (values, errors) = strings("write \"tab\\there \\\"quoted\\\" back\\\\slash\\n\", \"zażółć\", \"\".");
+	assert_eq!(values, ["tab\there \"quoted\" back\\slash\n", "zażółć", ""]);
+	assert!(errors.is_empty(), "{errors:?}");
+	// a string may run over several lines
+	assert_eq!(strings("write \"one\ntwo\".").0, ["one\ntwo"]);
+}
+
+#[test]
+fn malformed_strings_are_reported_where_they_start() {
+	let (_, errors) = strings("write \"bad \\q escape\".");
+	assert_eq!(errors, ["Error @ <input>:1: Unknown escape sequence: \\q"]);
+	// not at the end of the file, however far that is
+	let (values, errors) = strings("var x;\nwriteln \"oops.\n\nx := 1.");
+	assert!(values.is_empty());
+	assert_eq!(errors, ["Error @ <input>:2: Unterminated string literal"]);
+}
+
+#[test]
+fn write_and_writeln_mix_strings_and_expressions() {
+	let out = written("var x; begin x := 6; write \"x = \", x, \", twice: \", x * 2; writeln; writeln \"done\" end.");
+	assert_eq!(out, "x = 6, twice: 12\ndone\n");
+	assert_eq!(written("writeln \"zażółć\\tgęślą\"."), "zażółć\tgęślą\n");
+}
+
+#[test]
+fn bang_prints_text_with_the_prefix() {
+	let out = written("var x; begin x := 3; ! \"x is \", x; ! \"alone\"; ! x end.");
+	assert_eq!(out, "> x is 3\n> alone\n> 3\n");
+}
+
+#[test]
+fn strings_are_only_for_printing() {
+	let Err(Diagnostics::Compile(errors)) = plz::run_source("var x; x := \"text\".", Options::default()) else { panic!("expected compile errors") };
+	assert!(errors[0].to_string().ends_with("Expected an expression"), "{errors:?}");
+}